@@ -42,6 +42,15 @@ use std::time::Duration;
 
 /// Auto-start daemon if not running and send command
 pub fn run_client(command: common::Command) -> Result<(), Box<dyn std::error::Error>> {
+    // The daemon would read a cut-off payload as something else entirely
+    if command.is_truncated() {
+        return Err(format!(
+            "Request too large: the daemon takes up to {} bytes per command",
+            common::CHUNK_PAYLOAD_CAPACITY - 1
+        )
+        .into());
+    }
+
     // Use secure IPC directory
     let ipc_dir = get_secure_ipc_dir()?;
     
@@ -61,7 +70,8 @@ pub fn run_client(command: common::Command) -> Result<(), Box<dyn std::error::Er
         return Err("Daemon not running".into());
     }
 
-    match request_server(&node, &service_name, command, Duration::from_secs(5)) {
+    let timeout = command.response_timeout();
+    match request_server(&node, &service_name, command, timeout) {
         Ok(response) => {
             println!("{}", response);
         }
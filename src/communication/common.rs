@@ -3,6 +3,12 @@
 //! Defines commands and message chunks for daemon communication.
 
 use iceoryx2::prelude::ZeroCopySend;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::import::ImportSource;
 
 pub const MAX_PAYLOAD_SIZE: usize = 4096;
 pub const CHUNK_METADATA_SIZE: usize = std::mem::size_of::<u128>()
@@ -24,12 +30,21 @@ pub enum Command {
     Delete([u8; CHUNK_PAYLOAD_CAPACITY]),
     Logs([u8; CHUNK_PAYLOAD_CAPACITY]),
     Restart([u8; CHUNK_PAYLOAD_CAPACITY]),
+    Reload([u8; CHUNK_PAYLOAD_CAPACITY]),
     Flush([u8; CHUNK_PAYLOAD_CAPACITY]),
+    Reset([u8; CHUNK_PAYLOAD_CAPACITY]),
     Save,
     Resurrect,
+    ConfigShow([u8; CHUNK_PAYLOAD_CAPACITY]),
+    ReloadConfig([u8; CHUNK_PAYLOAD_CAPACITY]),
+    Scale([u8; CHUNK_PAYLOAD_CAPACITY]),
+    Export([u8; CHUNK_PAYLOAD_CAPACITY]),
+    Import([u8; CHUNK_PAYLOAD_CAPACITY]),
 }
 
 impl Command {
+    /// Copy `input` into a payload, cutting it off if it doesn't fit; see
+    /// `is_truncated`
     pub fn encode_payload(input: &str) -> [u8; CHUNK_PAYLOAD_CAPACITY] {
         let mut buffer = [0u8; CHUNK_PAYLOAD_CAPACITY];
         let bytes = input.as_bytes();
@@ -50,8 +65,8 @@ impl Command {
         Self::Status(Self::encode_payload(input))
     }
 
-    pub fn new_start(input: &str) -> Self {
-        Self::Start(Self::encode_payload(input))
+    pub fn new_start(request: &StartRequest) -> Self {
+        Self::Start(Self::encode_payload(&request.encode()))
     }
 
     pub fn new_stop(input: &str) -> Self {
@@ -78,9 +93,234 @@ impl Command {
         Self::Restart(Self::encode_payload(input))
     }
 
+    pub fn new_reload(request: &ReloadRequest) -> Self {
+        Self::Reload(Self::encode_payload(&request.encode()))
+    }
+
     pub fn new_flush(input: &str) -> Self {
         Self::Flush(Self::encode_payload(input))
     }
+
+    pub fn new_reset(input: &str) -> Self {
+        Self::Reset(Self::encode_payload(input))
+    }
+
+    pub fn new_config_show(input: &str) -> Self {
+        Self::ConfigShow(Self::encode_payload(input))
+    }
+
+    pub fn new_reload_config(request: &ReloadConfigRequest) -> Self {
+        Self::ReloadConfig(Self::encode_payload(&request.encode()))
+    }
+
+    pub fn new_scale(input: &str) -> Self {
+        Self::Scale(Self::encode_payload(input))
+    }
+
+    pub fn new_export(request: &ExportRequest) -> Self {
+        Self::Export(Self::encode_payload(&request.encode()))
+    }
+
+    pub fn new_import(request: &ImportRequest) -> Self {
+        Self::Import(Self::encode_payload(&request.encode()))
+    }
+
+    fn payload(&self) -> Option<&[u8; CHUNK_PAYLOAD_CAPACITY]> {
+        match self {
+            Self::List | Self::Save | Self::Resurrect => None,
+            Self::Status(payload)
+            | Self::Start(payload)
+            | Self::Stop(payload)
+            | Self::Enable(payload)
+            | Self::Disable(payload)
+            | Self::Delete(payload)
+            | Self::Logs(payload)
+            | Self::Restart(payload)
+            | Self::Reload(payload)
+            | Self::Flush(payload)
+            | Self::Reset(payload)
+            | Self::ConfigShow(payload)
+            | Self::ReloadConfig(payload)
+            | Self::Scale(payload)
+            | Self::Export(payload)
+            | Self::Import(payload) => Some(payload),
+        }
+    }
+
+    /// Whether the payload was cut off when it was encoded. It holds up to
+    /// `CHUNK_PAYLOAD_CAPACITY - 1` bytes, as the daemon reads up to the
+    /// first zero byte.
+    pub fn is_truncated(&self) -> bool {
+        self.payload()
+            .is_some_and(|payload| payload[CHUNK_PAYLOAD_CAPACITY - 1] != 0)
+    }
+
+    /// How long the client waits for the daemon's answer. Commands that stop
+    /// or start processes can take a while, especially for whole projects
+    /// whose apps wait on their dependencies.
+    pub fn response_timeout(&self) -> Duration {
+        match self {
+            Self::Start(_)
+            | Self::Stop(_)
+            | Self::Restart(_)
+            | Self::Reload(_)
+            | Self::Delete(_)
+            | Self::Enable(_)
+            | Self::Resurrect
+            | Self::ReloadConfig(_)
+            | Self::Scale(_)
+            | Self::Import(_) => Duration::from_secs(300),
+            _ => Duration::from_secs(5),
+        }
+    }
+}
+
+/// Payload of a `Start` command, sent as JSON. A payload that isn't JSON is
+/// read as a bare target.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StartRequest {
+    /// Config file, config directory, process name or `project:<name>`
+    pub target: String,
+    /// The target as an absolute path, when it is a file or directory under
+    /// the client's working directory. Used when the target isn't a
+    /// process or group the daemon knows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Env profile to apply (`--env <profile>`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    /// Instance counts per app, `web=2,worker=3` (`--formation`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formation: Option<String>,
+    /// App to run without a config file (`bpm start -- <command>`); the
+    /// target is ignored when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<AdHocApp>,
+}
+
+/// An app described on the command line instead of in a config file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AdHocApp {
+    pub name: String,
+    /// Program followed by its arguments
+    pub command: Vec<String>,
+    /// Absolute working directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Restart policy: always, on-failure or never
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<String>,
+}
+
+impl StartRequest {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| self.target.clone())
+    }
+
+    pub fn decode(payload: &str) -> Self {
+        serde_json::from_str(payload).unwrap_or_else(|_| Self {
+            target: payload.to_string(),
+            ..Self::default()
+        })
+    }
+}
+
+/// Payload of a `Reload` command, sent as JSON. A payload that isn't JSON
+/// is read as a bare target.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReloadRequest {
+    /// Process name or ID, app with `instances`, or `project:<name>`
+    pub target: String,
+    /// Instances replaced at a time, instead of each app's `batch_size`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<u32>,
+}
+
+impl ReloadRequest {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| self.target.clone())
+    }
+
+    pub fn decode(payload: &str) -> Self {
+        serde_json::from_str(payload).unwrap_or_else(|_| Self {
+            target: payload.to_string(),
+            ..Self::default()
+        })
+    }
+}
+
+/// Payload of a `ReloadConfig` command, sent as JSON. A payload that isn't
+/// JSON is read as a bare target.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReloadConfigRequest {
+    /// Process name, `project:<name>` or config path; every process when empty
+    pub target: String,
+    /// The target as an absolute path, as in `StartRequest`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl ReloadConfigRequest {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| self.target.clone())
+    }
+
+    pub fn decode(payload: &str) -> Self {
+        serde_json::from_str(payload).unwrap_or_else(|_| Self {
+            target: payload.to_string(),
+            ..Self::default()
+        })
+    }
+}
+
+/// Payload of an `Export` command, sent as JSON
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportRequest {
+    /// Process name or ID
+    pub name: String,
+    /// Absolute path to write the config to; its extension picks the format.
+    /// The config is sent back as JSON when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+}
+
+impl ExportRequest {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| self.name.clone())
+    }
+
+    pub fn decode(payload: &str) -> Self {
+        serde_json::from_str(payload).unwrap_or_else(|_| Self {
+            name: payload.to_string(),
+            ..Self::default()
+        })
+    }
+}
+
+/// Payload of an `Import` command, sent as JSON
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportRequest {
+    pub source: ImportSource,
+    /// Absolute path of the file to import
+    pub path: PathBuf,
+    /// Env profile to apply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+}
+
+impl ImportRequest {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn decode(payload: &str) -> Self {
+        serde_json::from_str(payload).unwrap_or_else(|_| Self {
+            path: PathBuf::from(payload),
+            ..Self::default()
+        })
+    }
 }
 
 /// Chunked message for large responses
@@ -165,6 +405,80 @@ mod tests {
         assert_eq!(decoded, test_str);
     }
 
+    #[test]
+    fn test_oversized_payload_is_truncated() {
+        let fits = "a".repeat(CHUNK_PAYLOAD_CAPACITY - 1);
+        assert!(!Command::new_stop(&fits).is_truncated());
+        assert!(!Command::List.is_truncated());
+
+        let mut env = BTreeMap::new();
+        env.insert("CERT".to_string(), "x".repeat(CHUNK_PAYLOAD_CAPACITY));
+        let command = Command::new_start(&StartRequest {
+            command: Some(AdHocApp {
+                name: "api".to_string(),
+                command: vec!["node".to_string()],
+                env,
+                ..AdHocApp::default()
+            }),
+            ..StartRequest::default()
+        });
+        assert!(command.is_truncated());
+    }
+
+    #[test]
+    fn test_start_request_round_trip() {
+        let request = StartRequest {
+            target: "api".to_string(),
+            path: Some("/srv/api".to_string()),
+            env: Some("production".to_string()),
+            formation: Some("web=2,worker=0".to_string()),
+            command: None,
+        };
+        assert_eq!(StartRequest::decode(&request.encode()), request);
+
+        let request = StartRequest {
+            command: Some(AdHocApp {
+                name: "redis-tunnel".to_string(),
+                command: vec!["ssh".to_string(), "-N".to_string(), "db-host".to_string()],
+                cwd: Some(PathBuf::from("/srv")),
+                env: BTreeMap::from([("K".to_string(), "V".to_string())]),
+                restart: Some("always".to_string()),
+            }),
+            ..StartRequest::default()
+        };
+        assert_eq!(StartRequest::decode(&request.encode()), request);
+
+        let bare = StartRequest::decode("my-app");
+        assert_eq!(bare.target, "my-app");
+        assert_eq!(bare.env, None);
+    }
+
+    #[test]
+    fn test_reload_request_round_trip() {
+        let request = ReloadRequest {
+            target: "web/api".to_string(),
+            batch_size: Some(2),
+        };
+        assert_eq!(ReloadRequest::decode(&request.encode()), request);
+
+        let bare = ReloadRequest::decode("web/api:1");
+        assert_eq!(bare.target, "web/api:1");
+        assert_eq!(bare.batch_size, None);
+    }
+
+    #[test]
+    fn test_reload_config_request_round_trip() {
+        let request = ReloadConfigRequest {
+            target: "api".to_string(),
+            path: Some("/srv/api".to_string()),
+        };
+        assert_eq!(ReloadConfigRequest::decode(&request.encode()), request);
+
+        let bare = ReloadConfigRequest::decode("project:shop");
+        assert_eq!(bare.target, "project:shop");
+        assert_eq!(bare.path, None);
+    }
+
     #[test]
     fn test_command_constructors() {
         let name = "test_app";
@@ -176,9 +490,13 @@ mod tests {
             panic!("Expected Status command");
         }
 
-        let cmd = Command::new_start(name);
+        let cmd = Command::new_start(&StartRequest {
+            target: name.to_string(),
+            ..StartRequest::default()
+        });
         if let Command::Start(payload) = cmd {
-            assert_eq!(Command::decode_payload(&payload).unwrap(), name);
+            let payload = Command::decode_payload(&payload).unwrap();
+            assert_eq!(StartRequest::decode(payload).target, name);
         } else {
             panic!("Expected Start command");
         }
@@ -1,15 +1,25 @@
 use crate::communication::common::ChunkPayload;
-use crate::config::read_config::AppConfig;
+use crate::config::procfile::{self, Formation};
+use crate::config::read_config::{format_duration, App, AppConfig, ConfigFormat};
+use crate::config::{export, import, merge, validate};
+use crate::process_manager::{cgroup, config_diff, dependency, limits, process, stop};
+use crate::process_manager::credentials::{self, Credentials};
 use crate::process_manager::health::{check_health, HealthStatus};
-use crate::process_manager::registry::{ProcessInfo, ProcessRegistry, ProcessState};
+use crate::process_manager::reaper::{self, Reaper};
+use crate::process_manager::registry::{ProcessInfo, ProcessRegistry, ProcessState, RestartReason};
+use crate::process_manager::reload::{self, NotifySocket, Readiness};
 use crate::process_manager::watch::FileWatcher;
 use chrono::Utc;
+use signal_hook::consts::SIGCHLD;
+use signal_hook::iterator::Signals;
 use iceoryx2::active_request::ActiveRequest;
+use iceoryx2::node::NodeWaitFailure;
 use iceoryx2::prelude::*;
 use iceoryx2::service::builder::request_response::RequestResponseOpenError;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
 
 use crate::communication::common;
 
@@ -111,11 +121,23 @@ pub fn run_server() -> Result<(), Box<dyn std::error::Error>> {
         std::process::exit(1);
     }
 
+    // Orphans of apps whose main process dies are re-parented to the daemon,
+    // so the monitor can clean them up
+    if let Err(e) = reaper::become_subreaper() {
+        eprintln!("Warning: {}", e);
+    }
+
     // Load previous state
     let registry = get_registry();
     if let Err(e) = registry.load_state(&get_state_file()) {
         eprintln!("Warning: Could not load previous state: {}", e);
     }
+    // Restarts reuse the registry's env, so put back the masked secrets now
+    for process in registry.list() {
+        if let Err(e) = restore_secret_env(registry, process) {
+            eprintln!("Warning: {}", e);
+        }
+    }
 
     let service_name = ipc_name.as_str().try_into()?;
     let service = node
@@ -128,46 +150,81 @@ pub fn run_server() -> Result<(), Box<dyn std::error::Error>> {
     println!("BPM daemon started");
     println!("Data directory: {}", get_data_dir().display());
 
+    // Reap apps as soon as they exit, so their exit status is recorded
+    let registry_clone = registry.clone();
+    let mut child_exits = Signals::new([SIGCHLD])?;
+    std::thread::spawn(move || {
+        for _ in child_exits.forever() {
+            for name in registry_clone.reap_exited() {
+                if let Some(exit) = registry_clone.get(&name).and_then(|p| p.last_exit) {
+                    println!("Process '{}' exited ({})", name, exit);
+                }
+            }
+        }
+    });
+
     // Spawn background monitoring thread
     let registry_clone = registry.clone();
     std::thread::spawn(move || {
         // Store file watchers for processes with watch enabled
         let mut file_watchers: HashMap<String, FileWatcher> = HashMap::new();
+        let mut reaper = Reaper::new();
 
         loop {
             std::thread::sleep(Duration::from_secs(1)); // Check every second
             registry_clone.refresh_metrics();
 
-            // Reset crash counter for processes that have been running > 5 seconds
-            let running = registry_clone.get_running_processes();
-            for process in &running {
-                if let Some(started) = process.started_at {
-                    let uptime = Utc::now().signed_duration_since(started);
-                    if uptime.num_seconds() >= 5 && process.quick_crash_count > 0 {
-                        let _ = registry_clone.reset_quick_crash_count(&process.name);
-                    }
-                }
+            // Reap exited children and stop what dead apps left behind
+            let app_pids: Vec<u32> = registry_clone.list().iter().filter_map(|p| p.pid).collect();
+            for (pid, signal) in reaper.sweep(&app_pids) {
+                println!("Sent {} to orphaned process {}", signal, pid);
             }
 
-            // Check for dead processes that need restart
+            registry_clone.reset_stable_restarts();
+
+            // Schedule restarts for dead processes
             let dead = registry_clone.check_dead_processes();
             for name in dead {
                 if let Some(process) = registry_clone.get(&name) {
-                    // Check if process died too quickly (< 5 seconds)
-                    let should_stop = registry_clone.check_quick_crash(&name);
-                    
-                    if should_stop {
+                    // Pause restarting processes that keep crashing
+                    if registry_clone.check_crash_loop(&name) {
+                        let crash_loop = &process.crash_loop;
                         println!(
-                            "Process '{}' crashed 3 times within 5 seconds. Giving up.",
-                            name
+                            "Process '{}' crashed {} times within {}, retrying in {}",
+                            name,
+                            crash_loop.max_crashes,
+                            format_duration(crash_loop.window),
+                            format_duration(crash_loop.cooldown)
                         );
-                        let _ = registry_clone.update_state(&name, ProcessState::Stopped);
                         continue;
                     }
-                    
-                    println!("Process '{}' died, attempting restart...", name);
-                    let _ = registry_clone.update_state(&name, ProcessState::Restarting);
+
+                    let died = match &process.last_exit {
+                        Some(exit) => format!("Process '{}' died ({})", name, exit),
+                        None => format!("Process '{}' died", name),
+                    };
+                    match registry_clone.schedule_restart(&name) {
+                        Ok(Some(delay)) => {
+                            println!("{}, restarting in {:.1}s", died, delay.as_secs_f64())
+                        }
+                        Ok(None) => println!(
+                            "{}, giving up after {} restarts in a row",
+                            died, process.restart.max_restarts
+                        ),
+                        Err(e) => eprintln!("Failed to schedule restart of '{}': {}", name, e),
+                    }
+                }
+            }
+
+            // Restart processes whose restart delay is up, once dependencies
+            // that are restarting too are back up
+            for name in registry_clone.due_restarts() {
+                if let Some(process) = registry_clone.get(&name) {
+                    if dependency::dependencies_ready(&registry_clone, &process) == Ok(false) {
+                        continue;
+                    }
                     let new_count = registry_clone.increment_restart_count(&name).unwrap_or(0);
+                    let _ = registry_clone.set_restart_reason(&name, RestartReason::Exited);
 
                     // Actually restart the process
                     match start_process(&registry_clone, &process) {
@@ -180,6 +237,17 @@ pub fn run_server() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
+            // Restart processes over their memory or CPU threshold
+            for (name, reason) in registry_clone.check_thresholds() {
+                if let Some(process) = registry_clone.get(&name) {
+                    println!("Process '{}' {}, restarting...", name, reason);
+                    let new_count = registry_clone.increment_restart_count(&name).unwrap_or(0);
+                    let restarted =
+                        format!("Process '{}' restarted (restart #{})", name, new_count);
+                    restart_in_background(&registry_clone, process, reason, restarted);
+                }
+            }
+
             // Run health checks on running processes
             let running = registry_clone.get_running_processes();
             for process in running {
@@ -221,20 +289,18 @@ pub fn run_server() -> Result<(), Box<dyn std::error::Error>> {
                                 // Restart if too many failures
                                 if failures >= hc_config.retries {
                                     println!("Process '{}' unhealthy, restarting...", process.name);
-                                    let _ = registry_clone
-                                        .update_state(&process.name, ProcessState::Restarting);
                                     let _ = registry_clone.reset_health_failures(&process.name);
                                     if let Some(proc) = registry_clone.get(&process.name) {
-                                        match start_process(&registry_clone, &proc) {
-                                            Ok(_) => println!(
-                                                "Process '{}' restarted due to health check",
-                                                process.name
-                                            ),
-                                            Err(e) => eprintln!(
-                                                "Failed to restart '{}': {}",
-                                                process.name, e
-                                            ),
-                                        }
+                                        let restarted = format!(
+                                            "Process '{}' restarted due to health check",
+                                            process.name
+                                        );
+                                        restart_in_background(
+                                            &registry_clone,
+                                            proc,
+                                            RestartReason::Unhealthy,
+                                            restarted,
+                                        );
                                     }
                                 }
                             }
@@ -266,21 +332,36 @@ pub fn run_server() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
-            // Restart processes with file changes
+            // Restart processes with file changes. One already restarting
+            // picks up the changes when it is spawned again.
             for name in to_restart {
-                if let Some(process) = registry_clone.get(&name) {
+                let process = registry_clone
+                    .get(&name)
+                    .filter(|process| process.state != ProcessState::Stopping);
+                if let Some(process) = process {
                     println!("Restarting '{}' due to file changes...", name);
-                    let _ = registry_clone.update_state(&name, ProcessState::Restarting);
-                    match start_process(&registry_clone, &process) {
-                        Ok(_) => println!("Process '{}' restarted due to file changes", name),
-                        Err(e) => eprintln!("Failed to restart '{}': {}", name, e),
-                    }
+                    let restarted = format!("Process '{}' restarted due to file changes", name);
+                    restart_in_background(
+                        &registry_clone,
+                        process,
+                        RestartReason::FilesChanged,
+                        restarted,
+                    );
                 }
             }
         }
     });
 
-    while node.wait(Duration::from_millis(100)).is_ok() {
+    // Requests whose handler runs on a thread of its own, with the channel
+    // its response comes back on
+    let mut pending = Vec::new();
+    loop {
+        match node.wait(Duration::from_millis(100)) {
+            Ok(()) => {}
+            // SIGCHLD from an exiting app interrupts the wait
+            Err(NodeWaitFailure::Interrupt) => continue,
+            Err(NodeWaitFailure::TerminationRequest) => break,
+        }
         while let Some(request) = server.receive()? {
             let response = match &*request {
                 common::Command::List => {
@@ -292,20 +373,57 @@ pub fn run_server() -> Result<(), Box<dyn std::error::Error>> {
                     handle_status(registry, name)
                 }
                 common::Command::Start(payload) => {
-                    let path = common::Command::decode_payload(payload).unwrap_or("");
-                    handle_start(registry, path)
+                    let payload = common::Command::decode_payload(payload).unwrap_or("");
+                    let request = common::StartRequest::decode(payload);
+                    let target = request_target(registry, &request.target, request.path.as_deref());
+                    match &request.command {
+                        Some(command) => handle_start_command(registry, command),
+                        None => match request.formation.as_deref().map(str::parse).transpose() {
+                            Ok(formation) => handle_start(
+                                registry,
+                                target,
+                                request.env.as_deref(),
+                                formation.as_ref(),
+                            ),
+                            Err(e) => format!("Invalid formation: {}", e),
+                        },
+                    }
                 }
                 common::Command::Stop(payload) => {
-                    let name = common::Command::decode_payload(payload).unwrap_or("");
-                    handle_stop(registry, name)
+                    let name = common::Command::decode_payload(payload)
+                        .unwrap_or("")
+                        .to_string();
+                    pending.push((request, in_background(move || handle_stop(registry, &name))));
+                    continue;
                 }
                 common::Command::Restart(payload) => {
-                    let name = common::Command::decode_payload(payload).unwrap_or("");
-                    handle_restart(registry, name)
+                    let name = common::Command::decode_payload(payload)
+                        .unwrap_or("")
+                        .to_string();
+                    pending.push((
+                        request,
+                        in_background(move || handle_restart(registry, &name)),
+                    ));
+                    continue;
+                }
+                common::Command::Reload(payload) => {
+                    let payload = common::Command::decode_payload(payload).unwrap_or("");
+                    let reload = common::ReloadRequest::decode(payload);
+                    pending.push((
+                        request,
+                        in_background(move || handle_reload(registry, &reload)),
+                    ));
+                    continue;
                 }
                 common::Command::Delete(payload) => {
-                    let name = common::Command::decode_payload(payload).unwrap_or("");
-                    handle_delete(registry, name)
+                    let name = common::Command::decode_payload(payload)
+                        .unwrap_or("")
+                        .to_string();
+                    pending.push((
+                        request,
+                        in_background(move || handle_delete(registry, &name)),
+                    ));
+                    continue;
                 }
                 common::Command::Enable(payload) => {
                     let path = common::Command::decode_payload(payload).unwrap_or("");
@@ -323,12 +441,57 @@ pub fn run_server() -> Result<(), Box<dyn std::error::Error>> {
                     let name = common::Command::decode_payload(payload).unwrap_or("");
                     handle_flush(registry, name)
                 }
+                common::Command::Reset(payload) => {
+                    let name = common::Command::decode_payload(payload).unwrap_or("");
+                    handle_reset(registry, name)
+                }
                 common::Command::Save => handle_save(registry),
                 common::Command::Resurrect => handle_resurrect(registry),
+                common::Command::ConfigShow(payload) => {
+                    let name = common::Command::decode_payload(payload).unwrap_or("");
+                    handle_config_show(registry, name)
+                }
+                common::Command::Scale(payload) => {
+                    let args = common::Command::decode_payload(payload).unwrap_or("");
+                    handle_scale(registry, args)
+                }
+                common::Command::ReloadConfig(payload) => {
+                    let payload = common::Command::decode_payload(payload).unwrap_or("");
+                    let request = common::ReloadConfigRequest::decode(payload);
+                    let target = request_target(registry, &request.target, request.path.as_deref());
+                    handle_reload_config(registry, target)
+                }
+                common::Command::Import(payload) => {
+                    let payload = common::Command::decode_payload(payload).unwrap_or("");
+                    handle_import(registry, &common::ImportRequest::decode(payload))
+                }
+                common::Command::Export(payload) => {
+                    let payload = common::Command::decode_payload(payload).unwrap_or("");
+                    handle_export(registry, &common::ExportRequest::decode(payload))
+                }
             };
 
             send_response(&request, response, common::CHUNK_PAYLOAD_CAPACITY)?;
         }
+
+        let mut index = 0;
+        while index < pending.len() {
+            let response = match pending[index].1.try_recv() {
+                Ok(response) => response,
+                Err(TryRecvError::Empty) => {
+                    index += 1;
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    "Error: the command failed; see the daemon log".to_string()
+                }
+            };
+            let (request, _) = pending.swap_remove(index);
+            // The client may have given up waiting by now
+            if let Err(e) = send_response(&request, response, common::CHUNK_PAYLOAD_CAPACITY) {
+                eprintln!("Failed to send a response: {}", e);
+            }
+        }
     }
 
     // Save state before exiting
@@ -339,31 +502,396 @@ pub fn run_server() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Run a slow request handler, like stopping processes, on a thread of its
+/// own, so the daemon keeps answering other requests meanwhile
+fn in_background(handler: impl FnOnce() -> String + Send + 'static) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(handler());
+    });
+    receiver
+}
+
+/// Target prefix selecting every process of a project, e.g. `project:billing`
+const PROJECT_PREFIX: &str = "project:";
+
+/// Processes selected by a group target: every process of a
+/// `project:<name>` target, or every instance of an app with `instances`.
+/// Returns None if the target is not a group, and an error message if it
+/// names a project that doesn't exist.
+fn group_processes(
+    registry: &ProcessRegistry,
+    target: &str,
+) -> Option<Result<Vec<ProcessInfo>, String>> {
+    if let Some(project) = target.strip_prefix(PROJECT_PREFIX) {
+        let processes = registry.list_project(project);
+        if processes.is_empty() {
+            return Some(Err(format!("Project '{}' not found", project)));
+        }
+        return Some(Ok(processes));
+    }
+
+    if registry.find(target).is_some() {
+        return None;
+    }
+    let instances = registry.find_instances(target);
+    (!instances.is_empty()).then_some(Ok(instances))
+}
+
+/// Target of a request that takes a process, group or config path. The
+/// client sends the target as typed, plus its absolute path when it exists
+/// in the client's working directory; a process or group of that name wins
+/// over the path.
+fn request_target<'a>(
+    registry: &ProcessRegistry,
+    target: &'a str,
+    path: Option<&'a str>,
+) -> &'a str {
+    let registered = registry.find(target).is_some() || group_processes(registry, target).is_some();
+    match path {
+        Some(path) if !registered => path,
+        _ => target,
+    }
+}
+
+/// Order in which a group's processes are handled
+#[derive(Clone, Copy)]
+enum GroupOrder {
+    /// Dependencies before the apps that depend on them
+    Start,
+    /// Dependents before their dependencies
+    Stop,
+}
+
+/// Run a per-process handler on every process of a group target (see
+/// `group_processes`). Returns None if the target is not a group.
+fn for_each_in_group(
+    registry: &ProcessRegistry,
+    target: &str,
+    order: GroupOrder,
+    handler: impl Fn(&ProcessRegistry, &str) -> String + Sync,
+) -> Option<String> {
+    let processes = match group_processes(registry, target)? {
+        Ok(processes) => dependency::order_processes(processes),
+        Err(message) => return Some(message),
+    };
+    let results: Vec<String> = match order {
+        GroupOrder::Start => processes
+            .iter()
+            .map(|process| handler(registry, &process.name))
+            .collect(),
+        GroupOrder::Stop => stop_in_waves(registry, processes, &handler),
+    };
+    Some(results.join("\n"))
+}
+
+/// Run a stop handler on processes in dependency order, dependents first.
+/// Processes no remaining process depends on are handled at the same time,
+/// so a project's slow stops don't add up. Results are in stop order.
+fn stop_in_waves(
+    registry: &ProcessRegistry,
+    processes: Vec<ProcessInfo>,
+    handler: impl Fn(&ProcessRegistry, &str) -> String + Sync,
+) -> Vec<String> {
+    // A process's wave comes after the waves of everything depending on it
+    let processes: Vec<ProcessInfo> = processes.into_iter().rev().collect();
+    let mut waves: Vec<usize> = Vec::with_capacity(processes.len());
+    for (index, process) in processes.iter().enumerate() {
+        let wave = processes[..index]
+            .iter()
+            .zip(&waves)
+            .filter(|(dependent, _)| {
+                dependent
+                    .depends_on
+                    .iter()
+                    .any(|d| d.name() == process.app_name())
+            })
+            .map(|(_, wave)| wave + 1)
+            .max()
+            .unwrap_or(0);
+        waves.push(wave);
+    }
+
+    let mut results = vec![String::new(); processes.len()];
+    for wave in 0..=waves.iter().copied().max().unwrap_or(0) {
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = processes
+                .iter()
+                .zip(&waves)
+                .enumerate()
+                .filter(|(_, (_, w))| **w == wave)
+                .map(|(index, (process, _))| {
+                    (index, scope.spawn(|| handler(registry, &process.name)))
+                })
+                .collect();
+            for (index, handle) in handles {
+                results[index] = handle
+                    .join()
+                    .unwrap_or_else(|_| format!("Failed to stop {}", processes[index].name));
+            }
+        });
+    }
+    results
+}
+
 fn handle_status(registry: &ProcessRegistry, name: &str) -> String {
-    match registry.get(name) {
+    match registry.find(name) {
         Some(process) => {
-            serde_json::to_string_pretty(&process).unwrap_or_else(|_| format!("{:?}", process))
+            let process = process.redacted();
+            let mut status = match serde_json::to_value(&process) {
+                Ok(status) => status,
+                Err(_) => return format!("{:?}", process),
+            };
+            // What the kernel enforces, which includes limits inherited from
+            // the daemon
+            if let Some(Ok(limits)) = process.pid.map(limits::read) {
+                status["limits_in_effect"] = serde_json::to_value(limits).unwrap_or_default();
+            }
+            serde_json::to_string_pretty(&status).unwrap_or_else(|_| format!("{:?}", process))
         }
         None => format!("Process '{}' not found", name),
     }
 }
 
-fn handle_start(registry: &ProcessRegistry, path_or_name: &str) -> String {
-    // First check if it's an existing process name or ID
-    let existing_process = if let Ok(id) = path_or_name.parse::<usize>() {
-        let processes = registry.list();
-        processes.get(id).cloned()
+fn handle_config_show(registry: &ProcessRegistry, name: &str) -> String {
+    let Some(process) = registry.find(name) else {
+        return format!("Process '{}' not found", name);
+    };
+
+    // Apps started from the command line have nothing to merge
+    if let Some(app) = process.redacted().adhoc_app {
+        return serde_json::to_string_pretty(&app)
+            .unwrap_or_else(|e| format!("Failed to show config for {}: {}", process.name, e));
+    }
+
+    merge::show_app(
+        &process.config_path,
+        &process.name,
+        process.profile.as_deref(),
+    )
+    .unwrap_or_else(|e| format!("Failed to show config for {}: {}", process.name, e))
+}
+
+fn handle_reload_config(registry: &ProcessRegistry, target: &str) -> String {
+    // An empty target reloads everything; a file or directory reloads the
+    // apps registered from it
+    let mut by_path = false;
+    let processes = if target.is_empty() {
+        registry.list()
+    } else if let Some(group) = group_processes(registry, target) {
+        match group {
+            Ok(processes) => processes,
+            Err(message) => return message,
+        }
+    } else if let Some(process) = registry.find(target) {
+        vec![process]
     } else {
-        registry.get(path_or_name)
+        by_path = true;
+        let path = PathBuf::from(target);
+        registry
+            .list()
+            .into_iter()
+            .filter(|p| p.config_path.starts_with(&path))
+            .collect()
     };
 
+    if processes.is_empty() {
+        return match target {
+            "" => "No processes running".to_string(),
+            _ => format!("No processes registered from '{}'", target),
+        };
+    }
+
+    // Apps started from the command line have no config to re-read
+    let (adhoc, processes): (Vec<ProcessInfo>, Vec<ProcessInfo>) =
+        processes.into_iter().partition(ProcessInfo::is_adhoc);
+    if processes.is_empty() {
+        let names: Vec<&str> = adhoc.iter().map(|p| p.name.as_str()).collect();
+        return format!(
+            "{} started from the command line; nothing to reload",
+            names.join(", ")
+        );
+    }
+
+    // Read each config file once
+    let mut by_config: BTreeMap<PathBuf, Vec<ProcessInfo>> = BTreeMap::new();
+    for process in processes {
+        by_config
+            .entry(process.config_path.clone())
+            .or_default()
+            .push(process);
+    }
+
+    let report_new_apps = target.is_empty() || by_path;
+    let mut results = Vec::new();
+    for (config_path, processes) in by_config {
+        reload_config_file(
+            registry,
+            &config_path,
+            &processes,
+            report_new_apps,
+            &mut results,
+        );
+    }
+
+    results.join("\n")
+}
+
+/// Re-read one config file and apply it to the processes registered from it,
+/// restarting those whose spawn settings changed
+fn reload_config_file(
+    registry: &ProcessRegistry,
+    config_path: &Path,
+    processes: &[ProcessInfo],
+    report_new_apps: bool,
+    results: &mut Vec<String>,
+) {
+    let report = validate::validate_file(config_path);
+    if report.has_errors() {
+        results.push(format!(
+            "Invalid config {}, nothing reloaded from it:\n{}",
+            config_path.display(),
+            report
+        ));
+        return;
+    }
+
+    let config = match AppConfig::from_file(&config_path.to_path_buf()) {
+        Ok(config) => config,
+        Err(e) => {
+            results.push(format!("Failed to parse {}: {}", config_path.display(), e));
+            return;
+        }
+    };
+    let config_dir = config_path.parent().unwrap_or(Path::new("."));
+
+    for process in processes {
+        let name = &process.name;
+        let Some(app) = config.find_app(process.app_name()) else {
+            results.push(format!(
+                "{}: no longer in {}, left as is",
+                name,
+                config_path.display()
+            ));
+            continue;
+        };
+        let app = match app
+            .with_profile(process.profile.as_deref())
+            .resolve(config_dir, process.instance.unwrap_or(0))
+        {
+            Ok(app) => app,
+            Err(e) => {
+                results.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+
+        let updated = ProcessInfo::from_app(&app, config_path.to_path_buf());
+        if updated.name != *name {
+            results.push(format!(
+                "{}: `instances` was added or removed, delete the app and start it again",
+                name
+            ));
+            continue;
+        }
+        let changes = config_diff::diff(process, &updated);
+        let cgroup_warnings = match &process.cgroup_path {
+            Some(path) if process.cgroup != updated.cgroup => {
+                cgroup::configure(path, &updated.cgroup)
+            }
+            _ => vec![],
+        };
+        if let Err(e) = registry.apply_config(updated) {
+            results.push(format!("{}: {}", name, e));
+            continue;
+        }
+
+        if changes.is_empty() {
+            results.push(format!("{}: unchanged", name));
+            continue;
+        }
+
+        let needs_restart = changes.iter().any(|change| change.needs_restart);
+        let running = process.pid.is_some();
+        let outcome = match (needs_restart, running) {
+            (true, true) => "restarting",
+            (true, false) => "updated, applies on next start",
+            (false, _) => "updated",
+        };
+        results.push(format!("{}: {}", name, outcome));
+        results.extend(changes.iter().map(|change| format!("  {}", change)));
+        for warning in cgroup_warnings {
+            results.push(format!("  {}", warning));
+        }
+
+        if needs_restart && running {
+            results.push(handle_restart(registry, name));
+        }
+    }
+
+    // New apps and instance counts are reported, not applied implicitly
+    for app in config.get_apps() {
+        let name = app.qualified_name();
+        let registered = registry
+            .list()
+            .into_iter()
+            .filter(|p| p.app_name() == name)
+            .count();
+
+        if registered == 0 && report_new_apps {
+            results.push(format!(
+                "{}: new in {}, run `bpm start {}` to start it",
+                name,
+                config_path.display(),
+                config_path.display()
+            ));
+        } else if registered > 0 && app.instances.is_some() && !procfile::is_procfile(config_path) {
+            let configured = app.instance_count() as usize;
+            if registered != configured {
+                results.push(format!(
+                    "{}: config has {} instances, {} registered, run `bpm scale {} {}`",
+                    name, configured, registered, name, configured
+                ));
+            }
+        }
+    }
+}
+
+fn handle_start(
+    registry: &ProcessRegistry,
+    path_or_name: &str,
+    profile: Option<&str>,
+    formation: Option<&Formation>,
+) -> String {
+    if let Some(output) = for_each_in_group(
+        registry,
+        path_or_name,
+        GroupOrder::Start,
+        |registry, name| handle_start(registry, name, profile, formation),
+    ) {
+        return output;
+    }
+
+    // First check if it's an existing process name or ID
+    let existing_process = registry.find(path_or_name);
+
     // If it's an existing process, just start it
     if let Some(process) = existing_process {
         let name = process.name.clone();
-        match start_process(registry, &process) {
-            Ok(_) => return format!("Started: {}", name),
-            Err(e) => return format!("Failed to start {}: {}", name, e),
+        if profile.is_some() && profile != process.profile.as_deref() {
+            return format!(
+                "{} is registered with env profile {}; delete it to start it with another",
+                name,
+                process.profile.as_deref().unwrap_or("(none)")
+            );
+        }
+        if formation.is_some() {
+            return format!(
+                "{} is already registered; use `bpm scale` to change its instances",
+                name
+            );
         }
+        return start_when_ready(registry, &process);
     }
 
     // Otherwise, treat it as a config file path
@@ -373,50 +901,415 @@ fn handle_start(registry: &ProcessRegistry, path_or_name: &str) -> String {
         return format!("Process or config file not found: {}", path_or_name);
     }
 
+    // Refuse the whole file (or directory) if anything in it is invalid
+    let report = validate::validate_file(&config_path);
+    if report.has_errors() {
+        return format!("Invalid config {}:\n{}", config_path.display(), report);
+    }
+
+    let mut results: Vec<String> = report.diagnostics.iter().map(|d| d.to_string()).collect();
+
+    // (config file, app) for everything to start
+    let mut apps = Vec::new();
+    if config_path.is_dir() {
+        match merge::config_files_in(&config_path) {
+            Ok(files) => {
+                for file in files {
+                    apps.extend(load_config_apps(&file, profile, &mut results));
+                }
+            }
+            Err(e) => results.push(format!("Failed to read {}: {}", config_path.display(), e)),
+        }
+    } else {
+        apps = load_config_apps(&config_path, profile, &mut results);
+    }
+    if let Some(formation) = formation {
+        apply_formation(formation, &mut apps, &mut results);
+    }
+
+    // Dependencies first; cycles were rejected by validation
+    let nodes: Vec<(String, Vec<String>)> = apps
+        .iter()
+        .map(|(_, app)| {
+            let deps = app
+                .depends_on
+                .iter()
+                .map(|d| d.qualified_name(app.project.as_deref()))
+                .collect();
+            (app.qualified_name(), deps)
+        })
+        .collect();
+    let order = dependency::start_order(&nodes).unwrap_or_else(|_| (0..apps.len()).collect());
+
+    for idx in order {
+        let (config_path, app) = &apps[idx];
+        for instance in 0..app.instance_count() {
+            results.push(start_instance(registry, app, config_path, instance));
+        }
+    }
+
+    results.join("\n")
+}
+
+/// Set instance counts from a formation, dropping apps scaled to zero
+fn apply_formation(
+    formation: &Formation,
+    apps: &mut Vec<(PathBuf, App)>,
+    results: &mut Vec<String>,
+) {
+    let names: Vec<&str> = apps.iter().map(|(_, app)| app.name.as_str()).collect();
+    for name in formation.unknown(&names) {
+        results.push(format!(
+            "Warning: formation names '{}', which isn't declared",
+            name
+        ));
+    }
+
+    apps.retain_mut(|(_, app)| match formation.count(&app.name) {
+        Some(0) => false,
+        Some(count) => {
+            app.instances = Some(count);
+            true
+        }
+        None => true,
+    });
+}
+
+/// Register and start an app described on the command line
+/// (`bpm start --name <name> -- <command>`)
+fn handle_start_command(registry: &ProcessRegistry, command: &common::AdHocApp) -> String {
+    let Some((program, args)) = command.command.split_first() else {
+        return "No command given".to_string();
+    };
+    if registry.find(&command.name).is_some() {
+        return format!("Process '{}' already exists", command.name);
+    }
+
+    // Check what a config file would have been checked for
+    if let Some(cwd) = &command.cwd {
+        if !cwd.is_dir() {
+            return format!("cwd '{}' does not exist", cwd.display());
+        }
+    }
+    let found = if program.contains('/') {
+        let cwd = command.cwd.clone().unwrap_or_default();
+        cwd.join(program).is_file()
+    } else {
+        validate::find_in_path(program).is_some()
+    };
+    if !found {
+        return format!("executable '{}' not found", program);
+    }
+
+    let mut app = serde_json::json!({
+        "name": command.name,
+        "script": program,
+        "args": args,
+        "cwd": command.cwd,
+        "env": command.env,
+    });
+    if let Some(policy) = &command.restart {
+        app["restart"] = serde_json::json!({ "policy": policy });
+    }
+    match serde_json::from_value(app) {
+        Ok(app) => start_adhoc(registry, ProcessInfo::from_command(&app)),
+        Err(e) => format!("Failed to start {}: {}", command.name, e),
+    }
+}
+
+/// Register and start an app that has no config file, dropping it again if
+/// it fails to start
+fn start_adhoc(registry: &ProcessRegistry, info: ProcessInfo) -> String {
+    if let Err(e) = registry.register(info.clone()) {
+        return format!("Failed to start {}: {}", info.name, e);
+    }
+    match start_process(registry, &info) {
+        Ok(_) => format!("Started: {}", info.name),
+        Err(e) => {
+            registry.remove(&info.name);
+            format!("Failed to start {}: {}", info.name, e)
+        }
+    }
+}
+
+/// Import another process manager's config and start its apps without
+/// writing config files (`bpm import <source> <file> --start`)
+fn handle_import(registry: &ProcessRegistry, request: &common::ImportRequest) -> String {
+    let import = match import::read(request.source, &request.path) {
+        Ok(import) => import,
+        Err(e) => return format!("Failed to import {}: {}", request.path.display(), e),
+    };
+
+    let mut results = vec![import.report()];
+    for imported in &import.apps {
+        let app = imported.with_profile(request.env.as_deref());
+        for instance in 0..app.instance_count() {
+            results.push(match app.resolve(Path::new("."), instance) {
+                Ok(resolved) => {
+                    // Export gives back the app as imported, not one instance
+                    let info = ProcessInfo {
+                        adhoc_app: Some(imported.clone()),
+                        ..ProcessInfo::from_command(&resolved)
+                    };
+                    start_adhoc(registry, info)
+                }
+                Err(e) => format!("Failed to start {}: {}", app.qualified_name(), e),
+            });
+        }
+    }
+    results.join("\n")
+}
+
+/// Write a process's app back out as a config file: the app as given on the
+/// command line, or its entry in the config file it was started from
+fn handle_export(registry: &ProcessRegistry, request: &common::ExportRequest) -> String {
+    let Some(process) = registry.find(&request.name) else {
+        return format!("Process '{}' not found", request.name);
+    };
+
+    let app = match &process.adhoc_app {
+        Some(app) => app.clone(),
+        None => {
+            let app = AppConfig::from_file(&process.config_path)
+                .map_err(|e| e.to_string())
+                .and_then(|config| {
+                    config.find_app(process.app_name()).ok_or_else(|| {
+                        format!("app is no longer in {}", process.config_path.display())
+                    })
+                });
+            match app {
+                Ok(app) => App {
+                    project: None,
+                    ..app
+                },
+                Err(e) => return format!("Failed to export {}: {}", process.name, e),
+            }
+        }
+    };
+
+    let format = request
+        .output
+        .as_deref()
+        .map_or(ConfigFormat::Json, ConfigFormat::from_path);
+    let content = match export::to_config(&app, format) {
+        Ok(content) => content,
+        Err(e) => return format!("Failed to export {}: {}", process.name, e),
+    };
+
+    match &request.output {
+        Some(path) => match std::fs::write(path, content) {
+            Ok(_) => format!("Exported {} to {}", process.name, path.display()),
+            Err(e) => format!("Failed to write {}: {}", path.display(), e),
+        },
+        None => content,
+    }
+}
+
+/// Read the apps of a config file with the env profile applied, if one was
+/// picked
+fn load_config_apps(
+    config_path: &Path,
+    profile: Option<&str>,
+    results: &mut Vec<String>,
+) -> Vec<(PathBuf, App)> {
+    let config_path = config_path.to_path_buf();
     let config = match AppConfig::from_file(&config_path) {
         Ok(c) => c,
-        Err(e) => return format!("Failed to parse config: {}", e),
+        Err(e) => {
+            results.push(format!("Failed to parse config: {}", e));
+            return Vec::new();
+        }
     };
 
-    let (_, apps) = config.get_apps();
-    let mut results = Vec::new();
+    let apps = config.get_apps();
 
-    for app in apps {
-        let info = ProcessInfo::from_app(&app, config_path.clone());
-        let name = info.name.clone();
-
-        if let Err(e) = registry.register(info.clone()) {
-            results.push(format!("Warning: {}", e));
-            continue;
+    // A profile no app declares is most likely a typo
+    if let Some(profile) = profile {
+        if !apps.iter().any(|app| app.env_profiles.contains_key(profile)) {
+            results.push(format!(
+                "Warning: no app in {} declares env_{}",
+                config_path.display(),
+                profile
+            ));
         }
+    }
+
+    apps.iter()
+        .map(|app| (config_path.clone(), app.with_profile(profile)))
+        .collect()
+}
+
+/// Resolve, register and start one instance of an app once its
+/// dependencies are up
+fn start_instance(
+    registry: &ProcessRegistry,
+    app: &App,
+    config_path: &Path,
+    instance: u32,
+) -> String {
+    let config_dir = config_path.parent().unwrap_or(Path::new("."));
+    let app = match app.resolve(config_dir, instance) {
+        Ok(app) => app,
+        Err(e) => return format!("Failed to start {}: {}", app.qualified_name(), e),
+    };
+    let info = ProcessInfo::from_app(&app, config_path.to_path_buf());
+    if let Err(e) = registry.register(info.clone()) {
+        return format!("Warning: {}", e);
+    }
+    start_when_ready(registry, &info)
+}
 
-        match start_process(registry, &info) {
-            Ok(_) => results.push(format!("Started: {}", name)),
-            Err(e) => results.push(format!("Failed to start {}: {}", name, e)),
+/// Start a registered process, right away if its dependencies are up.
+/// Otherwise it is left `Waiting` while a thread of its own waits for them,
+/// so requests aren't held up meanwhile; the outcome goes to the daemon's
+/// log.
+fn start_when_ready(registry: &ProcessRegistry, info: &ProcessInfo) -> String {
+    let name = info.name.clone();
+    match dependency::dependencies_ready(registry, info) {
+        Ok(true) => {
+            return match start_process(registry, info) {
+                Ok(_) => format!("Started: {}", name),
+                Err(e) => format!("Failed to start {}: {}", name, e),
+            }
         }
+        Ok(false) => {}
+        Err(e) => return format!("Failed to start {}: {}", name, e),
     }
 
-    results.join("\n")
+    let _ = registry.update_state(&name, ProcessState::Waiting);
+    let registry = registry.clone();
+    let waiting = info.clone();
+    std::thread::spawn(move || {
+        let result = match dependency::wait_for_dependencies(&registry, &waiting) {
+            Ok(true) => {
+                // A stop or delete since the last check calls the start off
+                if !registry.transition(&name, ProcessState::Waiting, ProcessState::Starting) {
+                    return;
+                }
+                start_process(&registry, &waiting).map_err(|e| e.to_string())
+            }
+            // Stopped or deleted while it waited
+            Ok(false) => return,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_) => println!("Process '{}' started once its dependencies were up", name),
+            Err(e) => {
+                eprintln!("Failed to start '{}': {}", name, e);
+                let _ = registry.update_state(&name, ProcessState::Errored);
+            }
+        }
+    });
+
+    let dependencies: Vec<&str> = info.depends_on.iter().map(|d| d.name()).collect();
+    format!("Waiting: {} (for {})", info.name, dependencies.join(", "))
 }
 
 fn start_process(
     registry: &ProcessRegistry,
     info: &ProcessInfo,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // Credentials and limits are checked first, so a user that can't be
+    // switched to is reported before the process is marked as starting
+    let credentials = info.run_as.resolve()?;
+    limits::check(&info.limits)?;
+    let _ = registry.update_state(&info.name, ProcessState::Starting);
+
+    let spawned = spawn_process(info, &credentials, &SpawnOptions::default())?;
+    let pid = spawned.child.id();
+    let cgroup_path = spawned.cgroup_path.clone();
+    if let Err(e) = record_spawn(registry, &info.name, spawned) {
+        // Deleted while it was being restarted, so nothing tracks it
+        let _ = stop::stop(pid, cgroup_path.as_deref(), &info.stop_sequence);
+        if let Some(path) = &cgroup_path {
+            cgroup::remove(path);
+        }
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// How `spawn_process` treats a process beyond its app's settings
+#[derive(Default)]
+struct SpawnOptions<'a> {
+    /// Append to the logs rather than start them over, for a process that
+    /// runs next to the one it replaces
+    append_logs: bool,
+    /// Passed to the process as `NOTIFY_SOCKET`
+    notify_socket: Option<&'a Path>,
+    /// Give the process a cgroup of its own rather than the app's, which
+    /// the process it replaces is still in
+    handover: bool,
+}
+
+/// A process spawned for an app, not yet recorded in the registry
+struct Spawned {
+    child: std::process::Child,
+    cgroup_path: Option<PathBuf>,
+    secret_values: Vec<String>,
+}
+
+fn spawn_process(
+    info: &ProcessInfo,
+    credentials: &Credentials,
+    options: &SpawnOptions,
+) -> Result<Spawned, Box<dyn std::error::Error>> {
     use std::process::{Command, Stdio};
 
-    let _ = registry.update_state(&info.name, ProcessState::Starting);
+    if info.has_masked_env() {
+        let fix = if info.is_adhoc() {
+            "delete it and start it again"
+        } else {
+            "run `bpm reload-config` to read them from its config again"
+        };
+        return Err(format!(
+            "its secret env values weren't kept when the daemon restarted; {}",
+            fix
+        )
+        .into());
+    }
 
     // Create log directories
-    if let Some(parent) = info.stdout_log.parent() {
-        std::fs::create_dir_all(parent)?;
+    for log in [&info.stdout_log, &info.stderr_log] {
+        if let Some(parent) = log.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
     }
 
-    let stdout_file = std::fs::File::create(&info.stdout_log)?;
-    let stderr_file = std::fs::File::create(&info.stderr_log)?;
+    let open_log = |path: &Path| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(options.append_logs)
+            .truncate(!options.append_logs)
+            .open(path)
+    };
+    let stdout_file = open_log(&info.stdout_log)?;
+    let stderr_file = open_log(&info.stderr_log)?;
+
+    // Apps run without a cgroup of their own where the host doesn't allow it
+    let prepared = match options.handover {
+        true => cgroup::prepare_handover(&info.name, &info.cgroup),
+        false => cgroup::prepare(&info.name, &info.cgroup),
+    };
+    let cgroup_path = match prepared {
+        Ok((path, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}: {}", info.name, warning);
+            }
+            Some(path)
+        }
+        Err(e) => {
+            if !info.cgroup.is_empty() {
+                eprintln!("{}: cgroup settings not applied: {}", info.name, e);
+            }
+            None
+        }
+    };
 
     let mut cmd = Command::new(&info.script);
-    cmd.args(&info.args)
+    process::in_own_session(&mut cmd)
+        .args(&info.args)
         .stdout(Stdio::from(stdout_file))
         .stderr(Stdio::from(stderr_file));
 
@@ -424,63 +1317,116 @@ fn start_process(
         cmd.current_dir(cwd);
     }
 
-    for (key, value) in &info.env {
+    // Secrets are read fresh on every spawn and only kept in memory
+    let env = info.spawn_env()?;
+    let secret_values = env
+        .iter()
+        .filter(|(key, value)| info.env.get(*key) != Some(*value))
+        .map(|(_, value)| value.clone())
+        .collect();
+    for (key, value) in credentials.env() {
         cmd.env(key, value);
     }
+    for (key, value) in &env {
+        cmd.env(key, value);
+    }
+    if let Some(socket) = options.notify_socket {
+        cmd.env("NOTIFY_SOCKET", socket);
+    }
+    // Joining the cgroup and raising limits need the privileges switching
+    // users drops
+    if let Some(path) = &cgroup_path {
+        cgroup::join(path, &mut cmd);
+    }
+    limits::apply(&info.limits, &mut cmd);
+    credentials.apply(&mut cmd);
+
+    Ok(Spawned {
+        child: reaper::spawn_app(&mut cmd)?,
+        cgroup_path,
+        secret_values,
+    })
+}
 
-    let child = cmd.spawn()?;
-    let pid = child.id();
+/// Make a spawned process the one the registry tracks for `name`. Returns
+/// the process it replaces, if that one was still running.
+fn record_spawn(
+    registry: &ProcessRegistry,
+    name: &str,
+    spawned: Spawned,
+) -> Result<Option<u32>, String> {
+    registry.set_cgroup(name, spawned.cgroup_path)?;
+    let replaced = registry.update_pid(name, Some(spawned.child.id()))?;
+    registry.set_secret_values(name, spawned.secret_values)?;
+    Ok(replaced)
+}
 
-    registry.update_pid(&info.name, Some(pid))?;
+/// Restart a running process, stopping it as `bpm stop` would. That can take
+/// up to its kill_timeout, so it happens on a thread of its own; until the
+/// new process is spawned it is `Stopping`, which isn't taken for a crash.
+/// `restarted` is logged once it is back.
+fn restart_in_background(
+    registry: &ProcessRegistry,
+    process: ProcessInfo,
+    reason: RestartReason,
+    restarted: String,
+) {
+    let _ = registry.update_state(&process.name, ProcessState::Stopping);
+    let _ = registry.set_restart_reason(&process.name, reason);
+    let registry = registry.clone();
+    std::thread::spawn(move || {
+        stop_before_restart(&registry, &process);
+        match start_process(&registry, &process) {
+            Ok(_) => println!("{}", restarted),
+            Err(e) => {
+                eprintln!("Failed to restart '{}': {}", process.name, e);
+                let _ = registry.update_state(&process.name, ProcessState::Stopped);
+            }
+        }
+    });
+}
 
-    Ok(())
+/// Stop a running process that is about to be spawned again, so the old one
+/// doesn't linger next to the new one
+fn stop_before_restart(registry: &ProcessRegistry, process: &ProcessInfo) {
+    if let Some(pid) = process.pid {
+        if let Err(e) = stop::stop(pid, process.cgroup_path.as_deref(), &process.stop_sequence) {
+            eprintln!("Failed to stop '{}': {}", process.name, e);
+        }
+        let _ = registry.update_pid(&process.name, None);
+    }
 }
 
 fn handle_stop(registry: &ProcessRegistry, name_or_id: &str) -> String {
-    // Try to find process by ID first, then by name
-    let process = if let Ok(id) = name_or_id.parse::<usize>() {
-        let processes = registry.list();
-        processes.get(id).cloned()
-    } else {
-        registry.get(name_or_id)
-    };
+    if let Some(output) = for_each_in_group(registry, name_or_id, GroupOrder::Stop, handle_stop) {
+        return output;
+    }
 
-    match process {
+    match registry.find(name_or_id) {
         Some(process) => {
             let name = process.name.clone();
             if let Some(pid) = process.pid {
                 let _ = registry.update_state(&name, ProcessState::Stopping);
 
-                // Send SIGTERM
-                if let Err(e) = nix::sys::signal::kill(
-                    nix::unistd::Pid::from_raw(pid as i32),
-                    nix::sys::signal::Signal::SIGTERM,
-                ) {
-                    return format!("Failed to send SIGTERM: {}", e);
-                }
-
-                // Wait a bit, then check if process is still running
-                std::thread::sleep(Duration::from_secs(2));
-
-                // Verify process is actually dead using sysinfo
-                use sysinfo::{Pid, ProcessesToUpdate, System};
-                let mut sys = System::new();
-                sys.refresh_processes(ProcessesToUpdate::All, true);
-                let sys_pid = Pid::from_u32(pid);
-                
-                if sys.process(sys_pid).is_some() {
-                    // Still running, send SIGKILL
-                    let _ = nix::sys::signal::kill(
-                        nix::unistd::Pid::from_raw(pid as i32),
-                        nix::sys::signal::Signal::SIGKILL,
-                    );
-                    std::thread::sleep(Duration::from_millis(500));
-                }
+                let outcome =
+                    match stop::stop(pid, process.cgroup_path.as_deref(), &process.stop_sequence) {
+                        Ok(outcome) => outcome,
+                        Err(e) => {
+                            let _ = registry.update_state(&name, process.state);
+                            return format!("Failed to stop {}: {}", name, e);
+                        }
+                    };
 
-                let _ = registry.update_state(&name, ProcessState::Stopped);
-                let _ = registry.update_pid(&name, None);
+                let _ = registry.mark_stopped(&name, pid, outcome.exit_reason());
 
-                format!("Stopped: {}", name)
+                format!(
+                    "Stopped: {} ({}){}",
+                    name,
+                    outcome,
+                    running_dependents(registry, &process)
+                )
+            } else if registry.transition(&name, ProcessState::Waiting, ProcessState::Stopped) {
+                format!("Stopped: {} (it was waiting for its dependencies)", name)
             } else {
                 format!("Process '{}' is not running", name)
             }
@@ -489,14 +1435,35 @@ fn handle_stop(registry: &ProcessRegistry, name_or_id: &str) -> String {
     }
 }
 
+/// A note naming the apps that depend on `process` and are still up, for a
+/// reply to stopping it on its own. Stopping a project stops dependents
+/// first, so it has none.
+fn running_dependents(registry: &ProcessRegistry, process: &ProcessInfo) -> String {
+    let dependents: Vec<String> = registry
+        .list()
+        .into_iter()
+        .filter(|p| p.pid.is_some() || p.state == ProcessState::Waiting)
+        .filter(|p| p.depends_on.iter().any(|d| d.name() == process.app_name()))
+        .map(|p| p.name)
+        .collect();
+    if dependents.is_empty() {
+        return String::new();
+    }
+    format!(
+        "\nNote: left running, though they depend on {}: {}",
+        process.name,
+        dependents.join(", ")
+    )
+}
+
 fn handle_restart(registry: &ProcessRegistry, name_or_id: &str) -> String {
+    if let Some(output) = for_each_in_group(registry, name_or_id, GroupOrder::Start, handle_restart)
+    {
+        return output;
+    }
+
     // Get the actual process name first
-    let process_name = if let Ok(id) = name_or_id.parse::<usize>() {
-        let processes = registry.list();
-        processes.get(id).map(|p| p.name.clone())
-    } else {
-        registry.get(name_or_id).map(|p| p.name.clone())
-    };
+    let process_name = registry.find(name_or_id).map(|p| p.name);
 
     let stop_result = handle_stop(registry, name_or_id);
 
@@ -506,6 +1473,7 @@ fn handle_restart(registry: &ProcessRegistry, name_or_id: &str) -> String {
             
             // Increment restart counter for manual restarts
             let new_count = registry.increment_restart_count(&name).unwrap_or(0);
+            let _ = registry.set_restart_reason(&name, RestartReason::Manual);
             
             match start_process(registry, &process) {
                 Ok(_) => format!("{}\nRestarted: {} (restart #{})", stop_result, name, new_count),
@@ -519,18 +1487,308 @@ fn handle_restart(registry: &ProcessRegistry, name_or_id: &str) -> String {
     }
 }
 
-fn handle_delete(registry: &ProcessRegistry, name: &str) -> String {
-    let stop_result = handle_stop(registry, name);
+fn handle_reload(registry: &ProcessRegistry, request: &common::ReloadRequest) -> String {
+    let processes = match group_processes(registry, &request.target) {
+        Some(Ok(processes)) => dependency::order_processes(processes),
+        Some(Err(message)) => return message,
+        None => match registry.find(&request.target) {
+            Some(process) => vec![process],
+            None => return format!("Process '{}' not found", request.target),
+        },
+    };
 
-    match registry.remove(name) {
-        Some(_) => format!("{}\nDeleted: {}", stop_result, name),
-        None => format!("Process '{}' not found", name),
+    // Instances of an app are replaced a batch at a time, one app after
+    // another, and the reload stops at the first batch that fails
+    let mut output = Vec::new();
+    let mut rest = processes.as_slice();
+    while let Some(first) = rest.first() {
+        let same_app = rest
+            .iter()
+            .take_while(|p| p.app_name() == first.app_name())
+            .count();
+        let batch_size = request.batch_size.unwrap_or(first.reload.batch_size).max(1);
+        let (batch, remaining) = rest.split_at(same_app.min(batch_size as usize));
+        rest = remaining;
+
+        let (lines, reloaded) = reload_batch(registry, batch);
+        output.extend(lines);
+        if !reloaded {
+            if !rest.is_empty() {
+                let names: Vec<&str> = rest.iter().map(|p| p.name.as_str()).collect();
+                output.push(format!(
+                    "Reload stopped; not reloaded: {}",
+                    names.join(", ")
+                ));
+            }
+            break;
+        }
+    }
+    output.join("\n")
+}
+
+/// A new process started next to the one it is to replace
+struct Handover<'a> {
+    process: &'a ProcessInfo,
+    old_pid: u32,
+    spawned: Spawned,
+    /// When the new process was spawned
+    started: Instant,
+    notify: Option<NotifySocket>,
+}
+
+/// Start a new process for `process`, which keeps running for now
+fn begin_handover(
+    process: &ProcessInfo,
+    old_pid: u32,
+) -> Result<Handover<'_>, Box<dyn std::error::Error>> {
+    let credentials = process.run_as.resolve()?;
+    limits::check(&process.limits)?;
+    if let (false, Some(healthcheck)) = (process.reload.wait_ready, &process.healthcheck) {
+        reload::check_readiness(healthcheck)?;
+    }
+    let notify = match process.reload.wait_ready {
+        true => Some(NotifySocket::new()?),
+        false => None,
+    };
+    if let (Some(socket), Some(user)) = (&notify, &process.run_as.user) {
+        socket.allow(credentials::lookup_user(user)?.uid)?;
+    }
+
+    let options = SpawnOptions {
+        append_logs: true,
+        notify_socket: notify.as_ref().map(NotifySocket::path),
+        handover: true,
+    };
+    let spawned = spawn_process(process, &credentials, &options)?;
+    Ok(Handover {
+        process,
+        old_pid,
+        spawned,
+        started: Instant::now(),
+        notify,
+    })
+}
+
+/// Replace every process of `batch` with a new one. The old ones are only
+/// stopped once all the new ones are ready; otherwise the new ones are
+/// stopped and the old ones keep running. Returns what happened, and
+/// whether the batch was reloaded.
+fn reload_batch(registry: &ProcessRegistry, batch: &[ProcessInfo]) -> (Vec<String>, bool) {
+    let mut lines = Vec::new();
+    let mut handovers = Vec::new();
+    let mut failed = false;
+    for process in batch {
+        match (process.pid, &process.state) {
+            (Some(old_pid), ProcessState::Running) => match begin_handover(process, old_pid) {
+                Ok(handover) => {
+                    let _ = registry.update_state(&process.name, ProcessState::Reloading);
+                    handovers.push(handover);
+                }
+                Err(e) => {
+                    failed = true;
+                    lines.push(format!("Failed to reload {}: {}", process.name, e));
+                }
+            },
+            // Nothing to hand over from a process that isn't running
+            (None, _) => match start_process(registry, process) {
+                Ok(()) => lines.push(format!("Started: {} (it wasn't running)", process.name)),
+                Err(e) => {
+                    failed = true;
+                    lines.push(format!("Failed to start {}: {}", process.name, e));
+                }
+            },
+            (Some(_), state) => lines.push(format!(
+                "Process '{}' is {}, not reloaded",
+                process.name, state
+            )),
+        }
+    }
+
+    for handover in &mut handovers {
+        if failed {
+            break;
+        }
+        let readiness = match (&handover.notify, &handover.process.healthcheck) {
+            (Some(socket), _) => Readiness::Notify(socket),
+            (None, Some(healthcheck)) => Readiness::Healthcheck(healthcheck),
+            (None, None) => Readiness::Uptime,
+        };
+        let timeout = handover.process.reload.ready_timeout;
+        let child = &mut handover.spawned.child;
+        if let Err(e) = reload::wait_ready(child, handover.started, readiness, timeout) {
+            failed = true;
+            lines.push(format!(
+                "Failed to reload {}: the new process {}",
+                handover.process.name, e
+            ));
+        }
+    }
+
+    for Handover {
+        process,
+        old_pid,
+        spawned,
+        ..
+    } in handovers
+    {
+        let new_pid = spawned.child.id();
+        let handover_cgroup = spawned.cgroup_path.clone();
+        if failed {
+            if let Err(e) = stop::stop(new_pid, handover_cgroup.as_deref(), &process.stop_sequence)
+            {
+                eprintln!(
+                    "Failed to stop the new process of '{}': {}",
+                    process.name, e
+                );
+            }
+            if let Some(path) = &handover_cgroup {
+                cgroup::remove(path);
+            }
+            let _ = registry.cancel_reload(&process.name);
+            let still_running = registry.get(&process.name).is_some_and(|p| p.pid.is_some());
+            lines.push(match still_running {
+                true => format!(
+                    "Rolled back {}: pid {} keeps running",
+                    process.name, old_pid
+                ),
+                false => format!(
+                    "Rolled back {}: pid {} exited during the reload",
+                    process.name, old_pid
+                ),
+            });
+            continue;
+        }
+
+        // The old process may have exited during the reload
+        let replaced = match record_spawn(registry, &process.name, spawned) {
+            Ok(replaced) => replaced,
+            Err(e) => {
+                // Deleted meanwhile, so nothing tracks the new process
+                let _ = stop::stop(new_pid, handover_cgroup.as_deref(), &process.stop_sequence);
+                if let Some(path) = &handover_cgroup {
+                    cgroup::remove(path);
+                }
+                lines.push(format!("Failed to reload {}: {}", process.name, e));
+                continue;
+            }
+        };
+        let new_count = registry.increment_restart_count(&process.name).unwrap_or(0);
+        let _ = registry.set_restart_reason(&process.name, RestartReason::Reloaded);
+        let stopped = match replaced {
+            Some(pid) => {
+                match stop::stop(pid, process.cgroup_path.as_deref(), &process.stop_sequence) {
+                    Ok(outcome) => outcome.to_string(),
+                    Err(e) => format!("failed to stop: {}", e),
+                }
+            }
+            None => "had already exited".to_string(),
+        };
+        // The new process keeps the cgroup it was spawned in, and the old
+        // process's is removed
+        if let Some(path) = process.cgroup_path.as_deref() {
+            if handover_cgroup.as_deref() != Some(path) {
+                cgroup::remove(path);
+            }
+        }
+        lines.push(format!(
+            "Reloaded: {} (pid {} -> {}, old process {}, restart #{})",
+            process.name, old_pid, new_pid, stopped, new_count
+        ));
+    }
+    (lines, !failed)
+}
+
+fn handle_delete(registry: &ProcessRegistry, name_or_id: &str) -> String {
+    if let Some(output) = for_each_in_group(registry, name_or_id, GroupOrder::Stop, handle_delete) {
+        return output;
+    }
+
+    let Some(process) = registry.find(name_or_id) else {
+        return format!("Process '{}' not found", name_or_id);
+    };
+    let stop_result = handle_stop(registry, &process.name);
+
+    match registry.remove(&process.name) {
+        Some(removed) => {
+            // A restart on another thread may have spawned it again since
+            // it was stopped
+            if let Some(pid) = removed.pid {
+                let _ = stop::stop(pid, removed.cgroup_path.as_deref(), &removed.stop_sequence);
+            }
+            if let Some(path) = &removed.cgroup_path {
+                cgroup::remove(path);
+            }
+            format!("{}\nDeleted: {}", stop_result, process.name)
+        }
+        None => format!("Process '{}' not found", process.name),
     }
 }
 
 fn handle_enable(registry: &ProcessRegistry, path: &str) -> String {
     // Enable is same as start for now
-    handle_start(registry, path)
+    handle_start(registry, path, None, None)
+}
+
+fn handle_scale(registry: &ProcessRegistry, args: &str) -> String {
+    // Payload is "<app>:<count>"
+    let Some((target, count)) = args.rsplit_once(':') else {
+        return "Usage: bpm scale <app> <count>".to_string();
+    };
+    let count = match count.parse::<u32>() {
+        Ok(count) if count > 0 => count,
+        _ => return format!("Invalid instance count '{}', expected 1 or more", count),
+    };
+
+    let instances = registry.find_instances(target);
+    let Some(first) = instances.first() else {
+        return match registry.find(target) {
+            Some(process) => format!(
+                "{} doesn't use `instances`; set it in the app's config to run copies",
+                process.name
+            ),
+            None => format!("Process '{}' not found", target),
+        };
+    };
+    let app_name = first.app_name().to_string();
+    let mut results = Vec::new();
+
+    // Scale down from the highest index
+    for process in instances.iter().rev() {
+        if process.instance.is_some_and(|instance| instance >= count) {
+            results.push(handle_delete(registry, &process.name));
+        }
+    }
+
+    // Scale up by filling in the missing indices, using the app's current
+    // config and the profile it was started with
+    let missing: Vec<u32> = (0..count)
+        .filter(|index| !instances.iter().any(|p| p.instance == Some(*index)))
+        .collect();
+    if !missing.is_empty() {
+        let app = match AppConfig::from_file(&first.config_path) {
+            Ok(config) => config.find_app(&app_name),
+            Err(e) => return format!("Failed to parse config: {}", e),
+        };
+        let Some(app) = app else {
+            return format!(
+                "{} is no longer in {}",
+                app_name,
+                first.config_path.display()
+            );
+        };
+
+        let mut app = app.with_profile(first.profile.as_deref());
+        app.instances = Some(count);
+        for instance in missing {
+            results.push(start_instance(registry, &app, &first.config_path, instance));
+        }
+    }
+
+    if results.is_empty() {
+        format!("{} already has {} instances", app_name, count)
+    } else {
+        results.join("\n")
+    }
 }
 
 fn handle_disable(registry: &ProcessRegistry, name: &str) -> String {
@@ -543,20 +1801,25 @@ fn handle_disable(registry: &ProcessRegistry, name: &str) -> String {
 }
 
 fn handle_logs(registry: &ProcessRegistry, args: &str) -> String {
-    let parts: Vec<&str> = args.split(':').collect();
-    let name_or_id = parts.first().unwrap_or(&"");
-    let lines: usize = parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
-    // follow is ignored in this simple implementation
-
-    // Try to find process by ID first, then by name
-    let process = if let Ok(id) = name_or_id.parse::<usize>() {
-        let processes = registry.list();
-        processes.get(id).cloned()
-    } else {
-        registry.get(name_or_id)
-    };
+    // Payload is "<target>:<lines>:<follow>"; the target itself may contain ':'
+    let mut parts = args.rsplitn(3, ':');
+    let _follow = parts.next(); // follow is ignored in this simple implementation
+    let lines: usize = parts.next().and_then(|s| s.parse().ok()).unwrap_or(20);
+    let name_or_id = parts.next().unwrap_or("");
+
+    if let Some(group) = group_processes(registry, name_or_id) {
+        let processes = match group {
+            Ok(processes) => processes,
+            Err(message) => return message,
+        };
+        let logs: Vec<String> = processes
+            .iter()
+            .map(|process| handle_logs(registry, &format!("{}:{}:false", process.name, lines)))
+            .collect();
+        return logs.join("\n");
+    }
 
-    if let Some(process) = process {
+    if let Some(process) = registry.find(name_or_id) {
         let name = process.name.clone();
         let mut output = String::new();
 
@@ -585,7 +1848,7 @@ fn handle_logs(registry: &ProcessRegistry, args: &str) -> String {
         if output.is_empty() {
             format!("No logs found for: {}", name)
         } else {
-            output
+            process.mask_secrets(&output)
         }
     } else {
         format!("Process '{}' not found", name_or_id)
@@ -610,6 +1873,21 @@ fn handle_flush(registry: &ProcessRegistry, name: &str) -> String {
     }
 }
 
+fn handle_reset(registry: &ProcessRegistry, name_or_id: &str) -> String {
+    if let Some(output) = for_each_in_group(registry, name_or_id, GroupOrder::Start, handle_reset) {
+        return output;
+    }
+
+    let Some(process) = registry.find(name_or_id) else {
+        return format!("Process '{}' not found", name_or_id);
+    };
+    match registry.reset_counters(&process.name) {
+        Ok(false) => format!("Reset: {}", process.name),
+        Ok(true) => format!("Reset: {} (restarting it now)", process.name),
+        Err(e) => format!("Failed to reset {}: {}", process.name, e),
+    }
+}
+
 fn handle_save(registry: &ProcessRegistry) -> String {
     match registry.save_state(&get_state_file()) {
         Ok(_) => format!("State saved to: {}", get_state_file().display()),
@@ -623,12 +1901,19 @@ fn handle_resurrect(registry: &ProcessRegistry) -> String {
         return format!("Failed to load state: {}", e);
     }
 
-    // Then start all processes that were running
-    let processes = registry.list();
+    // Then start all processes that were running, dependencies first
+    let processes = dependency::order_processes(registry.list());
     let mut results = Vec::new();
 
     for process in processes {
         if process.state == ProcessState::Running || process.state == ProcessState::Stopped {
+            let process = match restore_secret_env(registry, process) {
+                Ok(process) => process,
+                Err(e) => {
+                    results.push(e);
+                    continue;
+                }
+            };
             match start_process(registry, &process) {
                 Ok(_) => results.push(format!("Resurrected: {}", process.name)),
                 Err(e) => results.push(format!("Failed to resurrect {}: {}", process.name, e)),
@@ -643,6 +1928,53 @@ fn handle_resurrect(registry: &ProcessRegistry) -> String {
     }
 }
 
+/// Put back the env values masked in the state file, reading them from the
+/// process's config again
+fn restore_secret_env(
+    registry: &ProcessRegistry,
+    process: ProcessInfo,
+) -> Result<ProcessInfo, String> {
+    if !process.has_masked_env() {
+        return Ok(process);
+    }
+    let failed = |e: String| {
+        format!(
+            "Failed to resurrect {}: secret env not restored: {}",
+            process.name, e
+        )
+    };
+    if process.is_adhoc() {
+        return Err(failed(
+            "it was started from the command line; delete it and start it again".to_string(),
+        ));
+    }
+
+    let config = AppConfig::from_file(&process.config_path).map_err(|e| failed(e.to_string()))?;
+    let app = config.find_app(process.app_name()).ok_or_else(|| {
+        failed(format!(
+            "app is no longer in {}",
+            process.config_path.display()
+        ))
+    })?;
+    let config_dir = process.config_path.parent().unwrap_or(Path::new("."));
+    let app = app
+        .with_profile(process.profile.as_deref())
+        .resolve(config_dir, process.instance.unwrap_or(0))
+        .map_err(|e| failed(e.to_string()))?;
+
+    let mut restored = process.clone();
+    for key in std::mem::take(&mut restored.redacted_env) {
+        let value = app
+            .env
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| failed(format!("{} is no longer set", key)))?;
+        restored.env.insert(key, value);
+    }
+    registry.apply_config(restored.clone())?;
+    Ok(restored)
+}
+
 pub fn send_response<Service, RequestPayload, RequestHeader, ResponsePayload, ResponseHeader>(
     request: &ActiveRequest<
         Service,
@@ -0,0 +1,113 @@
+//! `.env` file parsing
+//!
+//! Supports `KEY=VALUE` lines, an optional `export` prefix, `#` comments,
+//! single-quoted (literal) and double-quoted (escaped) values.
+
+use std::path::Path;
+
+/// Parse dotenv content into ordered key/value pairs
+pub fn parse(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+
+    for (idx, raw_line) in content.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=VALUE", idx + 1))?;
+
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("line {}: invalid variable name '{}'", idx + 1, key));
+        }
+
+        let value = parse_value(value.trim()).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+        vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Read and parse a dotenv file
+pub fn load(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+    parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    if let Some(inner) = value.strip_prefix('\'') {
+        let end = inner.find('\'').ok_or("unterminated single quote")?;
+        return Ok(inner[..end].to_string());
+    }
+
+    if let Some(inner) = value.strip_prefix('"') {
+        let mut output = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Ok(output),
+                '\\' => match chars.next() {
+                    Some('n') => output.push('\n'),
+                    Some('t') => output.push('\t'),
+                    Some(other) => output.push(other),
+                    None => break,
+                },
+                other => output.push(other),
+            }
+        }
+        return Err("unterminated double quote".to_string());
+    }
+
+    // Unquoted values end at an inline comment
+    let value = match value.find(" #") {
+        Some(pos) => &value[..pos],
+        None => value,
+    };
+    Ok(value.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dotenv() {
+        let content = r#"
+# Database settings
+DATABASE_URL=postgres://localhost/db
+export PORT=3000
+GREETING="hello\nworld"
+LITERAL='no $expansion here'
+EMPTY=
+TRAILING=value # comment
+"#;
+
+        let vars = parse(content).unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                (
+                    "DATABASE_URL".to_string(),
+                    "postgres://localhost/db".to_string()
+                ),
+                ("PORT".to_string(), "3000".to_string()),
+                ("GREETING".to_string(), "hello\nworld".to_string()),
+                ("LITERAL".to_string(), "no $expansion here".to_string()),
+                ("EMPTY".to_string(), "".to_string()),
+                ("TRAILING".to_string(), "value".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_dotenv_errors() {
+        assert!(parse("NO_EQUALS_SIGN").is_err());
+        assert!(parse("BAD-NAME=1").is_err());
+        assert!(parse("QUOTED=\"unterminated").is_err());
+    }
+}
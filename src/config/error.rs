@@ -1,11 +1,44 @@
 //! Config Error Types
 
-#![allow(dead_code)] // Error types for future use
-
+use std::path::PathBuf;
 use thiserror::Error;
 
+use crate::config::read_config::ConfigFormat;
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
-    #[error("unforeseen error occurred")]
-    Unknown,
+    #[error("failed to read config file '{}': {source}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid {format} config{}: {message}", location_suffix(*.line, *.column))]
+    Parse {
+        format: ConfigFormat,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+
+    #[error("invalid Procfile{}: {message}", location_suffix(*.line, None))]
+    Procfile {
+        line: Option<usize>,
+        message: String,
+    },
+
+    #[error("app '{app}': {message}")]
+    Resolve { app: String, message: String },
+
+    #[error("config '{}': {message}", path.display())]
+    Layering { path: PathBuf, message: String },
+}
+
+fn location_suffix(line: Option<usize>, column: Option<usize>) -> String {
+    match (line, column) {
+        (Some(line), Some(column)) => format!(" at line {}, column {}", line, column),
+        (Some(line), None) => format!(" at line {}", line),
+        _ => String::new(),
+    }
 }
@@ -0,0 +1,118 @@
+//! Config export
+//!
+//! Writes an app back out as a config file for `bpm export`. Only settings
+//! that differ from the defaults are kept, so the result reads like a
+//! hand-written config, with fields in the order `App` declares them.
+
+use serde_yaml::Value;
+
+use crate::config::read_config::{App, ConfigFormat};
+
+/// Render `app` as a single-app config in `format`
+pub fn to_config(app: &App, format: ConfigFormat) -> Result<String, String> {
+    serialize(&minimal(app)?, format)
+}
+
+/// Render `apps` as a multi-app config in `format`, all under `project`
+pub fn to_project_config(
+    project: &str,
+    apps: &[App],
+    format: ConfigFormat,
+) -> Result<String, String> {
+    let apps = apps.iter().map(minimal).collect::<Result<Vec<_>, _>>()?;
+    let mut value = serde_yaml::Mapping::new();
+    value.insert(Value::from(project), Value::Sequence(apps));
+    serialize(&Value::Mapping(value), format)
+}
+
+fn serialize(value: &Value, format: ConfigFormat) -> Result<String, String> {
+    match format {
+        ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        ConfigFormat::Toml => toml::to_string(value).map_err(|e| e.to_string()),
+    }
+}
+
+/// The app as a config value without the settings left at their defaults
+fn minimal(app: &App) -> Result<Value, String> {
+    let mut value = serde_yaml::to_value(app).map_err(|e| e.to_string())?;
+    let defaults: App =
+        serde_json::from_str(r#"{"name": "", "script": ""}"#).map_err(|e| e.to_string())?;
+    let defaults = serde_yaml::to_value(defaults).map_err(|e| e.to_string())?;
+    prune(&mut value, &defaults);
+    Ok(value)
+}
+
+/// Remove nulls and every key of `value` that matches `defaults`, dropping
+/// objects that end up empty
+fn prune(value: &mut Value, defaults: &Value) {
+    let Value::Mapping(fields) = value else {
+        return;
+    };
+    fields.retain(|key, field| {
+        let default = defaults.get(key).unwrap_or(&Value::Null);
+        if field.is_mapping() {
+            prune(field, default);
+            return field.as_mapping().is_some_and(|m| !m.is_empty());
+        }
+        !field.is_null() && field != default
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config::{parse_as, AppConfig};
+    use serde_json::json;
+
+    fn app(json: &str) -> App {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_export_keeps_only_changed_settings() {
+        let app = app(r#"{
+            "name": "redis-tunnel",
+            "script": "ssh",
+            "args": ["-N", "db-host"],
+            "env": {"K": "V"},
+            "restart": {"policy": "always"}
+        }"#);
+        let exported = to_config(&app, ConfigFormat::Json).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&exported).unwrap(),
+            json!({
+                "name": "redis-tunnel",
+                "script": "ssh",
+                "args": ["-N", "db-host"],
+                "env": {"K": "V"},
+                "restart": {"policy": "always"}
+            })
+        );
+        assert!(exported.find("\"name\"") < exported.find("\"args\""));
+    }
+
+    #[test]
+    fn test_export_round_trips_in_every_format() {
+        let app = app(r#"{
+            "name": "api",
+            "script": "node",
+            "cwd": "/srv/api",
+            "env_staging": {"PORT": "80"},
+            "healthcheck": {"type": "tcp", "port": 80},
+            "depends_on": ["db"]
+        }"#);
+
+        for format in [ConfigFormat::Json, ConfigFormat::Yaml, ConfigFormat::Toml] {
+            let content = to_config(&app, format).unwrap();
+            let config: AppConfig = parse_as(&content, format).unwrap();
+            let apps = config.get_apps();
+            assert_eq!(apps.len(), 1, "{}", content);
+            assert_eq!(apps[0].name, "api");
+            assert_eq!(apps[0].env_profiles["staging"]["PORT"], "80");
+            assert_eq!(apps[0].healthcheck.as_ref().unwrap().port, Some(80));
+            assert_eq!(apps[0].depends_on.len(), 1);
+        }
+    }
+}
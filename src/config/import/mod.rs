@@ -0,0 +1,219 @@
+//! Config importers
+//!
+//! `bpm import <source> <file>` turns another process manager's config into
+//! bpm apps. Each importer returns the apps plus a report of what didn't
+//! carry over, so nothing is dropped silently.
+
+pub mod pm2;
+pub mod supervisord;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::export;
+use crate::config::read_config::{App, ConfigFormat};
+
+/// Process manager a config is imported from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    #[default]
+    Pm2,
+    Supervisord,
+}
+
+impl ImportSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            ImportSource::Pm2 => "pm2",
+            ImportSource::Supervisord => "supervisord",
+        }
+    }
+}
+
+/// A note about one app's setting that didn't map cleanly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportNote {
+    pub app: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for ImportNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.app, self.field, self.message)
+    }
+}
+
+/// Apps read from another process manager's config
+#[derive(Debug, Default)]
+pub struct Import {
+    pub apps: Vec<App>,
+    /// Settings that weren't imported, or were imported with a caveat
+    pub notes: Vec<ImportNote>,
+}
+
+impl Import {
+    /// Summary of the import, listing every note
+    pub fn report(&self) -> String {
+        let mut report = format!("Imported {} app(s)", self.apps.len());
+        if !self.notes.is_empty() {
+            report.push_str(&format!(
+                ", {} setting(s) need attention:",
+                self.notes.len()
+            ));
+            for note in &self.notes {
+                report.push_str(&format!("\n  {}", note));
+            }
+        }
+        report
+    }
+}
+
+/// Read a config file (or directory, where the source has them) of
+/// `source`
+pub fn read(source: ImportSource, path: &Path) -> Result<Import, String> {
+    match source {
+        ImportSource::Pm2 => {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+            let base_dir = std::path::absolute(path)
+                .ok()
+                .and_then(|path| path.parent().map(Path::to_path_buf))
+                .unwrap_or_default();
+            pm2::parse(&content, path, &base_dir)
+        }
+        ImportSource::Supervisord => supervisord::load(path),
+    }
+}
+
+/// All apps as one config in `format`: a single-app config for one app,
+/// otherwise a multi-app config with the apps in a project named after
+/// `source`
+pub fn render(source: ImportSource, apps: &[App], format: ConfigFormat) -> Result<String, String> {
+    match apps {
+        [app] => export::to_config(app, format),
+        apps => export::to_project_config(source.name(), apps, format),
+    }
+}
+
+/// Write each app to `<dir>/<name>.<ext>`. Nothing is written if one of the
+/// files already exists.
+pub fn write_configs(
+    apps: &[App],
+    dir: &Path,
+    format: ConfigFormat,
+) -> Result<Vec<PathBuf>, String> {
+    let extension = match format {
+        ConfigFormat::Json => "json",
+        ConfigFormat::Yaml => "yaml",
+        ConfigFormat::Toml => "toml",
+    };
+    let paths: Vec<PathBuf> = apps
+        .iter()
+        .map(|app| dir.join(format!("{}.{}", app.name, extension)))
+        .collect();
+    if let Some(existing) = paths.iter().find(|path| path.exists()) {
+        return Err(format!("'{}' already exists", existing.display()));
+    }
+
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("failed to create '{}': {}", dir.display(), e))?;
+    for (app, path) in apps.iter().zip(&paths) {
+        let content = export::to_config(app, format)?;
+        std::fs::write(path, content)
+            .map_err(|e| format!("failed to write '{}': {}", path.display(), e))?;
+    }
+    Ok(paths)
+}
+
+/// Split a command line into words, keeping quoted runs together. There
+/// is no shell, so nothing else is special.
+pub fn split_args(args: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+
+    for c in args.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config::{parse_as, AppConfig};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_loads_as_one_config() {
+        let apps: Vec<App> = ["api", "worker"]
+            .iter()
+            .map(|name| {
+                serde_json::from_value(serde_json::json!({
+                    "name": name,
+                    "script": "node",
+                    "env": {"NODE_ENV": "production"},
+                    "restart": {"policy": "always"}
+                }))
+                .unwrap()
+            })
+            .collect();
+
+        for format in [ConfigFormat::Json, ConfigFormat::Yaml, ConfigFormat::Toml] {
+            let content = render(ImportSource::Pm2, &apps, format).unwrap();
+            let config: AppConfig = parse_as(&content, format).unwrap();
+            let names: Vec<String> = config.get_apps().iter().map(App::qualified_name).collect();
+            assert_eq!(names, ["pm2/api", "pm2/worker"], "{}", content);
+
+            let content = render(ImportSource::Pm2, &apps[..1], format).unwrap();
+            let config: AppConfig = parse_as(&content, format).unwrap();
+            assert_eq!(config.get_apps()[0].qualified_name(), "api", "{}", content);
+        }
+    }
+
+    #[test]
+    fn test_write_configs() {
+        let dir = TempDir::new().unwrap();
+        let apps: Vec<App> = ["api", "worker"]
+            .iter()
+            .map(|name| {
+                serde_json::from_value(serde_json::json!({"name": name, "script": "node"})).unwrap()
+            })
+            .collect();
+
+        let paths = write_configs(&apps, dir.path(), ConfigFormat::Yaml).unwrap();
+        assert_eq!(
+            paths,
+            [dir.path().join("api.yaml"), dir.path().join("worker.yaml")]
+        );
+        let config = AppConfig::from_file(&paths[1]).unwrap();
+        assert_eq!(config.get_apps()[0].name, "worker");
+
+        let error = write_configs(&apps, dir.path(), ConfigFormat::Yaml).unwrap_err();
+        assert!(error.contains("api.yaml' already exists"), "{}", error);
+    }
+}
@@ -0,0 +1,518 @@
+//! PM2 importer
+//!
+//! Reads ecosystem files (`{"apps": [...]}` as JSON or YAML) and
+//! `pm2 save` dumps (`~/.pm2/dump.pm2`, a JSON array of processes). Relative
+//! paths are pinned to the file's directory, since the written configs may
+//! live somewhere else.
+
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+use crate::config::import::{split_args, Import, ImportNote};
+use crate::config::read_config::{
+    format_size, parse_as, parse_size_str, App, ConfigFormat, ENV_PROFILE_PREFIX,
+};
+
+/// Runtime state saved in dumps. It describes the last run rather than the
+/// app, so it isn't reported.
+const DUMP_STATE: &[&str] = &[
+    "pm_id",
+    "status",
+    "created_at",
+    "pm_uptime",
+    "restart_time",
+    "unstable_restarts",
+    "prev_restart_delay",
+    "exit_code",
+    "pm_pid_path",
+    "unique_id",
+    "username",
+    "node_version",
+    "version",
+    "versioning",
+    "vizion",
+    "vizion_running",
+    "km_link",
+    "axm_actions",
+    "axm_monitor",
+    "axm_options",
+    "axm_dynamic",
+    "pmx",
+    "automation",
+    "treekill",
+    "windowsHide",
+    "kill_retry_time",
+    "instance_var",
+    "filter_env",
+    "namespace",
+    "autostart",
+];
+
+/// Interpreters PM2 picks from the script's extension
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("js", "node"),
+    ("cjs", "node"),
+    ("mjs", "node"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("sh", "bash"),
+    ("php", "php"),
+    ("pl", "perl"),
+];
+
+/// Parse an ecosystem file or dump
+pub fn parse(content: &str, path: &Path, base_dir: &Path) -> Result<Import, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if matches!(extension, "js" | "cjs" | "mjs") {
+        return Err(format!(
+            "JavaScript ecosystem files can't be read; convert it to JSON first:\n  \
+             node -e \"console.log(JSON.stringify(require('./{}')))\" > ecosystem.json",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+    }
+    let value: Value =
+        parse_as(content, ConfigFormat::from_path(path)).map_err(|e| e.to_string())?;
+
+    let (entries, dump) = match value {
+        Value::Array(processes) => (processes, true),
+        Value::Object(mut root) => match root.shift_remove("apps") {
+            Some(Value::Array(apps)) => (apps, false),
+            Some(app @ Value::Object(_)) => (vec![app], false),
+            _ => return Err("no `apps` list in the ecosystem file".to_string()),
+        },
+        _ => return Err("expected an ecosystem file or a PM2 dump".to_string()),
+    };
+
+    let mut import = Import::default();
+    for entry in entries {
+        let Value::Object(fields) = entry else {
+            return Err("every app must be an object".to_string());
+        };
+        let mut entry = Entry {
+            fields,
+            dump,
+            notes: Vec::new(),
+        };
+        let app = entry.build_app(base_dir)?;
+        import
+            .notes
+            .extend(entry.notes.into_iter().map(|(field, message)| ImportNote {
+                app: app.name.clone(),
+                field,
+                message,
+            }));
+        import.apps.push(app);
+    }
+    Ok(import)
+}
+
+/// One PM2 app. Fields are taken out as they are mapped; whatever is left
+/// at the end is reported.
+struct Entry {
+    fields: Map<String, Value>,
+    dump: bool,
+    notes: Vec<(String, String)>,
+}
+
+impl Entry {
+    /// Take the first of `keys` that is set, removing all of them
+    fn take(&mut self, keys: &[&str]) -> Option<Value> {
+        keys.iter()
+            .filter_map(|key| self.fields.shift_remove(*key))
+            .find(|value| !value.is_null())
+    }
+
+    fn take_string(&mut self, keys: &[&str]) -> Option<String> {
+        self.take(keys).map(|value| to_string(&value))
+    }
+
+    /// A list given as an array or as a space-separated string
+    fn take_list(&mut self, keys: &[&str]) -> Vec<String> {
+        match self.take(keys) {
+            Some(Value::Array(items)) => items.iter().map(to_string).collect(),
+            Some(Value::String(s)) => split_args(&s),
+            Some(value) => vec![to_string(&value)],
+            None => vec![],
+        }
+    }
+
+    fn note(&mut self, field: &str, message: impl Into<String>) {
+        self.notes.push((field.to_string(), message.into()));
+    }
+
+    fn build_app(&mut self, base_dir: &Path) -> Result<App, String> {
+        // PM2 copies an app's settings into its env; the copies aren't env
+        let setting_keys: Vec<String> = self.fields.keys().cloned().collect();
+
+        let script = self
+            .take_string(&["pm_exec_path", "script"])
+            .ok_or("an app has no `script`")?;
+        let name = self.take_string(&["name"]).unwrap_or_else(|| {
+            Path::new(&script)
+                .file_stem()
+                .map_or(script.clone(), |stem| stem.to_string_lossy().to_string())
+        });
+        let cwd = match self.take_string(&["pm_cwd", "cwd"]) {
+            Some(cwd) => base_dir.join(cwd),
+            None => base_dir.to_path_buf(),
+        };
+
+        let mut app = json!({"name": name, "cwd": cwd});
+        self.command(&mut app, script, &cwd);
+        self.env(&mut app, &setting_keys);
+        self.instances(&mut app);
+        self.restart(&mut app);
+        self.stop(&mut app);
+        self.logs(&mut app, &cwd);
+
+        if let Some(schedule) = self.take_string(&["cron_restart"]) {
+            app["schedule"] = json!(schedule);
+            self.note(
+                "cron_restart",
+                "kept as `schedule`; bpm doesn't restart on a schedule yet",
+            );
+        }
+        // Dumps hold the limit in bytes, ecosystem files often as "300M"
+        if let Some(max_memory) = self.take_string(&["max_memory_restart"]) {
+            match parse_size_str(&max_memory) {
+                Ok(bytes) => app["max_memory_restart"] = json!(format_size(bytes)),
+                Err(e) => self.note("max_memory_restart", format!("not imported; {}", e)),
+            }
+        }
+        self.report_rest();
+
+        serde_json::from_value(app).map_err(|e| format!("{}: {}", name, e))
+    }
+
+    /// `script`, `args` and the interpreter PM2 would run the script with
+    fn command(&mut self, app: &mut Value, script: String, cwd: &Path) {
+        let interpreter = self.take_string(&["exec_interpreter", "interpreter"]);
+        let interpreter_args = self.take_list(&["node_args", "interpreter_args"]);
+        let args = self.take_list(&["args"]);
+
+        let extension = Path::new(&script)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        let interpreter = match interpreter.as_deref() {
+            Some("none") => None,
+            Some(interpreter) => Some(interpreter.to_string()),
+            None => INTERPRETERS
+                .iter()
+                .find(|(ext, _)| *ext == extension)
+                .map(|(_, interpreter)| interpreter.to_string()),
+        };
+
+        match interpreter {
+            Some(interpreter) => {
+                app["script"] = json!(interpreter);
+                let mut all_args = interpreter_args;
+                all_args.push(script);
+                all_args.extend(args);
+                app["args"] = json!(all_args);
+            }
+            None => {
+                if !interpreter_args.is_empty() {
+                    self.note(
+                        "interpreter_args",
+                        "not imported; the script has no interpreter",
+                    );
+                }
+                let script = if script.contains('/') {
+                    cwd.join(script).display().to_string()
+                } else {
+                    script
+                };
+                app["script"] = json!(script);
+                app["args"] = json!(args);
+            }
+        }
+    }
+
+    /// `env` and the `env_<profile>` blocks, which map to bpm env profiles
+    fn env(&mut self, app: &mut Value, setting_keys: &[String]) {
+        if let Some(Value::Object(env)) = self.take(&["env"]) {
+            let env: Map<String, Value> = env
+                .iter()
+                .filter(|(key, _)| {
+                    !self.dump
+                        || !(setting_keys.contains(key)
+                            || key.starts_with("PM2_")
+                            || key.starts_with("pm_"))
+                })
+                .filter(|(_, value)| !value.is_null() && !value.is_object())
+                .map(|(key, value)| (key.clone(), json!(to_string(value))))
+                .collect();
+            app["env"] = Value::Object(env);
+        }
+
+        let profiles: Vec<String> = self
+            .fields
+            .keys()
+            .filter(|key| key.starts_with(ENV_PROFILE_PREFIX))
+            .cloned()
+            .collect();
+        for key in profiles {
+            match self.fields.shift_remove(&key) {
+                Some(Value::Object(env)) => {
+                    let env: Map<String, Value> = env
+                        .iter()
+                        .map(|(key, value)| (key.clone(), json!(to_string(value))))
+                        .collect();
+                    app[key] = Value::Object(env);
+                }
+                Some(value) => {
+                    self.fields.insert(key, value);
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn instances(&mut self, app: &mut Value) {
+        let exec_mode = self.take_string(&["exec_mode"]).unwrap_or_default();
+        let Some(instances) = self.take(&["instances"]) else {
+            return;
+        };
+
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get() as i64);
+        let count = match &instances {
+            Value::Number(n) => n.as_i64().unwrap_or(1),
+            Value::String(s) if s == "max" => 0,
+            Value::String(s) => s.parse().unwrap_or(1),
+            _ => 1,
+        };
+        // 0 and "max" mean one per CPU, negative counts leave some CPUs free
+        let count = if count <= 0 {
+            (cpus + count).max(1)
+        } else {
+            count
+        };
+        if count <= 1 {
+            return;
+        }
+
+        app["instances"] = json!(count);
+        if !matches!(&instances, Value::Number(n) if n.as_i64() == Some(count)) {
+            self.note(
+                "instances",
+                format!("{} became {} (the CPUs on this host)", instances, count),
+            );
+        }
+        if exec_mode.starts_with("cluster") {
+            self.note(
+                "exec_mode",
+                "bpm runs instances as separate processes without a shared port; \
+                 give each its own port with `port_base`",
+            );
+        }
+    }
+
+    /// PM2 restarts on every exit unless `autorestart` is false
+    fn restart(&mut self, app: &mut Value) {
+        let policy = match self.take(&["autorestart"]) {
+            Some(Value::Bool(false)) => "never",
+            _ => "always",
+        };
+        app["restart"] = json!({ "policy": policy });
+
+        if let Some(max) = self.take(&["max_restarts"]).and_then(|v| v.as_i64()) {
+            app["restart"]["max_restarts"] = json!(max);
+        }
+        if let Some(delay) = self.take(&["restart_delay"]).and_then(|v| v.as_u64()) {
+            if delay > 0 {
+                app["restart"]["restart_delay"] = json!(format!("{}ms", delay));
+            }
+        }
+        // PM2 grows the delay by half each time up to 15s, and starts over
+        // after 30s up
+        if let Some(delay) = self
+            .take(&["exp_backoff_restart_delay"])
+            .and_then(|v| v.as_u64())
+        {
+            app["restart"]["restart_delay"] = json!(format!("{}ms", delay));
+            app["restart"]["backoff"] = json!({
+                "multiplier": 1.5,
+                "max_delay": "15s",
+                "jitter": 0,
+                "reset_after": "30s"
+            });
+        }
+    }
+
+    /// PM2 stops apps with SIGINT, then SIGKILL after `kill_timeout` ms
+    fn stop(&mut self, app: &mut Value) {
+        app["stop_signal"] = json!("SIGINT");
+        if let Some(timeout) = self.take(&["kill_timeout"]).and_then(|v| v.as_u64()) {
+            app["kill_timeout"] = json!(format!("{}ms", timeout));
+        }
+    }
+
+    fn logs(&mut self, app: &mut Value, cwd: &Path) {
+        let out = self.take_string(&["pm_out_log_path", "out_file", "output"]);
+        let error = self.take_string(&["pm_err_log_path", "error_file", "err_file", "error"]);
+        let path = |log: String| cwd.join(log).display().to_string();
+        match (out, error) {
+            (None, None) => {}
+            (out, error) => {
+                app["log"] = json!({
+                    "out": out.map_or("stdout".to_string(), path),
+                    "error": error.map_or("stderr".to_string(), path),
+                });
+            }
+        }
+    }
+
+    /// Note every setting left over. Unset, false and empty values are
+    /// skipped, as is dump state.
+    fn report_rest(&mut self) {
+        let fields = std::mem::take(&mut self.fields);
+        for (key, value) in fields {
+            let unset = match &value {
+                Value::Null | Value::Bool(false) => true,
+                Value::String(s) => s.is_empty(),
+                Value::Array(items) => items.is_empty(),
+                Value::Object(fields) => fields.is_empty(),
+                Value::Bool(true) | Value::Number(_) => false,
+            };
+            if unset || (self.dump && DUMP_STATE.contains(&key.as_str())) {
+                continue;
+            }
+            self.note(
+                &key,
+                format!("not imported ({}); bpm has no equivalent", value),
+            );
+        }
+    }
+}
+
+fn to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Where `pm2 save` writes its dump
+pub fn default_dump_path() -> Option<PathBuf> {
+    let pm2_home = std::env::var_os("PM2_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".pm2")))?;
+    Some(pm2_home.join("dump.pm2"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config::RestartPolicy;
+    use std::time::Duration;
+
+    fn parse_file(name: &str, content: &str) -> Import {
+        parse(content, Path::new(name), Path::new("/srv/eco")).unwrap()
+    }
+
+    fn fields(import: &Import) -> Vec<&str> {
+        import.notes.iter().map(|n| n.field.as_str()).collect()
+    }
+
+    #[test]
+    fn test_import_ecosystem() {
+        let import = parse_file(
+            "ecosystem.config.json",
+            r#"{"apps": [{
+                "name": "api",
+                "script": "server.js",
+                "args": "--port 3000 --label 'blue green'",
+                "cwd": "api",
+                "env": {"NODE_ENV": "development", "WORKERS": 2},
+                "env_production": {"NODE_ENV": "production"},
+                "instances": 4,
+                "exec_mode": "cluster",
+                "max_memory_restart": "300M",
+                "cron_restart": "0 3 * * *",
+                "out_file": "logs/out.log",
+                "error_file": "/var/log/api.err",
+                "watch": ["src"],
+                "merge_logs": false
+            }, {
+                "name": "tunnel",
+                "script": "ssh",
+                "args": ["-N", "db-host"],
+                "autorestart": false
+            }]}"#,
+        );
+
+        let api = &import.apps[0];
+        assert_eq!(api.script, "node");
+        assert_eq!(
+            api.args,
+            ["server.js", "--port", "3000", "--label", "blue green"]
+        );
+        assert_eq!(api.cwd.as_deref(), Some(Path::new("/srv/eco/api")));
+        assert_eq!(api.env["WORKERS"], "2");
+        assert_eq!(api.env_profiles["production"]["NODE_ENV"], "production");
+        assert_eq!(api.instances, Some(4));
+        assert_eq!(api.schedule.as_deref(), Some("0 3 * * *"));
+        assert_eq!(api.log.out, "/srv/eco/api/logs/out.log");
+        assert_eq!(api.log.error, "/var/log/api.err");
+        assert!(matches!(api.restart.policy, RestartPolicy::Always));
+        assert_eq!(fields(&import), ["exec_mode", "cron_restart", "watch"]);
+        assert_eq!(api.max_memory_restart, Some(300 << 20));
+
+        let tunnel = &import.apps[1];
+        assert_eq!(tunnel.script, "ssh");
+        assert_eq!(tunnel.args, ["-N", "db-host"]);
+        assert_eq!(tunnel.cwd.as_deref(), Some(Path::new("/srv/eco")));
+        assert!(matches!(tunnel.restart.policy, RestartPolicy::Never));
+    }
+
+    #[test]
+    fn test_import_dump() {
+        let import = parse_file(
+            "dump.pm2",
+            r#"[{
+                "name": "worker",
+                "pm_exec_path": "/srv/worker/main.py",
+                "pm_cwd": "/srv/worker",
+                "exec_interpreter": "python3",
+                "node_args": [],
+                "args": ["--queue", "mail"],
+                "env": {"QUEUE_URL": "amqp://q", "name": "worker", "PM2_HOME": "/root/.pm2"},
+                "instances": 1,
+                "exec_mode": "fork_mode",
+                "autorestart": true,
+                "max_restarts": 16,
+                "restart_delay": 0,
+                "exp_backoff_restart_delay": 100,
+                "pm_out_log_path": "/root/.pm2/logs/worker-out.log",
+                "pm_err_log_path": "/root/.pm2/logs/worker-error.log",
+                "pm_id": 3,
+                "status": "online",
+                "kill_timeout": 1600
+            }]"#,
+        );
+
+        let worker = &import.apps[0];
+        assert_eq!(worker.script, "python3");
+        assert_eq!(worker.args, ["/srv/worker/main.py", "--queue", "mail"]);
+        assert_eq!(worker.env.len(), 1);
+        assert_eq!(worker.instances, None);
+        assert_eq!(worker.restart.max_restarts, 16);
+        assert_eq!(worker.restart.delay(3, 0.0), Duration::from_millis(225));
+        assert_eq!(worker.log.out, "/root/.pm2/logs/worker-out.log");
+        assert!(import.notes.is_empty(), "{:?}", import.notes);
+        assert_eq!(worker.stop_sequence()[0].to_string(), "SIGINT 1600ms");
+    }
+
+    #[test]
+    fn test_reject_javascript_ecosystem() {
+        let error = parse(
+            "module.exports = {}",
+            Path::new("ecosystem.config.js"),
+            Path::new("/"),
+        )
+        .unwrap_err();
+        assert!(error.contains("convert it to JSON"), "{}", error);
+        assert_eq!(split_args(r#"a "b c" d''"#), ["a", "b c", "d"]);
+    }
+}
@@ -0,0 +1,523 @@
+//! supervisord importer
+//!
+//! Reads the `[program:x]` sections of a `supervisord.conf`, of the files
+//! its `[include]` section names, or of every `.conf`/`.ini` file in a
+//! directory such as `conf.d`. `%(here)s`, `%(program_name)s`,
+//! `%(process_num)d` and `%(ENV_X)s` are turned into their bpm equivalents.
+
+use indexmap::IndexMap;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::config::import::{split_args, Import, ImportNote};
+use crate::config::read_config::{parse_signal_str, parse_umask_str, App};
+use crate::config::secrets;
+
+const PROGRAM_PREFIX: &str = "program:";
+
+/// supervisord's defaults for settings bpm has nothing for; they aren't
+/// worth a note when left as is
+const DEFAULTS: &[(&str, &str)] = &[
+    ("autostart", "true"),
+    ("priority", "999"),
+    ("redirect_stderr", "false"),
+    ("stopasgroup", "false"),
+    ("killasgroup", "false"),
+    ("exitcodes", "0"),
+    ("startsecs", "1"),
+    ("numprocs_start", "0"),
+    ("stdout_logfile_maxbytes", "50MB"),
+    ("stdout_logfile_backups", "10"),
+    ("stderr_logfile_maxbytes", "50MB"),
+    ("stderr_logfile_backups", "10"),
+];
+
+/// An INI section's name and settings
+type Section = (String, IndexMap<String, String>);
+
+/// A `[program:x]` section and the directory of the file it came from
+struct Program {
+    name: String,
+    here: PathBuf,
+    settings: IndexMap<String, String>,
+}
+
+/// Read a supervisord config file or a directory of them
+pub fn load(path: &Path) -> Result<Import, String> {
+    let path = std::path::absolute(path).map_err(|e| e.to_string())?;
+    let mut files = if path.is_dir() {
+        config_files_in(&path)?
+    } else {
+        vec![path]
+    };
+
+    let mut programs = Vec::new();
+    let mut skipped = Vec::new();
+    // Files already read, so includes that match themselves or each other
+    // are only read once
+    let mut visited = HashSet::new();
+    let mut index = 0;
+    while index < files.len() {
+        let file = files[index].clone();
+        index += 1;
+        if !visited.insert(file.canonicalize().unwrap_or_else(|_| file.clone())) {
+            continue;
+        }
+        let content = std::fs::read_to_string(&file)
+            .map_err(|e| format!("failed to read '{}': {}", file.display(), e))?;
+        let here = file.parent().unwrap_or(Path::new("/")).to_path_buf();
+
+        for (section, settings) in
+            parse_ini(&content).map_err(|e| format!("{}: {}", file.display(), e))?
+        {
+            if section == "include" {
+                let patterns = settings.get("files").map_or("", String::as_str);
+                for pattern in patterns.split_whitespace() {
+                    files.extend(expand_include(&here.join(pattern))?);
+                }
+            } else if let Some(name) = section.strip_prefix(PROGRAM_PREFIX) {
+                programs.push(Program {
+                    name: name.trim().to_string(),
+                    here: here.clone(),
+                    settings,
+                });
+            } else if let Some((kind, name)) = section.split_once(':') {
+                skipped.push(ImportNote {
+                    app: name.trim().to_string(),
+                    field: format!("[{}]", kind),
+                    message: "not imported; only [program:x] sections are".to_string(),
+                });
+            }
+        }
+    }
+
+    let mut import = Import {
+        notes: skipped,
+        ..Import::default()
+    };
+    for program in programs {
+        let (app, notes) = convert(program)?;
+        import.apps.push(app);
+        import.notes.extend(notes);
+    }
+    Ok(import)
+}
+
+/// Build the app of one program, with notes on what didn't map
+fn convert(mut program: Program) -> Result<(App, Vec<ImportNote>), String> {
+    let name = program.name.clone();
+    let mut notes = Vec::new();
+    let mut note = |field: &str, message: String| {
+        notes.push(ImportNote {
+            app: name.clone(),
+            field: field.to_string(),
+            message,
+        })
+    };
+    let here = program.here.clone();
+    for (key, value) in &program.settings {
+        if key == "process_name" {
+            continue;
+        }
+        if let Some(spec) = padded_process_num(value) {
+            note(
+                key,
+                format!(
+                    "%(process_num){} became {{instance}}, which isn't zero-padded",
+                    spec
+                ),
+            );
+        }
+    }
+    // Env values aren't interpolated, so `%(ENV_X)s` is read now
+    let env = match program.settings.shift_remove("environment") {
+        Some(environment) => parse_environment(&expand(&environment, &name, &here, false))
+            .map_err(|e| format!("{}: {}", name, e))?,
+        None => IndexMap::new(),
+    };
+    let mut take = |key: &str| {
+        program
+            .settings
+            .shift_remove(key)
+            .map(|value| expand(&value, &name, &here, true))
+    };
+
+    let command = take("command").ok_or(format!("program '{}' has no command", name))?;
+    let mut words = split_args(&command).into_iter();
+    let script = words
+        .next()
+        .ok_or(format!("program '{}' has an empty command", name))?;
+    let mut app = json!({
+        "name": name,
+        "script": script,
+        "args": words.collect::<Vec<_>>(),
+        "env": env,
+    });
+
+    if let Some(directory) = take("directory") {
+        app["cwd"] = json!(here.join(directory));
+    }
+
+    let policy = match take("autorestart").as_deref() {
+        Some("false") => "never",
+        Some("unexpected") => "on-failure",
+        _ => "always",
+    };
+    app["restart"] = json!({ "policy": policy });
+    if let Some(retries) = take("startretries") {
+        match retries.parse::<i32>() {
+            Ok(retries) => app["restart"]["max_restarts"] = json!(retries),
+            Err(_) => note("startretries", format!("'{}' is not a number", retries)),
+        }
+    }
+
+    let mut log = serde_json::Map::new();
+    for (key, field) in [("stdout_logfile", "out"), ("stderr_logfile", "error")] {
+        match take(key).as_deref() {
+            None | Some("AUTO") => {}
+            Some("NONE") => {
+                log.insert(field.to_string(), json!("/dev/null"));
+            }
+            Some(logfile) => {
+                log.insert(field.to_string(), json!(here.join(logfile)));
+            }
+        }
+    }
+    if !log.is_empty() {
+        app["log"] = Value::Object(log);
+    }
+
+    let numprocs = take("numprocs").unwrap_or_default();
+    let process_name = take("process_name");
+    match numprocs.parse::<u32>() {
+        Ok(count) if count > 1 => {
+            app["instances"] = json!(count);
+            if process_name.is_some() {
+                note(
+                    "process_name",
+                    "not imported; bpm names instances <name>:<n>".to_string(),
+                );
+            }
+        }
+        Ok(_) => {}
+        Err(_) if numprocs.is_empty() => {}
+        Err(_) => note("numprocs", format!("'{}' is not a number", numprocs)),
+    }
+
+    if let Some(signal) = take("stopsignal") {
+        match parse_signal_str(&signal) {
+            Ok(signal) => app["stop_signal"] = json!(signal.as_str()),
+            Err(e) => note("stopsignal", e),
+        }
+    }
+    if let Some(seconds) = take("stopwaitsecs") {
+        match seconds.parse::<u64>() {
+            Ok(seconds) => app["kill_timeout"] = json!(format!("{}s", seconds)),
+            Err(_) => note("stopwaitsecs", format!("'{}' is not a number", seconds)),
+        }
+    }
+
+    if let Some(user) = take("user") {
+        app["user"] = json!(user);
+    }
+    if let Some(umask) = take("umask") {
+        match parse_umask_str(&umask) {
+            Ok(_) => app["umask"] = json!(umask),
+            Err(e) => note("umask", e),
+        }
+    }
+
+    let unsupported = [("startsecs", "bpm has no start grace period")];
+    for (key, reason) in unsupported {
+        if let Some(value) = take(key) {
+            if !is_default(key, &value) {
+                note(key, format!("not imported ({}); {}", value, reason));
+            }
+        }
+    }
+    for (key, value) in std::mem::take(&mut program.settings) {
+        if !is_default(&key, &value) {
+            note(
+                &key,
+                format!("not imported ({}); bpm has no equivalent", value),
+            );
+        }
+    }
+
+    let app = serde_json::from_value(app).map_err(|e| format!("{}: {}", name, e))?;
+    Ok((app, notes))
+}
+
+fn is_default(key: &str, value: &str) -> bool {
+    DEFAULTS
+        .iter()
+        .any(|(k, v)| *k == key && v.eq_ignore_ascii_case(value))
+}
+
+/// Turn supervisord's `%(name)s` expansions into bpm's. For fields bpm
+/// interpolates, `$` is escaped first, since supervisord passes it through
+/// untouched, and `%(ENV_X)s` becomes `${X}`; otherwise the variable's
+/// current value is used.
+fn expand(value: &str, program: &str, here: &Path, interpolated: bool) -> String {
+    let mut output = String::new();
+    let mut rest = if interpolated {
+        value.replace('$', "$$")
+    } else {
+        value.to_string()
+    };
+
+    while let Some(start) = rest.find("%(") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find(')') else {
+            break;
+        };
+        let key = &after[..end];
+        // The conversion type, like `s`, `d` or `02d`
+        let spec_len = after[end + 1..]
+            .find(|c: char| c.is_ascii_alphabetic())
+            .map_or(0, |pos| pos + 1);
+
+        let replacement = match key {
+            "program_name" | "group_name" => program.to_string(),
+            "here" => here.display().to_string(),
+            "process_num" => "{instance}".to_string(),
+            _ => match key.strip_prefix("ENV_") {
+                Some(var) if interpolated => format!("${{{}}}", var),
+                Some(var) => std::env::var(var).unwrap_or_default(),
+                None => format!("%({})", key),
+            },
+        };
+        output.push_str(&replacement);
+        rest = after[end + 1 + spec_len..].to_string();
+    }
+    output.push_str(&rest);
+    output
+}
+
+/// The conversion of the first zero-padded `%(process_num)`, like `02d`
+fn padded_process_num(value: &str) -> Option<&str> {
+    value
+        .match_indices("%(process_num)")
+        .find_map(|(pos, key)| {
+            let after = &value[pos + key.len()..];
+            let end = after.find(|c: char| c.is_ascii_alphabetic())? + 1;
+            after.starts_with('0').then(|| &after[..end])
+        })
+}
+
+/// Parse INI content into sections in file order. Values can continue on
+/// indented lines, and `;` after whitespace starts a comment.
+fn parse_ini(content: &str) -> Result<Vec<Section>, String> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut last_key: Option<String> = None;
+
+    for (index, raw) in content.lines().enumerate() {
+        let line = match raw.find(" ;").or_else(|| raw.find("\t;")) {
+            Some(pos) => &raw[..pos],
+            None => raw,
+        };
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            if let (Some(key), Some((_, settings))) = (&last_key, sections.last_mut()) {
+                if let Some(value) = settings.get_mut(key) {
+                    value.push(' ');
+                    value.push_str(trimmed);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(section) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            sections.push((section.trim().to_string(), IndexMap::new()));
+            last_key = None;
+            continue;
+        }
+
+        let (key, value) = trimmed
+            .split_once(['=', ':'])
+            .ok_or_else(|| format!("line {}: expected `key = value`", index + 1))?;
+        let (_, settings) = sections
+            .last_mut()
+            .ok_or_else(|| format!("line {}: setting outside of a section", index + 1))?;
+        let key = key.trim().to_string();
+        settings.insert(key.clone(), value.trim().to_string());
+        last_key = Some(key);
+    }
+    Ok(sections)
+}
+
+/// Parse `KEY="value",KEY2=value2`
+fn parse_environment(environment: &str) -> Result<IndexMap<String, String>, String> {
+    let mut env = IndexMap::new();
+    let mut rest = environment.trim();
+
+    while !rest.is_empty() {
+        let (key, after) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("environment entry '{}' has no '='", rest))?;
+        let after = after.trim_start();
+        let (value, after) = match after.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let end = after[1..]
+                    .find(quote)
+                    .ok_or_else(|| format!("unterminated quote in environment '{}'", rest))?;
+                (&after[1..=end], &after[end + 2..])
+            }
+            _ => after
+                .split_once(',')
+                .map_or((after, ""), |(value, after)| (value, after)),
+        };
+        env.insert(key.trim().to_string(), value.trim().to_string());
+        rest = after.trim_start().trim_start_matches(',').trim_start();
+    }
+    Ok(env)
+}
+
+/// Files an `[include]` pattern matches; `*` is only supported in the
+/// file name
+fn expand_include(pattern: &Path) -> Result<Vec<PathBuf>, String> {
+    let file_pattern = pattern
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !file_pattern.contains('*') {
+        return Ok(vec![pattern.to_path_buf()]);
+    }
+
+    let dir = pattern.parent().unwrap_or(Path::new("/"));
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| format!("failed to read '{}': {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.file_name().is_some_and(|name| {
+                    secrets::matches_pattern(&name.to_string_lossy(), &file_pattern)
+                })
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// The `.conf` and `.ini` files of a directory, in name order
+fn config_files_in(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for pattern in ["*.conf", "*.ini"] {
+        files.extend(expand_include(&dir.join(pattern))?);
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config::RestartPolicy;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_import_programs() {
+        let dir = TempDir::new().unwrap();
+        let conf_d = dir.path().join("conf.d");
+        std::fs::create_dir(&conf_d).unwrap();
+        std::fs::write(
+            dir.path().join("supervisord.conf"),
+            "[supervisord]\nlogfile=/tmp/supervisord.log\n\n[include]\nfiles = conf.d/*.ini\n",
+        )
+        .unwrap();
+        std::fs::write(
+            conf_d.join("worker.ini"),
+            r#"; queue workers
+[program:worker]
+command=/usr/bin/python3 worker.py --queue "mail out" --id %(process_num)02d
+directory=/srv/app
+environment=QUEUE_URL="amqp://q,1",HOME="%(ENV_HOME)s",
+    MODE=batch
+stdout_logfile=logs/%(program_name)s.log ; relative to here
+autorestart=unexpected
+startsecs=1
+startretries=5
+stopsignal=QUIT
+stopwaitsecs=30
+stderr_logfile=NONE
+user=www-data
+umask=022
+numprocs=3
+process_name=%(program_name)s_%(process_num)02d
+priority=10
+
+[eventlistener:memmon]
+command=memmon -a 200MB
+"#,
+        )
+        .unwrap();
+
+        let import = load(&dir.path().join("supervisord.conf")).unwrap();
+        let worker = &import.apps[0];
+        assert_eq!(worker.name, "worker");
+        assert_eq!(worker.script, "/usr/bin/python3");
+        assert_eq!(
+            worker.args,
+            ["worker.py", "--queue", "mail out", "--id", "{instance}"]
+        );
+        assert_eq!(worker.cwd.as_deref(), Some(Path::new("/srv/app")));
+        assert_eq!(worker.env["QUEUE_URL"], "amqp://q,1");
+        assert_eq!(
+            worker.env["HOME"],
+            std::env::var("HOME").unwrap_or_default()
+        );
+        assert_eq!(worker.env["MODE"], "batch");
+        assert!(matches!(worker.restart.policy, RestartPolicy::OnFailure));
+        assert_eq!(worker.restart.max_restarts, 5);
+        assert_eq!(
+            worker.log.out,
+            conf_d.join("logs/worker.log").display().to_string()
+        );
+        assert_eq!(worker.log.error, "/dev/null");
+        assert_eq!(worker.instances, Some(3));
+        assert_eq!(worker.stop_sequence()[0].to_string(), "SIGQUIT 30s");
+        assert_eq!(worker.user.as_deref(), Some("www-data"));
+        assert_eq!(worker.umask, Some(0o022));
+
+        let fields: Vec<&str> = import.notes.iter().map(|n| n.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            ["[eventlistener]", "command", "process_name", "priority"]
+        );
+        assert!(import.notes[1].message.contains("%(process_num)02d"));
+    }
+
+    #[test]
+    fn test_include_loops_are_read_once() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("a.conf"),
+            "[include]\nfiles = *.conf b.ini\n\n[program:a]\ncommand=/bin/a\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.ini"),
+            "[include]\nfiles = a.conf\n\n[program:b]\ncommand=/bin/b\n",
+        )
+        .unwrap();
+
+        let import = load(&dir.path().join("a.conf")).unwrap();
+        let names: Vec<&str> = import.apps.iter().map(|app| app.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_parse_environment() {
+        let env = parse_environment(r#"A="1,2", B=plain,C='x "y"'"#).unwrap();
+        assert_eq!(env["A"], "1,2");
+        assert_eq!(env["B"], "plain");
+        assert_eq!(env["C"], r#"x "y""#);
+        assert!(parse_environment("A=\"open").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Variable interpolation for app configs
+//!
+//! Expands `${VAR}`, `${VAR:-default}`, a leading `~` and the `{name}` and
+//! `{instance}` placeholders in config strings. `$$` produces a literal `$`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Values available while expanding a config string
+pub struct Context<'a> {
+    /// App name, substituted for `{name}`
+    pub name: &'a str,
+    /// Instance index, substituted for `{instance}`
+    pub instance: u32,
+    /// App environment, consulted before the daemon's own environment
+    pub env: &'a HashMap<String, String>,
+}
+
+impl Context<'_> {
+    fn lookup(&self, var: &str) -> Option<String> {
+        self.env
+            .get(var)
+            .cloned()
+            .or_else(|| std::env::var(var).ok())
+    }
+}
+
+/// Expand variables, placeholders and a leading `~` in a string
+pub fn expand(input: &str, ctx: &Context) -> Result<String, String> {
+    let input = expand_tilde(input);
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input.as_str();
+
+    while let Some(pos) = rest.find(['$', '{']) {
+        output.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("unterminated variable in '{}'", input))?;
+            let inner = &after[..end];
+            let (var, default) = match inner.split_once(":-") {
+                Some((var, default)) => (var, Some(default)),
+                None => (inner, None),
+            };
+            // Like the shell, `:-` also applies when the variable is empty
+            let value = match (ctx.lookup(var), default) {
+                (Some(value), None) => value,
+                (Some(value), Some(_)) if !value.is_empty() => value,
+                (_, Some(default)) => default.to_string(),
+                (None, None) => return Err(format!("undefined variable '{}'", var)),
+            };
+            output.push_str(&value);
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix("{name}") {
+            output.push_str(ctx.name);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{instance}") {
+            output.push_str(&ctx.instance.to_string());
+            rest = after;
+        } else {
+            // A lone `$` or `{` is kept as-is
+            output.push_str(&rest[..1]);
+            rest = &rest[1..];
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Expand a path, see [`expand`]
+pub fn expand_path(path: &Path, ctx: &Context) -> Result<PathBuf, String> {
+    expand(&path.to_string_lossy(), ctx).map(PathBuf::from)
+}
+
+/// Replace a leading `~` with the user's home directory
+pub fn expand_tilde(input: &str) -> String {
+    if input == "~" || input.starts_with("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return format!("{}{}", home.display(), &input[1..]);
+        }
+    }
+    input.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx(env: &HashMap<String, String>) -> Context<'_> {
+        Context {
+            name: "api",
+            instance: 2,
+            env,
+        }
+    }
+
+    #[test]
+    fn test_expand_variables() {
+        let mut env = HashMap::new();
+        env.insert("PORT".to_string(), "3000".to_string());
+        let ctx = ctx(&env);
+
+        assert_eq!(expand("--port=${PORT}", &ctx).unwrap(), "--port=3000");
+        assert_eq!(expand("${BPM_TEST_UNSET_VAR:-8080}", &ctx).unwrap(), "8080");
+        assert_eq!(expand("${PORT:-8080}", &ctx).unwrap(), "3000");
+        assert!(expand("${BPM_TEST_UNSET_VAR}", &ctx).is_err());
+        assert!(expand("${PORT", &ctx).is_err());
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let env = HashMap::new();
+        let ctx = ctx(&env);
+
+        assert_eq!(
+            expand("/var/log/{name}-{instance}.log", &ctx).unwrap(),
+            "/var/log/api-2.log"
+        );
+        // Other braces and lone dollars are left alone
+        assert_eq!(expand(r#"{"a": 1}"#, &ctx).unwrap(), r#"{"a": 1}"#);
+        assert_eq!(expand("cost: $5, $$HOME", &ctx).unwrap(), "cost: $5, $HOME");
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_tilde("~"), home.display().to_string());
+        assert_eq!(expand_tilde("~/logs"), format!("{}/logs", home.display()));
+        assert_eq!(expand_tilde("/tmp/~user"), "/tmp/~user");
+    }
+}
@@ -0,0 +1,322 @@
+//! Config layering
+//!
+//! Resolves `extends` (one config file layered over another) and the
+//! top-level `defaults` block that is deep-merged into every app.
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::config::error::ConfigError;
+use crate::config::read_config::{parse_as, AppConfig, ConfigFormat};
+use crate::config::secrets;
+
+/// Key naming the config file this one is layered over
+pub const EXTENDS_KEY: &str = "extends";
+/// Key holding settings merged into every app
+pub const DEFAULTS_KEY: &str = "defaults";
+
+/// Whether a parsed config uses `extends` or `defaults`
+pub fn is_layered(value: &Value) -> bool {
+    value.get(EXTENDS_KEY).is_some() || value.get(DEFAULTS_KEY).is_some()
+}
+
+/// Load a config file as a generic value with `extends` and `defaults`
+/// fully applied
+pub fn load(path: &Path) -> Result<Value, ConfigError> {
+    let mut value = load_extends(path, &mut Vec::new())?;
+    apply_defaults(&mut value).map_err(|message| ConfigError::Layering {
+        path: path.to_path_buf(),
+        message,
+    })?;
+    Ok(value)
+}
+
+/// Parse a config file as a generic value without applying any layering
+pub fn load_raw(path: &Path) -> Result<Value, ConfigError> {
+    let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_as(&content, ConfigFormat::from_path(path))
+}
+
+/// The files `path` is layered over through `extends`, nearest first. A
+/// base that can't be read ends the chain, and is included so it counts as
+/// changed once it appears.
+pub fn extends_chain(path: &Path) -> Vec<PathBuf> {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut seen = vec![canonical(path)];
+    let mut chain = Vec::new();
+    let mut current = path.to_path_buf();
+    while let Some(Value::String(base)) = load_raw(&current)
+        .ok()
+        .and_then(|mut value| value.get_mut(EXTENDS_KEY).map(Value::take))
+    {
+        current = current.parent().unwrap_or(Path::new(".")).join(base);
+        if seen.contains(&canonical(&current)) {
+            break;
+        }
+        seen.push(canonical(&current));
+        chain.push(current.clone());
+    }
+    chain
+}
+
+fn load_extends(path: &Path, chain: &mut Vec<PathBuf>) -> Result<Value, ConfigError> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if chain.contains(&canonical) {
+        return Err(ConfigError::Layering {
+            path: path.to_path_buf(),
+            message: "circular 'extends'".to_string(),
+        });
+    }
+    chain.push(canonical);
+
+    let mut value = load_raw(path)?;
+    let Some(extends) = value
+        .as_object_mut()
+        .and_then(|o| o.shift_remove(EXTENDS_KEY))
+    else {
+        return Ok(value);
+    };
+
+    let Value::String(base) = extends else {
+        return Err(ConfigError::Layering {
+            path: path.to_path_buf(),
+            message: "'extends' must be a file path".to_string(),
+        });
+    };
+
+    let base_path = path.parent().unwrap_or(Path::new(".")).join(base);
+    let mut merged = load_extends(&base_path, chain)?;
+    deep_merge(&mut merged, value);
+    Ok(merged)
+}
+
+/// Merge `defaults` into every app and remove the block
+pub fn apply_defaults(value: &mut Value) -> Result<(), String> {
+    let Some(top) = value.as_object_mut() else {
+        return Ok(());
+    };
+    let Some(defaults) = top.shift_remove(DEFAULTS_KEY) else {
+        return Ok(());
+    };
+
+    if !defaults.is_object() {
+        return Err("'defaults' must be an object".to_string());
+    }
+
+    let with_defaults = |app: &Value| {
+        let mut merged = defaults.clone();
+        deep_merge(&mut merged, app.clone());
+        merged
+    };
+
+    if top.contains_key("name") || top.contains_key("script") {
+        *value = with_defaults(value);
+    } else {
+        for apps in top.values_mut() {
+            if let Some(apps) = apps.as_array_mut() {
+                for app in apps.iter_mut() {
+                    *app = with_defaults(app);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Deep-merge `overlay` into `base`.
+///
+/// Objects are merged key by key. Lists of named objects (such as a
+/// project's apps) are merged by `name`, appending new entries. Anything
+/// else in `overlay` replaces the value in `base`.
+pub fn deep_merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(overlay))
+            if is_named_list(base) && is_named_list(&overlay) =>
+        {
+            for item in overlay {
+                let existing = base.iter_mut().find(|b| b.get("name") == item.get("name"));
+                match existing {
+                    Some(existing) => deep_merge(existing, item),
+                    None => base.push(item),
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn is_named_list(items: &[Value]) -> bool {
+    !items.is_empty()
+        && items
+            .iter()
+            .all(|item| item.as_object().is_some_and(|o| o.contains_key("name")))
+}
+
+/// Render one app from a config file exactly as it will be started, with
+/// `extends`, `defaults`, the env profile, env files and variables all
+/// applied. Secret env values are masked.
+pub fn show_app(config_path: &Path, name: &str, profile: Option<&str>) -> Result<String, String> {
+    // `api:1` selects one instance of an app with `instances`
+    let (app_name, instance) = name
+        .rsplit_once(':')
+        .and_then(|(app, index)| Some((app, index.parse::<u32>().ok()?)))
+        .unwrap_or((name, 0));
+
+    let config = AppConfig::from_file(&config_path.to_path_buf()).map_err(|e| e.to_string())?;
+    let app = config
+        .find_app(app_name)
+        .ok_or_else(|| format!("app '{}' not found in {}", app_name, config_path.display()))?;
+
+    let base_dir = config_path.parent().unwrap_or(Path::new("."));
+    let mut resolved = app
+        .with_profile(profile)
+        .resolve(base_dir, instance)
+        .map_err(|e| e.to_string())?;
+    resolved.env = secrets::mask_env(&resolved.env, &resolved.secrets, &resolved.secret_patterns);
+    serde_json::to_string_pretty(&resolved).map_err(|e| e.to_string())
+}
+
+/// Config files loaded when a directory is started, conf.d style: every
+/// JSON, YAML and TOML file in name order. Files starting with `_` or `.`
+/// are skipped so they can serve as `extends` bases.
+pub fn config_files_in(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            !name.starts_with('_')
+                && !name.starts_with('.')
+                && matches!(extension, "json" | "yaml" | "yml" | "toml")
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_deep_merge() {
+        let mut base = json!({
+            "cwd": "/srv",
+            "env": {"A": "1", "B": "2"},
+            "args": ["one", "two"]
+        });
+        deep_merge(
+            &mut base,
+            json!({"env": {"B": "3", "C": "4"}, "args": ["three"]}),
+        );
+
+        assert_eq!(
+            base,
+            json!({
+                "cwd": "/srv",
+                "env": {"A": "1", "B": "3", "C": "4"},
+                "args": ["three"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_defaults_merged_into_apps() {
+        let mut value = json!({
+            "defaults": {"cwd": "/srv/app", "env": {"NODE_ENV": "production"}},
+            "web": [
+                {"name": "api", "script": "node"},
+                {"name": "worker", "script": "node", "env": {"QUEUE": "jobs"}}
+            ]
+        });
+        apply_defaults(&mut value).unwrap();
+
+        assert!(value.get("defaults").is_none());
+        assert_eq!(value["web"][0]["cwd"], "/srv/app");
+        assert_eq!(
+            value["web"][1]["env"],
+            json!({"NODE_ENV": "production", "QUEUE": "jobs"})
+        );
+    }
+
+    #[test]
+    fn test_extends() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("_base.yaml"),
+            "defaults:\n  cwd: /srv\nweb:\n  - name: api\n    script: node\n    args: [a.js]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("prod.json"),
+            r#"{"extends": "./_base.yaml", "web": [{"name": "api", "args": ["b.js"]}, {"name": "worker", "script": "python"}]}"#,
+        )
+        .unwrap();
+
+        let value = load(&dir.path().join("prod.json")).unwrap();
+        assert_eq!(value["web"][0]["script"], "node");
+        assert_eq!(value["web"][0]["args"], json!(["b.js"]));
+        assert_eq!(value["web"][0]["cwd"], "/srv");
+        assert_eq!(value["web"][1]["cwd"], "/srv");
+
+        // Partials are skipped when loading a directory
+        let files = config_files_in(dir.path()).unwrap();
+        assert_eq!(files, vec![dir.path().join("prod.json")]);
+    }
+
+    #[test]
+    fn test_layering_keeps_declaration_order() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("_base.yaml"),
+            "defaults:\n  cwd: /srv\nworkers:\n  - name: mailer\n    script: python\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("prod.yaml"),
+            "extends: _base.yaml\nweb:\n  - name: zeta\n    script: node\n  - name: alpha\n    script: node\nworkers:\n  - name: billing\n    script: python\n",
+        )
+        .unwrap();
+
+        // Base projects come first, then the ones the overlay adds
+        let config = AppConfig::from_file(&dir.path().join("prod.yaml")).unwrap();
+        let names: Vec<String> = config
+            .get_apps()
+            .iter()
+            .map(|app| app.qualified_name())
+            .collect();
+        assert_eq!(
+            names,
+            ["workers/mailer", "workers/billing", "web/zeta", "web/alpha"]
+        );
+    }
+
+    #[test]
+    fn test_circular_extends() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.json"), r#"{"extends": "b.json"}"#).unwrap();
+        std::fs::write(dir.path().join("b.json"), r#"{"extends": "a.json"}"#).unwrap();
+
+        assert!(matches!(
+            load(&dir.path().join("a.json")),
+            Err(ConfigError::Layering { .. })
+        ));
+    }
+}
@@ -1,4 +1,13 @@
-pub(super) mod error;
+pub mod dotenv;
+pub mod error;
+pub mod export;
+pub mod import;
+pub mod interpolate;
+pub mod merge;
+pub mod procfile;
 pub mod read_config;
+pub mod schema;
+pub mod secrets;
 pub mod startup;
 pub mod state;
+pub mod validate;
@@ -0,0 +1,232 @@
+//! Procfile support
+//!
+//! A `Procfile` (or `Procfile.<suffix>`) is read as a project named after
+//! its directory, with one app per `<process>: <command>` line. Commands run
+//! through `sh -c`, the directory's `.env` is loaded into every app, and,
+//! like foreman, each process type gets its own block of ports starting at
+//! 5000 (or `PORT` from `.env`), 100 apart. Every app uses `instances` so a
+//! formation can run several copies.
+
+use indexmap::IndexMap;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::config::dotenv;
+use crate::config::error::ConfigError;
+use crate::config::read_config::{App, AppConfig};
+
+/// Port of the first process type when `.env` doesn't set `PORT`
+const DEFAULT_BASE_PORT: u16 = 5000;
+/// Ports reserved for each process type's instances
+const PORT_STEP: u16 = 100;
+const ENV_FILE: &str = ".env";
+
+/// Whether a path names a Procfile
+pub fn is_procfile(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name == "Procfile" || name.starts_with("Procfile."))
+}
+
+/// Read a Procfile as a project named after its directory
+pub fn load(path: &Path) -> Result<AppConfig, ConfigError> {
+    let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let dir = std::path::absolute(path)
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+
+    let env_file = dir.join(ENV_FILE);
+    let base_port = if env_file.is_file() {
+        let env = dotenv::load(&env_file).map_err(|message| ConfigError::Procfile {
+            line: None,
+            message,
+        })?;
+        env.into_iter()
+            .find(|(key, _)| key == "PORT")
+            .and_then(|(_, port)| port.parse().ok())
+    } else {
+        None
+    };
+
+    parse(&content, &dir, base_port.unwrap_or(DEFAULT_BASE_PORT))
+}
+
+/// Parse Procfile content for a Procfile in `dir`
+pub fn parse(content: &str, dir: &Path, base_port: u16) -> Result<AppConfig, ConfigError> {
+    let project = dir.file_name().map_or("procfile".to_string(), |name| {
+        name.to_string_lossy().to_string()
+    });
+    let env_file: Vec<PathBuf> = if dir.join(ENV_FILE).is_file() {
+        vec![PathBuf::from(ENV_FILE)]
+    } else {
+        vec![]
+    };
+
+    let mut apps: Vec<App> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let error = |message: String| ConfigError::Procfile {
+            line: Some(index + 1),
+            message,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, command) = line
+            .split_once(':')
+            .ok_or_else(|| error("expected `<process>: <command>`".to_string()))?;
+        let (name, command) = (name.trim(), command.trim());
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(error(format!("invalid process name '{}'", name)));
+        }
+        if command.is_empty() {
+            return Err(error(format!("process '{}' has no command", name)));
+        }
+        if apps.iter().any(|app| app.name == name) {
+            return Err(error(format!("process '{}' is declared twice", name)));
+        }
+
+        let port_base = u16::try_from(apps.len())
+            .ok()
+            .and_then(|n| n.checked_mul(PORT_STEP))
+            .and_then(|offset| base_port.checked_add(offset))
+            .ok_or_else(|| error("ran out of ports for this process".to_string()))?;
+        // `$` is the shell's, not bpm's interpolation
+        let app = json!({
+            "name": name,
+            "script": "sh",
+            "args": ["-c", command.replace('$', "$$")],
+            "cwd": dir,
+            "env_file": env_file,
+            "instances": 1,
+            "port_base": port_base,
+        });
+        apps.push(serde_json::from_value(app).map_err(|e| error(e.to_string()))?);
+    }
+
+    if apps.is_empty() {
+        return Err(ConfigError::Procfile {
+            line: None,
+            message: "no processes declared".to_string(),
+        });
+    }
+    Ok(AppConfig::MultiApp(Box::new(IndexMap::from([(
+        project, apps,
+    )]))))
+}
+
+/// Instance counts per process type, foreman style: `web=2,worker=3`, with
+/// `all=<n>` for every type not listed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Formation {
+    all: Option<u32>,
+    counts: BTreeMap<String, u32>,
+}
+
+impl Formation {
+    /// Instance count for an app, if the formation sets one
+    pub fn count(&self, name: &str) -> Option<u32> {
+        self.counts.get(name).copied().or(self.all)
+    }
+
+    /// Process types the formation names that aren't in `names`
+    pub fn unknown<'a>(&'a self, names: &[&str]) -> Vec<&'a str> {
+        self.counts
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !names.contains(name))
+            .collect()
+    }
+}
+
+impl FromStr for Formation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut formation = Formation::default();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, count) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("formation entry '{}' is not <process>=<count>", entry))?;
+            let count: u32 = count
+                .trim()
+                .parse()
+                .map_err(|_| format!("formation count '{}' is not a number", count))?;
+            match name.trim() {
+                "all" => formation.all = Some(count),
+                name => {
+                    formation.counts.insert(name.to_string(), count);
+                }
+            }
+        }
+        Ok(formation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_procfile() {
+        let dir = TempDir::new().unwrap();
+        let shop = dir.path().join("shop");
+        std::fs::create_dir(&shop).unwrap();
+        std::fs::write(shop.join(".env"), "PORT=3000\nRAILS_ENV=production\n").unwrap();
+        std::fs::write(
+            shop.join("Procfile"),
+            "# processes\nweb: bundle exec puma -p $PORT\n\nworker:bundle exec sidekiq\n",
+        )
+        .unwrap();
+
+        let config = load(&shop.join("Procfile")).unwrap();
+        let apps = config.get_apps();
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps[0].qualified_name(), "shop/web");
+        assert_eq!(apps[0].script, "sh");
+        assert_eq!(apps[1].port_base, Some(3100));
+
+        let web = apps[0].resolve(&shop, 1).unwrap();
+        assert_eq!(web.args, ["-c", "bundle exec puma -p $PORT"]);
+        assert_eq!(web.env["PORT"], "3001");
+        assert_eq!(web.env["RAILS_ENV"], "production");
+        assert_eq!(web.process_name(), "shop/web:1");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let dir = Path::new("/srv/shop");
+        for (content, message) in [
+            ("web bundle exec puma", "line 1: expected"),
+            ("web: a\nweb: b", "line 2: process 'web' is declared twice"),
+            ("# nothing\n", "no processes declared"),
+            ("web.1: a", "invalid process name"),
+        ] {
+            let error = parse(content, dir, 5000).unwrap_err().to_string();
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_formation() {
+        let formation: Formation = "web=2, worker=0,all=1".parse().unwrap();
+        assert_eq!(formation.count("web"), Some(2));
+        assert_eq!(formation.count("worker"), Some(0));
+        assert_eq!(formation.count("clock"), Some(1));
+        assert_eq!(formation.unknown(&["web"]), ["worker"]);
+        assert_eq!("web=2".parse::<Formation>().unwrap().count("clock"), None);
+        assert!("web:2".parse::<Formation>().is_err());
+    }
+}
@@ -1,22 +1,69 @@
+use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 
+use nix::sys::signal::Signal;
 use serde::de::Deserializer;
 use std::time::Duration;
-#[derive(Debug, Deserialize, Serialize, Clone)]
+
+use crate::config::error::ConfigError;
+use crate::config::secrets::{self, SecretSource};
+use crate::config::{dotenv, interpolate, merge, procfile, schema};
+
+/// Config file formats, picked from the file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Detect the format from a path's extension.
+    /// Unknown or missing extensions are treated as JSON, as they always were.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigFormat::Json => write!(f, "JSON"),
+            ConfigFormat::Yaml => write!(f, "YAML"),
+            ConfigFormat::Toml => write!(f, "TOML"),
+        }
+    }
+}
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 #[serde(untagged)] //auto cohersion into what matches signature
 pub enum AppConfig {
     // Single app will look like { "name": "web-server", "script": "node server.js", ... }
     SingleApp(Box<App>),
 
     // Multi-app will look like { "my-project": [{ "name": "web-server", ... }, { "name": "worker", ... }] }
-    MultiApp(Box<HashMap<String, Vec<App>>>),
+    // Projects keep their declaration order
+    MultiApp(Box<IndexMap<String, Vec<App>>>),
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[schemars(deny_unknown_fields, transform = schema::app_transform)]
 pub struct App {
     pub name: String,
+    /// Project the app was declared under in a multi-app config
+    #[serde(skip)]
+    pub project: Option<String>,
     pub script: String,
     #[serde(default)]
     pub args: Vec<String>,
@@ -24,17 +71,173 @@ pub struct App {
     pub cwd: Option<PathBuf>,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Dotenv files merged into `env`; inline `env` entries win
+    #[serde(default)]
+    pub env_file: Vec<PathBuf>,
+    /// Dotenv files of secrets, read each time the process is spawned
+    #[serde(default)]
+    pub env_from_file: Vec<PathBuf>,
+    /// Secret env values read from a file or a command's stdout each time
+    /// the process is spawned
+    #[serde(default)]
+    pub secrets: BTreeMap<String, SecretSource>,
+    /// Env keys whose values are masked in `bpm status`, saved state and
+    /// logs, e.g. `*_TOKEN`
+    #[serde(default = "secrets::default_patterns")]
+    pub secret_patterns: Vec<String>,
+    /// `env_<profile>` blocks, keyed by profile name. The one picked with
+    /// `bpm start --env <profile>` is merged over `env`.
+    #[serde(
+        flatten,
+        deserialize_with = "deserialize_env_profiles",
+        serialize_with = "serialize_env_profiles"
+    )]
+    #[schemars(skip)]
+    pub env_profiles: BTreeMap<String, HashMap<String, String>>,
+    /// Profile applied with `with_profile`
+    #[serde(skip)]
+    pub profile: Option<String>,
     #[serde(default)]
     pub log: LogConfig,
     #[serde(default)]
     pub restart: RestartConfig,
+    /// When repeated crashes pause restarting
+    #[serde(default)]
+    pub crash_loop: CrashLoop,
+    /// How `bpm reload` hands over to a new process
+    #[serde(default)]
+    pub reload: ReloadConfig,
     #[serde(default)]
     pub healthcheck: Option<HealthCheck>,
     #[serde(default)]
     pub schedule: Option<String>, // this will use cron syntax
+    /// Run this many copies, registered as `name:0`, `name:1`, ...
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub instances: Option<u32>,
+    /// Give each instance `PORT = port_base + index`
+    #[serde(default)]
+    pub port_base: Option<u16>,
+    /// Instance index set by `resolve` for apps with `instances`
+    #[serde(skip)]
+    pub instance: Option<u32>,
+    /// Apps that must be up before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<Dependency>,
+    /// Signal sent to stop the process
+    #[serde(
+        default = "default_stop_signal",
+        deserialize_with = "parse_signal",
+        serialize_with = "serialize_signal"
+    )]
+    #[schemars(schema_with = "schema::signal")]
+    pub stop_signal: Signal,
+    /// How long to wait for the process to exit after `stop_signal` before
+    /// sending SIGKILL
+    #[serde(
+        default = "default_kill_timeout",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
+    pub kill_timeout: Duration,
+    /// Signals to send in turn, each with its own wait, before SIGKILL.
+    /// Replaces `stop_signal` and `kill_timeout`.
+    #[serde(default)]
+    pub stop_ladder: Vec<StopStep>,
+    /// User to run the app as, by name or uid. Switching users needs a
+    /// daemon running as root.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Group to run the app as, by name or gid; defaults to the user's
+    /// primary group
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Groups, by name or gid, added to those of `user`
+    #[serde(default)]
+    pub supplementary_groups: Vec<String>,
+    /// File mode creation mask, in octal like "027"
+    #[serde(
+        default,
+        deserialize_with = "parse_umask",
+        serialize_with = "serialize_umask"
+    )]
+    #[schemars(schema_with = "schema::umask")]
+    pub umask: Option<u32>,
+    /// Capabilities the app keeps as `user`, e.g. CAP_NET_BIND_SERVICE
+    #[serde(default, deserialize_with = "parse_capabilities")]
+    #[schemars(schema_with = "schema::capabilities")]
+    pub ambient_capabilities: Vec<String>,
+    /// Resource limits and scheduling settings applied before the app execs
+    #[serde(default)]
+    pub limits: Limits,
+    /// Limits of the app's cgroup, when the daemon manages cgroups
+    #[serde(default)]
+    pub cgroup: CgroupConfig,
+    /// Memory, e.g. "512M", above which the app is restarted
+    #[serde(
+        default,
+        deserialize_with = "parse_size",
+        serialize_with = "serialize_size"
+    )]
+    #[schemars(schema_with = "schema::size")]
+    pub max_memory_restart: Option<u64>,
+    /// CPU use above which the app is restarted once it lasts
+    #[serde(default)]
+    pub max_cpu: Option<MaxCpu>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// One step of stopping a process: send `signal`, then wait up to `timeout`
+/// for it to exit
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct StopStep {
+    #[serde(deserialize_with = "parse_signal", serialize_with = "serialize_signal")]
+    #[schemars(schema_with = "schema::signal")]
+    pub signal: Signal,
+    #[serde(
+        default = "default_kill_timeout",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
+    pub timeout: Duration,
+}
+
+/// An app that must be up before another one starts. Either just a name
+/// (wait until it is running) or `{name, condition, timeout}`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum Dependency {
+    Name(String),
+    #[schemars(extend("additionalProperties" = false))]
+    Detailed {
+        name: String,
+        #[serde(default)]
+        condition: DependencyCondition,
+        #[serde(
+            default = "default_dependency_timeout",
+            deserialize_with = "parse_duration",
+            serialize_with = "serialize_duration"
+        )]
+        #[schemars(schema_with = "schema::duration")]
+        timeout: Duration,
+    },
+}
+
+/// What a dependency has to reach before its dependents start
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum DependencyCondition {
+    /// The process was spawned and is running
+    #[default]
+    Running,
+    /// The process passes its healthcheck
+    Healthy,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct LogConfig {
     #[serde(default = "default_log_out")]
     pub out: String,
@@ -44,17 +247,274 @@ pub struct LogConfig {
     pub combined: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct RestartConfig {
     #[serde(default = "default_restart_policy")]
     pub policy: RestartPolicy,
+    /// Restarts in a row before giving up, -1 for unlimited. The count
+    /// starts over when the app is started by hand, and once it stays up
+    /// for `backoff.reset_after` (a minute without `backoff`).
     #[serde(default = "default_max_restarts")]
-    pub max_restarts: i32, // we use -1 for unlimited
-    #[serde(deserialize_with = "parse_duration")]
+    pub max_restarts: i32,
+    #[serde(
+        default = "default_restart_delay",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
     pub restart_delay: Duration,
+    /// Grow `restart_delay` with each restart in a row
+    #[serde(default)]
+    pub backoff: Option<Backoff>,
+    /// Exit codes that count as a clean exit, which `on-failure` doesn't
+    /// restart
+    #[serde(default = "default_success_exit_codes")]
+    pub success_exit_codes: Vec<i32>,
+    /// Exit codes that restart the app whatever the policy
+    #[serde(default)]
+    pub restart_on_exit_codes: Vec<i32>,
+}
+
+/// Exponential backoff between restarts: the nth restart in a row waits
+/// `restart_delay * multiplier^(n-1)`, at most `max_delay`, give or take
+/// `jitter`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Backoff {
+    #[serde(default = "default_backoff_multiplier")]
+    pub multiplier: f64,
+    #[serde(
+        default = "default_backoff_max_delay",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
+    pub max_delay: Duration,
+    /// Fraction of the delay to randomly add or take away, so apps that
+    /// crash together don't restart together
+    #[serde(default = "default_backoff_jitter")]
+    pub jitter: f64,
+    /// Uptime after which the app counts as stable and the delay starts
+    /// over
+    #[serde(
+        default = "default_backoff_reset_after",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
+    pub reset_after: Duration,
+}
+
+/// Crash-loop detection: an app that crashes `max_crashes` times within
+/// `window` isn't restarted again until `cooldown` is up
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct CrashLoop {
+    #[serde(
+        default = "default_crash_window",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
+    pub window: Duration,
+    #[serde(default = "default_max_crashes")]
+    #[schemars(range(min = 1))]
+    pub max_crashes: u32,
+    #[serde(
+        default = "default_crash_cooldown",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
+    pub cooldown: Duration,
+}
+
+/// Zero-downtime reloads: the new process has `ready_timeout` to become
+/// ready before the reload is rolled back, and apps with `instances` are
+/// replaced `batch_size` at a time
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct ReloadConfig {
+    #[serde(default = "default_reload_batch_size")]
+    #[schemars(range(min = 1))]
+    pub batch_size: u32,
+    #[serde(
+        default = "default_ready_timeout",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
+    pub ready_timeout: Duration,
+    /// Wait for `READY=1` on `NOTIFY_SOCKET`, as with systemd's
+    /// `Type=notify`, rather than for the healthcheck
+    #[serde(default)]
+    pub wait_ready: bool,
+}
+
+/// Resource limits, CPU and IO priority, OOM score and CPU affinity of an
+/// app. Unset fields are inherited from the daemon.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Limits {
+    /// Open files
+    #[serde(
+        default,
+        deserialize_with = "parse_rlimit",
+        serialize_with = "serialize_rlimit"
+    )]
+    #[schemars(schema_with = "schema::rlimit")]
+    pub nofile: Option<Rlimit>,
+    /// Processes and threads of the app's user
+    #[serde(
+        default,
+        deserialize_with = "parse_rlimit",
+        serialize_with = "serialize_rlimit"
+    )]
+    #[schemars(schema_with = "schema::rlimit")]
+    pub nproc: Option<Rlimit>,
+    /// Core dump size, in bytes or e.g. "512M"
+    #[serde(
+        default,
+        deserialize_with = "parse_rlimit",
+        serialize_with = "serialize_rlimit"
+    )]
+    #[schemars(schema_with = "schema::rlimit")]
+    pub core: Option<Rlimit>,
+    /// Address space, in bytes or e.g. "2G"
+    #[serde(
+        rename = "as",
+        default,
+        deserialize_with = "parse_rlimit",
+        serialize_with = "serialize_rlimit"
+    )]
+    #[schemars(schema_with = "schema::rlimit")]
+    pub address_space: Option<Rlimit>,
+    /// CPU time, in seconds
+    #[serde(
+        default,
+        deserialize_with = "parse_rlimit",
+        serialize_with = "serialize_rlimit"
+    )]
+    #[schemars(schema_with = "schema::rlimit")]
+    pub cpu: Option<Rlimit>,
+    /// Scheduling priority, from -20 (highest) to 19 (lowest)
+    #[serde(default)]
+    #[schemars(range(min = -20, max = 19))]
+    pub nice: Option<i32>,
+    #[serde(default)]
+    pub ionice: Option<Ionice>,
+    /// How likely the kernel's OOM killer picks the app, from -1000
+    /// (never) to 1000
+    #[serde(default)]
+    #[schemars(range(min = -1000, max = 1000))]
+    pub oom_score_adj: Option<i32>,
+    /// CPUs the app may run on, as a list or a range list like "0-3,6"
+    #[serde(default, deserialize_with = "parse_cpu_affinity")]
+    #[schemars(schema_with = "schema::cpu_affinity")]
+    pub cpu_affinity: Option<Vec<usize>>,
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    /// The resource limits by their names in config files
+    pub fn rlimits(&self) -> [(&'static str, Option<Rlimit>); 5] {
+        [
+            ("nofile", self.nofile),
+            ("nproc", self.nproc),
+            ("core", self.core),
+            ("as", self.address_space),
+            ("cpu", self.cpu),
+        ]
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// A CPU threshold: the app is restarted once it has used more than
+/// `percent` of a CPU for `for` in a row
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct MaxCpu {
+    /// Percent of one CPU; above 100 for apps using several
+    pub percent: f32,
+    #[serde(
+        rename = "for",
+        default = "default_max_cpu_for",
+        deserialize_with = "parse_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "schema::duration")]
+    pub sustained: Duration,
+}
+
+/// cgroup v2 controls of an app. They cover every process of the app
+/// together, and are written to the control file of the same name.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct CgroupConfig {
+    /// Memory above which the app is OOM-killed, e.g. "512M"
+    #[serde(
+        default,
+        deserialize_with = "parse_size",
+        serialize_with = "serialize_size"
+    )]
+    #[schemars(schema_with = "schema::size")]
+    pub memory_max: Option<u64>,
+    /// Memory above which the app is throttled and its memory reclaimed
+    #[serde(
+        default,
+        deserialize_with = "parse_size",
+        serialize_with = "serialize_size"
+    )]
+    #[schemars(schema_with = "schema::size")]
+    pub memory_high: Option<u64>,
+    /// CPUs worth of time the app may use, e.g. 1.5
+    #[serde(default)]
+    pub cpu_max: Option<f64>,
+    /// Processes and threads
+    #[serde(default)]
+    pub pids_max: Option<u64>,
+    /// IO weight relative to other cgroups, from 1 to 10000 (default 100)
+    #[serde(default)]
+    #[schemars(range(min = 1, max = 10000))]
+    pub io_weight: Option<u16>,
+}
+
+impl CgroupConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == CgroupConfig::default()
+    }
+}
+
+/// A soft and hard resource limit; `None` is unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rlimit {
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+/// IO scheduling class and priority, as set by `ionice`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Ionice {
+    pub class: IoniceClass,
+    /// From 0 (highest) to 7; ignored by the idle class
+    #[serde(default = "default_ionice_level")]
+    #[schemars(range(max = 7))]
+    pub level: u8,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoniceClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub enum RestartPolicy {
     Always,
@@ -62,17 +522,21 @@ pub enum RestartPolicy {
     Never,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[schemars(deny_unknown_fields, transform = schema::healthcheck_transform)]
 pub struct HealthCheck {
     #[serde(rename = "type")]
     pub check_type: HealthCheckType,
     #[serde(default = "default_health_interval")]
+    #[schemars(schema_with = "schema::duration")]
     pub interval: String,
     #[serde(default = "default_health_timeout")]
+    #[schemars(schema_with = "schema::duration")]
     pub timeout: String,
     #[serde(default = "default_health_retries")]
     pub retries: u32,
     #[serde(default)]
+    #[schemars(schema_with = "schema::duration")]
     pub start_period: Option<String>,
 
     // HTTP specific
@@ -90,7 +554,7 @@ pub struct HealthCheck {
     pub port: Option<u16>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum HealthCheckType {
     Http,
@@ -110,9 +574,54 @@ fn default_restart_policy() -> RestartPolicy {
 fn default_max_restarts() -> i32 {
     -1
 }
+fn default_success_exit_codes() -> Vec<i32> {
+    vec![0]
+}
+fn default_dependency_timeout() -> Duration {
+    Duration::from_secs(60)
+}
 fn default_restart_delay() -> Duration {
     Duration::from_secs(5)
 }
+fn default_crash_window() -> Duration {
+    Duration::from_secs(60)
+}
+fn default_max_crashes() -> u32 {
+    5
+}
+fn default_crash_cooldown() -> Duration {
+    Duration::from_secs(600)
+}
+fn default_reload_batch_size() -> u32 {
+    1
+}
+fn default_ready_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+fn default_backoff_max_delay() -> Duration {
+    Duration::from_secs(300)
+}
+fn default_backoff_jitter() -> f64 {
+    0.1
+}
+fn default_backoff_reset_after() -> Duration {
+    Duration::from_secs(60)
+}
+fn default_max_cpu_for() -> Duration {
+    Duration::from_secs(60)
+}
+fn default_ionice_level() -> u8 {
+    4
+}
+fn default_stop_signal() -> Signal {
+    Signal::SIGTERM
+}
+fn default_kill_timeout() -> Duration {
+    Duration::from_secs(10)
+}
 fn default_health_interval() -> String {
     "30s".to_string()
 }
@@ -127,30 +636,695 @@ where
     D: Deserializer<'de>,
 {
     let s: String = Deserialize::deserialize(deserializer)?;
-    let duration = if s.ends_with("s") {
-        let secs = s
-            .trim_end_matches("s")
-            .parse::<u64>()
-            .map_err(serde::de::Error::custom)?;
-        Duration::from_secs(secs)
-    } else if s.ends_with("min") || s.ends_with("m") {
-        let mins = s
-            .trim_end_matches("min")
-            .trim_end_matches("m")
-            .parse::<u64>()
-            .map_err(serde::de::Error::custom)?;
-        Duration::from_secs(mins * 60)
-    } else if s.ends_with("hr") || s.ends_with("h") {
-        let hrs = s
-            .trim_end_matches("hr")
-            .trim_end_matches("h")
-            .parse::<u64>()
-            .map_err(serde::de::Error::custom)?;
-        Duration::from_secs(hrs * 3600)
+    parse_duration_str(&s).map_err(serde::de::Error::custom)
+}
+
+fn parse_signal<'de, D>(deserializer: D) -> Result<Signal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+    parse_signal_str(&s).map_err(serde::de::Error::custom)
+}
+
+fn serialize_signal<S>(signal: &Signal, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(signal.as_str())
+}
+
+/// Parse a signal name, with or without its `SIG` prefix: "SIGTERM" or
+/// "QUIT"
+pub fn parse_signal_str(s: &str) -> Result<Signal, String> {
+    let name = s.trim();
+    let name = if name.starts_with("SIG") {
+        name.to_string()
+    } else {
+        format!("SIG{}", name)
+    };
+    name.parse().map_err(|_| {
+        format!(
+            "unknown signal '{}', expected e.g. \"SIGTERM\" or \"QUIT\"",
+            s.trim()
+        )
+    })
+}
+
+/// Linux capabilities, indexed by number
+pub const CAPABILITIES: &[&str] = &[
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+/// Parse a capability name, with or without its `CAP_` prefix and in any
+/// case: "CAP_NET_BIND_SERVICE" or "net_bind_service". Returns its number.
+pub fn parse_capability_str(s: &str) -> Result<u32, String> {
+    let name = s.trim().to_uppercase();
+    let name = if name.starts_with("CAP_") {
+        name
     } else {
-        return Err(serde::de::Error::custom("Invalid duration format"));
+        format!("CAP_{}", name)
+    };
+    CAPABILITIES
+        .iter()
+        .position(|cap| *cap == name)
+        .map(|number| number as u32)
+        .ok_or_else(|| {
+            format!(
+                "unknown capability '{}', expected e.g. \"CAP_NET_BIND_SERVICE\"",
+                s.trim()
+            )
+        })
+}
+
+/// Capabilities by their full names
+fn parse_capabilities<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let names: Vec<String> = Deserialize::deserialize(deserializer)?;
+    names
+        .iter()
+        .map(|name| {
+            parse_capability_str(name).map(|number| CAPABILITIES[number as usize].to_string())
+        })
+        .collect::<Result<_, _>>()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Parse an octal umask such as "027" or "0027"
+pub fn parse_umask_str(s: &str) -> Result<u32, String> {
+    let digits = s.trim();
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mask| *mask <= 0o777)
+        .ok_or_else(|| format!("invalid umask '{}', expected octal like \"027\"", digits))
+}
+
+/// A umask as an octal string, or a number whose digits are read as octal,
+/// since YAML, JSON and TOML all drop the leading zero of 027
+fn parse_umask<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Umask {
+        Text(String),
+        Number(u32),
+    }
+    match Option::<Umask>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Umask::Text(s)) => parse_umask_str(&s).map(Some),
+        Some(Umask::Number(n)) => parse_umask_str(&n.to_string()).map(Some),
+    }
+    .map_err(serde::de::Error::custom)
+}
+
+fn serialize_umask<S>(umask: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match umask {
+        Some(mask) => serializer.serialize_str(&format!("{:04o}", mask)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Parse a size in bytes, optionally with a K, M, G or T suffix (powers of
+/// 1024): "65536", "512M" or "2GB"
+pub fn parse_size_str(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let invalid = || format!("invalid size '{}', expected e.g. \"512M\" or \"2G\"", s);
+
+    let value = number.parse::<u64>().map_err(|_| invalid())?;
+    let unit = unit.trim().to_uppercase();
+    let shift = match unit.strip_suffix('B').unwrap_or(&unit) {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(invalid()),
+    };
+    value.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+/// Format a size using the largest unit that represents it exactly
+pub fn format_size(bytes: u64) -> String {
+    for (shift, unit) in [(40, "T"), (30, "G"), (20, "M"), (10, "K")] {
+        if bytes != 0 && bytes.is_multiple_of(1 << shift) {
+            return format!("{}{}", bytes >> shift, unit);
+        }
+    }
+    bytes.to_string()
+}
+
+/// A size as a number of bytes or a string like "512M"
+fn parse_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    match Option::<Size>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::Text(s)) => parse_size_str(&s)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+fn serialize_size<S>(size: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match size {
+        Some(bytes) => serializer.serialize_str(&format_size(*bytes)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// One resource limit value: a number, a size like "512M", or "unlimited"
+fn parse_limit(value: &serde_json::Value) -> Result<Option<u64>, String> {
+    match value {
+        serde_json::Value::Number(n) => n
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| format!("invalid limit {}, expected a whole number", n)),
+        serde_json::Value::String(s) if matches!(s.trim(), "unlimited" | "infinity") => Ok(None),
+        serde_json::Value::String(s) => parse_size_str(s).map(Some),
+        other => Err(format!(
+            "invalid limit {}, expected a number, a size or \"unlimited\"",
+            other
+        )),
+    }
+}
+
+/// A resource limit: one value for both the soft and hard limit, or
+/// `{soft, hard}`
+pub fn parse_rlimit_value(value: &serde_json::Value) -> Result<Rlimit, String> {
+    let limit = match value.as_object() {
+        Some(fields) => {
+            if let Some(key) = fields
+                .keys()
+                .find(|key| !["soft", "hard"].contains(&key.as_str()))
+            {
+                return Err(format!("unknown field '{}', expected soft or hard", key));
+            }
+            let field = |name: &str| match fields.get(name) {
+                Some(value) => parse_limit(value),
+                None => Err(format!("missing '{}' limit", name)),
+            };
+            Rlimit {
+                soft: field("soft")?,
+                hard: field("hard")?,
+            }
+        }
+        None => {
+            let limit = parse_limit(value)?;
+            Rlimit {
+                soft: limit,
+                hard: limit,
+            }
+        }
+    };
+    // Unlimited is above any number
+    if limit.soft.unwrap_or(u64::MAX) > limit.hard.unwrap_or(u64::MAX) {
+        return Err("the soft limit is above the hard limit".to_string());
+    }
+    Ok(limit)
+}
+
+fn parse_rlimit<'de, D>(deserializer: D) -> Result<Option<Rlimit>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(value) => parse_rlimit_value(&value)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+fn serialize_rlimit<S>(limit: &Option<Rlimit>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let value = |limit: Option<u64>| match limit {
+        Some(n) => serde_json::json!(n),
+        None => serde_json::json!("unlimited"),
+    };
+    match limit {
+        None => serializer.serialize_none(),
+        Some(limit) if limit.soft == limit.hard => value(limit.soft).serialize(serializer),
+        Some(limit) => serde_json::json!({"soft": value(limit.soft), "hard": value(limit.hard)})
+            .serialize(serializer),
+    }
+}
+
+/// Parse a CPU list like "0-3,6", as taken by `taskset -c`
+pub fn parse_cpu_list_str(s: &str) -> Result<Vec<usize>, String> {
+    let invalid = || format!("invalid CPU list '{}', expected e.g. \"0-3,6\"", s.trim());
+    let mut cpus = Vec::new();
+    for part in s.split(',').map(str::trim) {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let first = first.trim().parse::<usize>().map_err(|_| invalid())?;
+        let last = last.trim().parse::<usize>().map_err(|_| invalid())?;
+        if first > last {
+            return Err(invalid());
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// CPUs as a list of numbers or a CPU list string
+fn parse_cpu_affinity<'de, D>(deserializer: D) -> Result<Option<Vec<usize>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Cpus {
+        Text(String),
+        List(Vec<usize>),
+    }
+    let cpus = match Option::<Cpus>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Cpus::Text(s)) => parse_cpu_list_str(&s).map_err(serde::de::Error::custom)?,
+        Some(Cpus::List(mut cpus)) => {
+            cpus.sort_unstable();
+            cpus.dedup();
+            cpus
+        }
+    };
+    if cpus.is_empty() {
+        return Err(serde::de::Error::custom(
+            "cpu_affinity needs at least one CPU",
+        ));
+    }
+    Ok(Some(cpus))
+}
+
+/// Prefix of the keys declaring env profiles, e.g. `env_production`
+pub const ENV_PROFILE_PREFIX: &str = "env_";
+
+/// Collect the `env_<profile>` keys left over after the named `App` fields
+fn deserialize_env_profiles<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<String, HashMap<String, String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rest: BTreeMap<String, serde_json::Value> = Deserialize::deserialize(deserializer)?;
+    rest.into_iter()
+        .filter_map(|(key, value)| {
+            let profile = key.strip_prefix(ENV_PROFILE_PREFIX)?.to_string();
+            Some(
+                serde_json::from_value(value)
+                    .map(|env| (profile, env))
+                    .map_err(|e| serde::de::Error::custom(format!("{}: {}", key, e))),
+            )
+        })
+        .collect()
+}
+
+fn serialize_env_profiles<S>(
+    profiles: &BTreeMap<String, HashMap<String, String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    use serde::ser::SerializeMap;
+
+    let mut map = serializer.serialize_map(Some(profiles.len()))?;
+    for (profile, env) in profiles {
+        map.serialize_entry(&format!("{}{}", ENV_PROFILE_PREFIX, profile), env)?;
+    }
+    map.end()
+}
+
+fn serialize_duration<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&format_duration(*duration))
+}
+
+/// Parse a duration like "500ms", "30s", "5m", "5min", "1h" or "1hr".
+/// This is the single duration grammar used by every config field.
+pub fn parse_duration_str(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let value = number.parse::<u64>().map_err(|_| {
+        format!(
+            "invalid duration '{}', expected e.g. \"30s\", \"5m\" or \"1h\"",
+            s
+        )
+    })?;
+
+    let secs = |per_unit: u64| {
+        value
+            .checked_mul(per_unit)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("duration '{}' is too large", s))
+    };
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "s" => Ok(Duration::from_secs(value)),
+        "m" | "min" => secs(60),
+        "h" | "hr" => secs(3600),
+        _ => Err(format!(
+            "invalid duration unit in '{}', expected one of ms, s, m, min, h, hr",
+            s
+        )),
+    }
+}
+
+/// Format a duration using the largest unit that represents it exactly
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if !millis.is_multiple_of(1000) {
+        format!("{}ms", millis)
+    } else if millis == 0 {
+        "0s".to_string()
+    } else {
+        let secs = duration.as_secs();
+        if secs.is_multiple_of(3600) {
+            format!("{}h", secs / 3600)
+        } else if secs.is_multiple_of(60) {
+            format!("{}m", secs / 60)
+        } else {
+            format!("{}s", secs)
+        }
+    }
+}
+
+impl Dependency {
+    pub fn name(&self) -> &str {
+        match self {
+            Dependency::Name(name) => name,
+            Dependency::Detailed { name, .. } => name,
+        }
+    }
+
+    pub fn condition(&self) -> DependencyCondition {
+        match self {
+            Dependency::Name(_) => DependencyCondition::Running,
+            Dependency::Detailed { condition, .. } => *condition,
+        }
+    }
+
+    /// How long dependents wait for the condition
+    pub fn timeout(&self) -> Duration {
+        match self {
+            Dependency::Name(_) => default_dependency_timeout(),
+            Dependency::Detailed { timeout, .. } => *timeout,
+        }
+    }
+
+    /// Registry name of the dependency. Plain names refer to apps in the
+    /// same project; `project/name` reaches into another one.
+    pub fn qualified_name(&self, project: Option<&str>) -> String {
+        match project {
+            Some(project) if !self.name().contains('/') => format!("{}/{}", project, self.name()),
+            _ => self.name().to_string(),
+        }
+    }
+
+    /// The same dependency with its name qualified for `project`
+    pub fn qualified(&self, project: Option<&str>) -> Dependency {
+        Dependency::Detailed {
+            name: self.qualified_name(project),
+            condition: self.condition(),
+            timeout: self.timeout(),
+        }
+    }
+}
+
+impl App {
+    /// Registry name of the app: `project/name` for apps declared under a
+    /// project, so two projects can both have a `worker`
+    pub fn qualified_name(&self) -> String {
+        match &self.project {
+            Some(project) => format!("{}/{}", project, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Number of copies to run
+    pub fn instance_count(&self) -> u32 {
+        self.instances.unwrap_or(1)
+    }
+
+    /// Signals sent in turn to stop the app; SIGKILL follows the last one
+    pub fn stop_sequence(&self) -> Vec<StopStep> {
+        if self.stop_ladder.is_empty() {
+            vec![StopStep {
+                signal: self.stop_signal,
+                timeout: self.kill_timeout,
+            }]
+        } else {
+            self.stop_ladder.clone()
+        }
+    }
+
+    /// Registry name of one running copy: the qualified name, suffixed with
+    /// `:<index>` for apps with `instances`
+    pub fn process_name(&self) -> String {
+        match self.instance {
+            Some(instance) => format!("{}:{}", self.qualified_name(), instance),
+            None => self.qualified_name(),
+        }
+    }
+
+    /// Apply an env profile: its `env_<profile>` block is merged over `env`.
+    /// Apps without that block only have the profile recorded.
+    pub fn with_profile(&self, profile: Option<&str>) -> App {
+        let mut app = self.clone();
+        if let Some(profile) = profile {
+            if let Some(env) = self.env_profiles.get(profile) {
+                app.env.extend(env.clone());
+            }
+            app.profile = Some(profile.to_string());
+        }
+        app
+    }
+
+    /// Resolve one instance of the app: merge `env_file` entries and expand
+    /// variables, placeholders and `~` in `script`, `args`, `cwd` and the log
+    /// paths.
+    ///
+    /// Environment precedence, lowest to highest: `env_file` entries in list
+    /// order, then the inline `env` map (including any applied profile),
+    /// then `BPM_INSTANCE_ID` (for apps with `instances`) and the `port_base`
+    /// derived `PORT`. Variables are looked up in the merged environment
+    /// first and in the daemon's environment second. Relative `env_file`
+    /// paths are resolved against `base_dir`.
+    pub fn resolve(&self, base_dir: &Path, instance: u32) -> Result<App, ConfigError> {
+        let error = |message: String| ConfigError::Resolve {
+            app: self.name.clone(),
+            message,
+        };
+
+        let mut env = HashMap::new();
+        let mut env_files = Vec::new();
+        for env_file in &self.env_file {
+            let ctx = interpolate::Context {
+                name: &self.name,
+                instance,
+                env: &self.env,
+            };
+            let path = interpolate::expand_path(env_file, &ctx)
+                .map_err(|e| error(format!("env_file: {}", e)))?;
+            let path = base_dir.join(path);
+            for (key, value) in dotenv::load(&path).map_err(error)? {
+                env.insert(key, value);
+            }
+            env_files.push(path);
+        }
+        env.extend(self.env.clone());
+        if self.instances.is_some() {
+            env.insert("BPM_INSTANCE_ID".to_string(), instance.to_string());
+        }
+        if let Some(port_base) = self.port_base {
+            let port = u32::from(port_base) + instance;
+            if port > u32::from(u16::MAX) {
+                return Err(error(format!(
+                    "port_base {} + {} is not a valid port",
+                    port_base, instance
+                )));
+            }
+            env.insert("PORT".to_string(), port.to_string());
+        }
+
+        let ctx = interpolate::Context {
+            name: &self.name,
+            instance,
+            env: &env,
+        };
+        let expand = |field: &str, value: &str| {
+            interpolate::expand(value, &ctx).map_err(|e| error(format!("{}: {}", field, e)))
+        };
+
+        let mut app = self.clone();
+        app.script = expand("script", &self.script)?;
+        app.args = self
+            .args
+            .iter()
+            .map(|arg| expand("args", arg))
+            .collect::<Result<_, _>>()?;
+        app.cwd = match &self.cwd {
+            Some(cwd) => Some(PathBuf::from(expand("cwd", &cwd.to_string_lossy())?)),
+            None => None,
+        };
+        app.log.out = expand("log.out", &self.log.out)?;
+        app.log.error = expand("log.error", &self.log.error)?;
+        // Secret paths are read at spawn time, so pin them down now
+        let secret_path = |field: &str, path: &Path| {
+            interpolate::expand_path(path, &ctx)
+                .map(|path| base_dir.join(path))
+                .map_err(|e| error(format!("{}: {}", field, e)))
+        };
+        app.env_from_file = self
+            .env_from_file
+            .iter()
+            .map(|path| secret_path("env_from_file", path))
+            .collect::<Result<_, _>>()?;
+        for (key, source) in app.secrets.iter_mut() {
+            if let SecretSource::File(path) = source {
+                *path = secret_path(&format!("secrets.{}", key), path)?;
+            }
+        }
+        app.env = env;
+        // Pinned down too, so edits to them can be noticed
+        app.env_file = env_files;
+        app.instance = self.instances.map(|_| instance);
+
+        Ok(app)
+    }
+}
+
+/// Deserialize config content in the given format, reporting the location
+/// of syntax and type errors
+pub fn parse_as<T: serde::de::DeserializeOwned>(
+    content: &str,
+    format: ConfigFormat,
+) -> Result<T, ConfigError> {
+    match format {
+        ConfigFormat::Json => serde_json::from_str(content).map_err(|e| {
+            let (line, column) = (e.line(), e.column());
+            parse_error(format, Some(line), Some(column), &e.to_string())
+        }),
+        ConfigFormat::Yaml => serde_yaml::from_str(content).map_err(|e| {
+            let location = e.location();
+            parse_error(
+                format,
+                location.as_ref().map(|l| l.line()),
+                location.as_ref().map(|l| l.column()),
+                &e.to_string(),
+            )
+        }),
+        ConfigFormat::Toml => toml::from_str(content).map_err(|e| {
+            let (line, column) = match e.span() {
+                Some(span) => {
+                    let (line, column) = line_column(content, span.start);
+                    (Some(line), Some(column))
+                }
+                None => (None, None),
+            };
+            parse_error(format, line, column, e.message())
+        }),
+    }
+}
+
+/// Build a parse error, dropping the "at line X column Y" suffix that
+/// serde_json and serde_yaml append, since we report the location ourselves
+fn parse_error(
+    format: ConfigFormat,
+    line: Option<usize>,
+    column: Option<usize>,
+    message: &str,
+) -> ConfigError {
+    let message = match (line, column) {
+        (Some(line), Some(column)) => message
+            .trim_end_matches(&format!(" at line {} column {}", line, column))
+            .to_string(),
+        _ => message.to_string(),
     };
-    Ok(duration)
+
+    ConfigError::Parse {
+        format,
+        line,
+        column,
+        message,
+    }
+}
+
+/// Convert a byte offset into a 1-based line and column
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map(|newline| before[newline + 1..].chars().count())
+        .unwrap_or_else(|| before.chars().count())
+        + 1;
+    (line, column)
 }
 
 impl Default for LogConfig {
@@ -163,36 +1337,163 @@ impl Default for LogConfig {
     }
 }
 
+impl Default for StopStep {
+    fn default() -> Self {
+        Self {
+            signal: default_stop_signal(),
+            timeout: default_kill_timeout(),
+        }
+    }
+}
+
+impl std::fmt::Display for StopStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.signal, format_duration(self.timeout))
+    }
+}
+
+impl std::fmt::Display for MaxCpu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}% for {}",
+            self.percent,
+            format_duration(self.sustained)
+        )
+    }
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: default_reload_batch_size(),
+            ready_timeout: default_ready_timeout(),
+            wait_ready: false,
+        }
+    }
+}
+
+impl Default for CrashLoop {
+    fn default() -> Self {
+        Self {
+            window: default_crash_window(),
+            max_crashes: default_max_crashes(),
+            cooldown: default_crash_cooldown(),
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            multiplier: default_backoff_multiplier(),
+            max_delay: default_backoff_max_delay(),
+            jitter: default_backoff_jitter(),
+            reset_after: default_backoff_reset_after(),
+        }
+    }
+}
+
+impl RestartConfig {
+    /// How long to wait before restart number `attempt` in a row (counting
+    /// from 1). `noise` in [0, 1) picks the jitter.
+    pub fn delay(&self, attempt: u32, noise: f64) -> Duration {
+        let Some(backoff) = &self.backoff else {
+            return self.restart_delay;
+        };
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.restart_delay.as_secs_f64() * backoff.multiplier.powi(exponent);
+        let delay = delay.min(backoff.max_delay.as_secs_f64());
+        let jittered = delay * (1.0 + backoff.jitter * (2.0 * noise - 1.0));
+        Duration::from_secs_f64(jittered.max(0.0))
+    }
+
+    /// Uptime after which the app counts as stable and its restarts in a
+    /// row start over
+    pub fn stable_after(&self) -> Duration {
+        self.backoff
+            .map_or_else(default_backoff_reset_after, |backoff| backoff.reset_after)
+    }
+}
+
 impl Default for RestartConfig {
     fn default() -> Self {
         Self {
             policy: default_restart_policy(),
             max_restarts: default_max_restarts(),
             restart_delay: default_restart_delay(),
+            backoff: None,
+            success_exit_codes: default_success_exit_codes(),
+            restart_on_exit_codes: vec![],
         }
     }
 }
 
 impl AppConfig {
     pub fn from_file(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = std::fs::read_to_string(path)?;
-        let config: AppConfig = serde_json::from_str(&content)?;
-        Ok(config)
+        if procfile::is_procfile(path) {
+            return Ok(procfile::load(path)?);
+        }
+
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.clone(),
+            source,
+        })?;
+        let format = ConfigFormat::from_path(path);
+
+        // Parse typed directly when possible so errors keep their location
+        let value: serde_json::Value = parse_as(&content, format)?;
+        if !merge::is_layered(&value) {
+            return Ok(Self::from_str(&content, format)?);
+        }
+
+        Ok(Self::from_value(merge::load(path)?)?)
+    }
+
+    /// Build a config from an already layered generic value
+    pub fn from_value(value: serde_json::Value) -> Result<Self, ConfigError> {
+        serde_json::from_value(value).map_err(|e| ConfigError::Parse {
+            format: ConfigFormat::Json,
+            line: None,
+            column: None,
+            message: e.to_string(),
+        })
     }
 
-    // Get all apps from this config, regardless of format
-    pub fn get_apps(&self) -> (Option<String>, Vec<App>) {
+    /// Parse config content in the given format
+    pub fn from_str(content: &str, format: ConfigFormat) -> Result<Self, ConfigError> {
+        parse_as(content, format)
+    }
+
+    /// Get all apps from this config, regardless of format, in declaration
+    /// order. Apps from a multi-app config have their `project` set.
+    pub fn get_apps(&self) -> Vec<App> {
         match self {
-            AppConfig::SingleApp(app) => (None, vec![*app.clone()]),
-            AppConfig::MultiApp(projects) => {
-                let mut apps = Vec::new();
-                for (_, project_apps) in (*projects).iter() {
-                    for app in project_apps {
-                        apps.push(app.clone());
-                    }
-                }
-                (Some(projects.keys().next().unwrap().to_owned()), apps)
-            }
+            AppConfig::SingleApp(app) => vec![*app.clone()],
+            AppConfig::MultiApp(projects) => projects
+                .iter()
+                .flat_map(|(project, apps)| {
+                    apps.iter().map(|app| App {
+                        project: Some(project.clone()),
+                        ..app.clone()
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Find an app by qualified name (`project/name`), or by bare name when
+    /// it is unambiguous
+    pub fn find_app(&self, name: &str) -> Option<App> {
+        let apps = self.get_apps();
+        if let Some(app) = apps.iter().find(|app| app.qualified_name() == name) {
+            return Some(app.clone());
+        }
+
+        let mut matches = apps.into_iter().filter(|app| app.name == name);
+        match (matches.next(), matches.next()) {
+            (Some(app), None) => Some(app),
+            _ => None,
         }
     }
 }
@@ -210,7 +1511,7 @@ mod tests {
         }"#;
 
         let config: AppConfig = serde_json::from_str(json).unwrap();
-        let (_, apps) = config.get_apps();
+        let apps = config.get_apps();
 
         assert_eq!(apps.len(), 1);
         assert_eq!(apps[0].name, "test-app");
@@ -228,10 +1529,11 @@ mod tests {
         }"#;
 
         let config: AppConfig = serde_json::from_str(json).unwrap();
-        let (project_name, apps) = config.get_apps();
+        let apps = config.get_apps();
 
-        assert_eq!(project_name, Some("my-project".to_string()));
         assert_eq!(apps.len(), 2);
+        assert_eq!(apps[0].project, Some("my-project".to_string()));
+        assert_eq!(apps[0].qualified_name(), "my-project/app1");
     }
 
     #[test]
@@ -251,7 +1553,7 @@ mod tests {
         }"#;
 
         let config: AppConfig = serde_json::from_str(json).unwrap();
-        let (_, apps) = config.get_apps();
+        let apps = config.get_apps();
 
         let hc = apps[0].healthcheck.as_ref().unwrap();
         assert!(matches!(hc.check_type, HealthCheckType::Tcp));
@@ -273,7 +1575,7 @@ mod tests {
         }"#;
 
         let config: AppConfig = serde_json::from_str(json).unwrap();
-        let (_, apps) = config.get_apps();
+        let apps = config.get_apps();
 
         assert!(matches!(apps[0].restart.policy, RestartPolicy::OnFailure));
         assert_eq!(apps[0].restart.max_restarts, 5);
@@ -284,7 +1586,7 @@ mod tests {
         let json = r#"{"name": "minimal", "script": "echo", "args": []}"#;
 
         let config: AppConfig = serde_json::from_str(json).unwrap();
-        let (_, apps) = config.get_apps();
+        let apps = config.get_apps();
 
         // Check defaults
         assert_eq!(apps[0].log.out, "stdout");
@@ -292,4 +1594,510 @@ mod tests {
         assert!(matches!(apps[0].restart.policy, RestartPolicy::OnFailure));
         assert_eq!(apps[0].restart.max_restarts, -1);
     }
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("app.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("app.yaml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("app.YML")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("app.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("app")),
+            ConfigFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_parse_yaml() {
+        let yaml = r#"
+# Comments are the main reason to use YAML
+name: test-app
+script: node
+args: [app.js]
+restart:
+  policy: always
+  restart_delay: 3s
+"#;
+
+        let config = AppConfig::from_str(yaml, ConfigFormat::Yaml).unwrap();
+        let apps = config.get_apps();
+
+        assert_eq!(apps[0].name, "test-app");
+        assert_eq!(apps[0].args, vec!["app.js"]);
+        assert!(matches!(apps[0].restart.policy, RestartPolicy::Always));
+        assert_eq!(apps[0].restart.restart_delay, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_parse_yaml_multi_app() {
+        let yaml = r#"
+my-project:
+  - name: app1
+    script: node
+  - name: app2
+    script: python
+"#;
+
+        let config = AppConfig::from_str(yaml, ConfigFormat::Yaml).unwrap();
+        let apps = config.get_apps();
+
+        assert_eq!(apps[0].project, Some("my-project".to_string()));
+        assert_eq!(apps.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let toml = r#"
+name = "test-app"
+script = "node"
+args = ["app.js"]
+
+[healthcheck]
+type = "tcp"
+port = 3000
+"#;
+
+        let config = AppConfig::from_str(toml, ConfigFormat::Toml).unwrap();
+        let apps = config.get_apps();
+
+        assert_eq!(apps[0].name, "test-app");
+        assert_eq!(apps[0].healthcheck.as_ref().unwrap().port, Some(3000));
+    }
+
+    #[test]
+    fn test_parse_toml_multi_app() {
+        let toml = r#"
+[[my-project]]
+name = "app1"
+script = "node"
+
+[[my-project]]
+name = "app2"
+script = "python"
+"#;
+
+        let config = AppConfig::from_str(toml, ConfigFormat::Toml).unwrap();
+        let apps = config.get_apps();
+
+        assert_eq!(apps.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_error_locations() {
+        let json = "{\n  \"name\": \"app\",\n  \"script\": node\n}";
+        match AppConfig::from_str(json, ConfigFormat::Json) {
+            Err(ConfigError::Parse { line, column, .. }) => {
+                assert_eq!(line, Some(3));
+                assert!(column.is_some());
+            }
+            other => panic!("Expected parse error, got {:?}", other),
+        }
+
+        let yaml = "name: app\nscript: node\nargs: [unclosed\n";
+        match AppConfig::from_str(yaml, ConfigFormat::Yaml) {
+            Err(ConfigError::Parse { line, column, .. }) => {
+                assert!(line.is_some());
+                assert!(column.is_some());
+            }
+            other => panic!("Expected parse error, got {:?}", other),
+        }
+
+        let toml = "name = \"app\"\nscript = node\n";
+        match AppConfig::from_str(toml, ConfigFormat::Toml) {
+            Err(ConfigError::Parse { line, column, .. }) => {
+                assert_eq!(line, Some(2));
+                assert_eq!(column, Some(10));
+            }
+            other => panic!("Expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_example_configs_parse() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("example/configs");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) == Some("md") {
+                continue;
+            }
+            if let Err(e) = AppConfig::from_file(&path) {
+                panic!("{} failed to parse: {}", path.display(), e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_env_profiles() {
+        let yaml = r#"
+name: api
+script: node
+env:
+  NODE_ENV: development
+  PORT: "3000"
+env_production:
+  NODE_ENV: production
+"#;
+        let config = AppConfig::from_str(yaml, ConfigFormat::Yaml).unwrap();
+        let app = &config.get_apps()[0];
+        assert_eq!(app.env_profiles.len(), 1);
+
+        let production = app.with_profile(Some("production"));
+        assert_eq!(production.env["NODE_ENV"], "production");
+        assert_eq!(production.env["PORT"], "3000");
+        assert_eq!(production.profile.as_deref(), Some("production"));
+
+        // Profiles survive a round trip under their original keys
+        let value = serde_json::to_value(app).unwrap();
+        assert_eq!(value["env_production"]["NODE_ENV"], "production");
+
+        let unknown = app.with_profile(Some("staging"));
+        assert_eq!(unknown.env["NODE_ENV"], "development");
+    }
+
+    #[test]
+    fn test_resolve_env_file_and_interpolation() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(".env"),
+            "PORT=4000\nLOG_DIR=/var/log/app\nNODE_ENV=development\n",
+        )
+        .unwrap();
+
+        let json = r#"{
+            "name": "api",
+            "script": "${BPM_TEST_UNSET_BIN:-node}",
+            "args": ["server.js", "--port", "${PORT}"],
+            "cwd": "~/app",
+            "env": {"NODE_ENV": "production"},
+            "env_file": [".env"],
+            "log": {"out": "${LOG_DIR}/{name}-{instance}.log"}
+        }"#;
+
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        let apps = config.get_apps();
+        let app = apps[0].resolve(dir.path(), 0).unwrap();
+
+        assert_eq!(app.script, "node");
+        assert_eq!(app.args, vec!["server.js", "--port", "4000"]);
+        assert_eq!(app.cwd, Some(dirs::home_dir().unwrap().join("app")));
+        assert_eq!(app.log.out, "/var/log/app/api-0.log");
+        // Inline env wins over env_file
+        assert_eq!(app.env["NODE_ENV"], "production");
+        assert_eq!(app.env["PORT"], "4000");
+    }
+
+    #[test]
+    fn test_resolve_instances() {
+        let json = r#"{
+            "name": "api",
+            "script": "node",
+            "args": ["--port", "${PORT}"],
+            "env": {"PORT": "80"},
+            "instances": 3,
+            "port_base": 3000
+        }"#;
+        let app: App = serde_json::from_str(json).unwrap();
+        assert_eq!(app.instance_count(), 3);
+
+        let second = app.resolve(Path::new("/tmp"), 1).unwrap();
+        assert_eq!(second.process_name(), "api:1");
+        assert_eq!(second.env["BPM_INSTANCE_ID"], "1");
+        assert_eq!(second.args, vec!["--port", "3001"]);
+
+        // Apps without `instances` keep their plain name
+        let json = r#"{"name": "api", "script": "node"}"#;
+        let app: App = serde_json::from_str(json).unwrap();
+        let resolved = app.resolve(Path::new("/tmp"), 0).unwrap();
+        assert_eq!(resolved.process_name(), "api");
+        assert!(!resolved.env.contains_key("BPM_INSTANCE_ID"));
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let json = r#"{"name": "api", "script": "${BPM_TEST_UNSET_BIN}"}"#;
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        let apps = config.get_apps();
+        assert!(matches!(
+            apps[0].resolve(Path::new("/tmp"), 0),
+            Err(ConfigError::Resolve { .. })
+        ));
+
+        let json = r#"{"name": "api", "script": "node", "env_file": ["/nonexistent/.env"]}"#;
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        let apps = config.get_apps();
+        assert!(apps[0].resolve(Path::new("/tmp"), 0).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_str() {
+        assert_eq!(parse_duration_str("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration_str("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration_str("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration_str("5min"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration_str("2hr"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration_str("10").is_err());
+        assert!(parse_duration_str("s").is_err());
+        assert!(parse_duration_str("5x").is_err());
+        assert_eq!(
+            parse_duration_str("18446744073709551615h"),
+            Err("duration '18446744073709551615h' is too large".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_duration_roundtrip() {
+        for s in ["250ms", "0s", "45s", "5m", "2h", "90s"] {
+            let duration = parse_duration_str(s).unwrap();
+            assert_eq!(parse_duration_str(&format_duration(duration)), Ok(duration));
+        }
+        assert_eq!(format_duration(Duration::from_secs(90)), "90s");
+        assert_eq!(format_duration(Duration::from_secs(120)), "2m");
+    }
+
+    #[test]
+    fn test_restart_delay_default() {
+        let json = r#"{"name": "app", "script": "node", "restart": {"policy": "always"}}"#;
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        let apps = config.get_apps();
+        assert_eq!(apps[0].restart.restart_delay, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let json = r#"{"name": "app", "script": "node", "restart": {
+            "restart_delay": "1s",
+            "backoff": {"max_delay": "10s", "jitter": 0.5}
+        }}"#;
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        let restart = &config.get_apps()[0].restart;
+        assert_eq!(restart.backoff.unwrap().multiplier, 2.0);
+        assert_eq!(
+            restart.backoff.unwrap().reset_after,
+            Duration::from_secs(60)
+        );
+
+        let secs = |attempt, noise| restart.delay(attempt, noise).as_secs_f64();
+        assert_eq!(secs(1, 0.5), 1.0);
+        assert_eq!(secs(2, 0.5), 2.0);
+        assert_eq!(secs(4, 0.5), 8.0);
+        assert_eq!(secs(5, 0.5), 10.0);
+        assert_eq!(secs(1000, 0.5), 10.0);
+        assert_eq!(secs(2, 0.0), 1.0);
+        assert_eq!(secs(5, 1.0), 15.0);
+
+        let plain = RestartConfig::default();
+        assert_eq!(plain.delay(7, 0.9), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_multiple_projects_keep_order() {
+        let json = r#"{
+            "zeta": [{"name": "worker", "script": "node"}],
+            "alpha": [
+                {"name": "worker", "script": "python"},
+                {"name": "api", "script": "python"}
+            ]
+        }"#;
+
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        let names: Vec<String> = config
+            .get_apps()
+            .iter()
+            .map(|app| app.qualified_name())
+            .collect();
+
+        assert_eq!(names, vec!["zeta/worker", "alpha/worker", "alpha/api"]);
+    }
+
+    #[test]
+    fn test_depends_on_forms() {
+        let yaml = r#"
+shop:
+  - name: worker
+    script: node
+    depends_on:
+      - api
+      - name: db
+        condition: healthy
+        timeout: 2m
+      - billing/ledger
+"#;
+        let config: AppConfig = serde_yaml::from_str(yaml).unwrap();
+        let worker = &config.get_apps()[0];
+        let deps = &worker.depends_on;
+
+        assert_eq!(deps[0], Dependency::Name("api".to_string()));
+        assert_eq!(deps[0].condition(), DependencyCondition::Running);
+        assert_eq!(deps[0].timeout(), Duration::from_secs(60));
+        assert_eq!(deps[1].condition(), DependencyCondition::Healthy);
+        assert_eq!(deps[1].timeout(), Duration::from_secs(120));
+
+        let project = worker.project.as_deref();
+        assert_eq!(deps[1].qualified_name(project), "shop/db");
+        assert_eq!(deps[2].qualified_name(project), "billing/ledger");
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(parse_size_str("65536"), Ok(65536));
+        assert_eq!(parse_size_str("512M"), Ok(512 << 20));
+        assert_eq!(parse_size_str("2gb"), Ok(2 << 30));
+        assert!(parse_size_str("2 lots").is_err());
+        assert_eq!(parse_cpu_list_str("4,0-2, 1"), Ok(vec![0, 1, 2, 4]));
+        assert!(parse_cpu_list_str("3-1").is_err());
+
+        let yaml = r#"
+name: worker
+script: node
+limits:
+  nofile: 65536
+  core: {soft: 0, hard: unlimited}
+  as: 2G
+  nice: 10
+  ionice: {class: best-effort}
+  cpu_affinity: [3, 1]
+"#;
+        let app: App = serde_yaml::from_str(yaml).unwrap();
+        let limits = &app.limits;
+        assert_eq!(
+            limits.nofile,
+            Some(Rlimit {
+                soft: Some(65536),
+                hard: Some(65536)
+            })
+        );
+        assert_eq!(
+            limits.core,
+            Some(Rlimit {
+                soft: Some(0),
+                hard: None
+            })
+        );
+        assert_eq!(limits.address_space.unwrap().hard, Some(2 << 30));
+        assert_eq!(limits.ionice.unwrap().level, 4);
+        assert_eq!(limits.cpu_affinity, Some(vec![1, 3]));
+
+        let value = serde_json::to_value(limits).unwrap();
+        assert_eq!(value["nofile"], 65536);
+        assert_eq!(
+            value["core"],
+            serde_json::json!({"soft": 0, "hard": "unlimited"})
+        );
+        let roundtrip: Limits = serde_json::from_value(value).unwrap();
+        assert_eq!(&roundtrip, limits);
+
+        assert_eq!(format_size(512 << 20), "512M");
+        assert_eq!(format_size(1000), "1000");
+        let yaml =
+            "{name: a, script: b, cgroup: {memory_max: 1G, memory_high: 900M, cpu_max: 1.5}}";
+        let app: App = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(app.cgroup.memory_max, Some(1 << 30));
+        assert_eq!(app.cgroup.cpu_max, Some(1.5));
+        let value = serde_json::to_value(&app.cgroup).unwrap();
+        assert_eq!(value["memory_high"], "900M");
+
+        let error =
+            serde_yaml::from_str::<App>("{name: a, script: b, limits: {nofile: {soft: 2}}}");
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .contains("missing 'hard' limit"));
+    }
+
+    #[test]
+    fn test_parse_reload() {
+        let app: App = serde_yaml::from_str("{name: a, script: b}").unwrap();
+        assert_eq!(app.reload, ReloadConfig::default());
+        assert_eq!(app.reload.ready_timeout, Duration::from_secs(30));
+
+        let yaml =
+            "{name: a, script: b, reload: {batch_size: 2, ready_timeout: 1m, wait_ready: true}}";
+        let app: App = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            app.reload,
+            ReloadConfig {
+                batch_size: 2,
+                ready_timeout: Duration::from_secs(60),
+                wait_ready: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_restart_thresholds() {
+        let yaml = r#"
+name: api
+script: node
+max_memory_restart: 512M
+max_cpu: {percent: 90, for: 5m}
+"#;
+        let app: App = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(app.max_memory_restart, Some(512 << 20));
+        let max_cpu = app.max_cpu.unwrap();
+        assert_eq!(max_cpu.percent, 90.0);
+        assert_eq!(max_cpu.sustained, Duration::from_secs(300));
+        let value = serde_json::to_value(&app).unwrap();
+        assert_eq!(value["max_memory_restart"], "512M");
+
+        let app: App =
+            serde_yaml::from_str("{name: a, script: b, max_cpu: {percent: 150}}").unwrap();
+        assert_eq!(app.max_cpu.unwrap().sustained, Duration::from_secs(60));
+        assert!(serde_yaml::from_str::<App>("{name: a, script: b, max_cpu: {for: 1m}}").is_err());
+    }
+
+    #[test]
+    fn test_stop_sequence() {
+        let app: App = serde_json::from_str(r#"{"name": "a", "script": "sh"}"#).unwrap();
+        assert_eq!(app.stop_sequence(), [StopStep::default()]);
+        assert_eq!(app.stop_sequence()[0].to_string(), "SIGTERM 10s");
+
+        let yaml = r#"
+name: nginx
+script: nginx
+stop_signal: QUIT
+kill_timeout: 30s
+"#;
+        let app: App = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(app.stop_sequence()[0].to_string(), "SIGQUIT 30s");
+
+        let yaml = r#"
+name: api
+script: java
+stop_signal: QUIT
+stop_ladder:
+  - signal: SIGINT
+    timeout: 5s
+  - signal: TERM
+"#;
+        let app: App = serde_yaml::from_str(yaml).unwrap();
+        let steps: Vec<String> = app.stop_sequence().iter().map(|s| s.to_string()).collect();
+        assert_eq!(steps, ["SIGINT 5s", "SIGTERM 10s"]);
+
+        assert_eq!(parse_signal_str(" HUP "), Ok(Signal::SIGHUP));
+        assert!(parse_signal_str("term")
+            .unwrap_err()
+            .contains("unknown signal"));
+    }
+
+    #[test]
+    fn test_single_app_has_no_project() {
+        let json = r#"{"name": "solo", "script": "node"}"#;
+        let config: AppConfig = serde_json::from_str(json).unwrap();
+        let apps = config.get_apps();
+
+        assert_eq!(apps[0].project, None);
+        assert_eq!(apps[0].qualified_name(), "solo");
+    }
 }
@@ -0,0 +1,337 @@
+//! JSON Schema for config files
+//!
+//! `bpm schema` prints a JSON Schema generated from the config types in
+//! `read_config`, so editors can autocomplete and check configs. The parts
+//! serde attributes don't describe are filled in here: the duration syntax,
+//! signal and capability names, resource limits, `env_<profile>` blocks, the
+//! fields each healthcheck type requires, and the `extends`/`defaults`
+//! layering keys.
+
+use nix::sys::signal::Signal;
+use schemars::{json_schema, schema_for, Schema, SchemaGenerator};
+use serde_json::{json, Value};
+
+use crate::config::merge::{DEFAULTS_KEY, EXTENDS_KEY};
+use crate::config::read_config::{AppConfig, CAPABILITIES, ENV_PROFILE_PREFIX};
+
+/// Durations accepted by `parse_duration_str`
+pub const DURATION_PATTERN: &str = r"^\s*[0-9]+(ms|s|m|min|h|hr)\s*$";
+
+/// Sizes accepted by `parse_size_str`
+pub const SIZE_PATTERN: &str = r"^\s*[0-9]+\s*([kKmMgGtT][bB]?|[bB])?\s*$";
+
+/// The JSON Schema of a config file
+pub fn generate() -> Schema {
+    let mut schema = schema_for!(AppConfig);
+    let defs = schema
+        .get_mut("$defs")
+        .and_then(Value::as_object_mut)
+        .expect("AppConfig schema has definitions");
+
+    // Apps in layered configs may leave out anything `extends` or
+    // `defaults` provide, and `defaults` itself is a partial app
+    let app = defs["App"].clone();
+    let mut app_override = app.clone();
+    app_override["required"] = json!(["name"]);
+    app_override["properties"][EXTENDS_KEY] = extends_schema();
+    app_override["properties"][DEFAULTS_KEY] = json!({"$ref": "#/$defs/AppDefaults"});
+    let mut app_defaults = app;
+    if let Some(fields) = app_defaults.as_object_mut() {
+        fields.remove("required");
+        fields.insert(
+            "description".to_string(),
+            json!("Settings merged into every app of the config"),
+        );
+    }
+    defs.insert("AppOverride".to_string(), app_override);
+    defs.insert("AppDefaults".to_string(), app_defaults);
+
+    let layered = json!({"anyOf": [{"required": [EXTENDS_KEY]}, {"required": [DEFAULTS_KEY]}]});
+    let projects = |app_ref: &str| {
+        json!({
+            "properties": {EXTENDS_KEY: true, DEFAULTS_KEY: true},
+            "additionalProperties": {
+                "description": "A project: the apps it runs, registered as `<project>/<app>`",
+                "type": "array",
+                "items": {"$ref": app_ref}
+            }
+        })
+    };
+
+    let root = schema.ensure_object();
+    root.remove("anyOf");
+    root.remove("description");
+    root.insert("title".to_string(), json!("bpm config"));
+    root.insert(
+        "description".to_string(),
+        json!("A single app, or projects mapping a project name to a list of apps"),
+    );
+    root.insert("type".to_string(), json!("object"));
+    root.insert(
+        "properties".to_string(),
+        json!({
+            EXTENDS_KEY: extends_schema(),
+            DEFAULTS_KEY: {"$ref": "#/$defs/AppDefaults"}
+        }),
+    );
+    root.insert(
+        "if".to_string(),
+        json!({"anyOf": [{"required": ["name"]}, {"required": ["script"]}]}),
+    );
+    root.insert(
+        "then".to_string(),
+        json!({
+            "if": layered,
+            "then": {"$ref": "#/$defs/AppOverride"},
+            "else": {"$ref": "#/$defs/App"}
+        }),
+    );
+    root.insert(
+        "else".to_string(),
+        json!({
+            "if": layered,
+            "then": projects("#/$defs/AppOverride"),
+            "else": projects("#/$defs/App")
+        }),
+    );
+
+    schema
+}
+
+fn extends_schema() -> Value {
+    json!({
+        "description": "Config file this one is layered over, relative to this file",
+        "type": "string"
+    })
+}
+
+/// Schema of a duration field
+pub fn duration(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "description": "A duration: a whole number followed by ms, s, m, min, h or hr",
+        "type": "string",
+        "pattern": DURATION_PATTERN,
+        "examples": ["500ms", "30s", "5m", "1h"]
+    })
+}
+
+/// Schema of a signal field
+pub fn signal(_: &mut SchemaGenerator) -> Schema {
+    let names: Vec<&str> = Signal::iterator()
+        .flat_map(|signal| {
+            let name = signal.as_str();
+            [name, name.trim_start_matches("SIG")]
+        })
+        .collect();
+    json_schema!({
+        "description": "A signal name, with or without its SIG prefix",
+        "type": "string",
+        "enum": names
+    })
+}
+
+/// Schema of the `umask` field
+pub fn umask(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "description": "A file mode creation mask in octal, e.g. \"027\"",
+        "type": ["string", "integer", "null"],
+        "pattern": "^\\s*[0-7]{1,4}\\s*$",
+        "examples": ["022", "027", "077"]
+    })
+}
+
+/// Schema of the `ambient_capabilities` field
+pub fn capabilities(_: &mut SchemaGenerator) -> Schema {
+    let names: Vec<&str> = CAPABILITIES
+        .iter()
+        .flat_map(|name| [*name, name.trim_start_matches("CAP_")])
+        .collect();
+    json_schema!({
+        "description": "Linux capabilities, with or without their CAP_ prefix",
+        "type": "array",
+        "items": {"type": "string", "enum": names}
+    })
+}
+
+/// Schema of a size field
+pub fn size(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "description": "A size in bytes, or a whole number followed by K, M, G or T",
+        "type": ["integer", "string", "null"],
+        "minimum": 0,
+        "pattern": SIZE_PATTERN,
+        "examples": ["512M", "2G"]
+    })
+}
+
+/// Schema of a resource limit
+pub fn rlimit(_: &mut SchemaGenerator) -> Schema {
+    let value = json!({
+        "anyOf": [
+            {"type": "integer", "minimum": 0},
+            {"type": "string", "pattern": SIZE_PATTERN},
+            {"enum": ["unlimited", "infinity"]}
+        ]
+    });
+    json_schema!({
+        "description": "A limit for both the soft and hard limit, or {soft, hard}",
+        "anyOf": [
+            {"type": "null"},
+            value,
+            {
+                "type": "object",
+                "properties": {"soft": value, "hard": value},
+                "required": ["soft", "hard"],
+                "additionalProperties": false
+            }
+        ],
+        "examples": [65536, "512M", "unlimited", {"soft": 1024, "hard": 4096}]
+    })
+}
+
+/// Schema of the `cpu_affinity` field
+pub fn cpu_affinity(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "description": "CPUs to run on, as a list or a range list like \"0-3,6\"",
+        "anyOf": [
+            {"type": "null"},
+            {"type": "array", "items": {"type": "integer", "minimum": 0}, "minItems": 1},
+            {"type": "string", "pattern": r"^\s*[0-9]+(-[0-9]+)?(\s*,\s*[0-9]+(-[0-9]+)?)*\s*$"}
+        ]
+    })
+}
+
+/// Add `env_<profile>` blocks to the app schema. `env_file` and
+/// `env_from_file` are regular fields, so the pattern skips them.
+pub fn app_transform(schema: &mut Schema) {
+    schema.insert(
+        "patternProperties".to_string(),
+        json!({
+            format!("^{}(?!file$|from_file$)", ENV_PROFILE_PREFIX): {
+                "description": "Env profile merged over `env` with `bpm start --env <profile>`",
+                "type": "object",
+                "additionalProperties": {"type": "string"}
+            }
+        }),
+    );
+}
+
+/// Require the field each healthcheck type reads
+pub fn healthcheck_transform(schema: &mut Schema) {
+    let requirement = |check_type: &str, field: &str| {
+        json!({
+            "if": {"properties": {"type": {"const": check_type}}},
+            "then": {"required": [field]}
+        })
+    };
+    schema.insert(
+        "allOf".to_string(),
+        json!([
+            requirement("http", "url"),
+            requirement("tcp", "port"),
+            requirement("command", "command")
+        ]),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config::{parse_as, ConfigFormat};
+    use std::path::Path;
+
+    fn validator() -> jsonschema::Validator {
+        jsonschema::validator_for(generate().as_value()).unwrap()
+    }
+
+    fn errors(validator: &jsonschema::Validator, config: &Value) -> Vec<String> {
+        validator
+            .iter_errors(config)
+            .map(|e| format!("{} at {}", e, e.instance_path))
+            .collect()
+    }
+
+    #[test]
+    fn test_examples_match_schema() {
+        let validator = validator();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("example/configs");
+        let mut checked = 0;
+
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let format = match path.extension().and_then(|e| e.to_str()) {
+                Some("json" | "yaml" | "yml" | "toml") => ConfigFormat::from_path(&path),
+                _ => continue,
+            };
+            let content = std::fs::read_to_string(&path).unwrap();
+            let config: Value = parse_as(&content, format).unwrap();
+
+            let errors = errors(&validator, &config);
+            assert!(errors.is_empty(), "{}: {:?}", path.display(), errors);
+            checked += 1;
+        }
+        assert!(checked > 10);
+    }
+
+    #[test]
+    fn test_schema_rejects_invalid_configs() {
+        let validator = validator();
+        let invalid = [
+            json!({"name": "a", "script": "sh", "restart": {"policy": "sometimes"}}),
+            json!({"name": "a", "script": "sh", "restart": {"restart_delay": "5 seconds"}}),
+            json!({"name": "a", "script": "sh", "healthcheck": {"type": "http"}}),
+            json!({"name": "a", "script": "sh", "healthcheck": {"type": "udp", "port": 1}}),
+            json!({"name": "a", "script": "sh", "scirpt": "typo"}),
+            json!({"name": "a", "script": "sh", "stop_signal": "SIGSTOPPLS"}),
+            json!({"name": "a", "script": "sh", "stop_ladder": [{"timeout": "5s"}]}),
+            json!({"name": "a", "script": "sh", "env_staging": {"PORT": 80}}),
+            json!({"name": "a", "script": "sh", "limits": {"nofile": "lots"}}),
+            json!({"name": "a", "script": "sh", "limits": {"nice": 40}}),
+            json!({"name": "a", "script": "sh", "cgroup": {"memory_max": "lots"}}),
+            json!({"name": "a", "script": "sh", "max_cpu": {"for": "5m"}}),
+            json!({"name": "a", "script": "sh", "reload": {"batch_size": 0}}),
+            json!({"name": "a"}),
+            json!({"web": [{"name": "a"}]}),
+        ];
+        for config in invalid {
+            assert!(!validator.is_valid(&config), "accepted {}", config);
+        }
+    }
+
+    #[test]
+    fn test_schema_accepts_layered_and_profiled_configs() {
+        let validator = validator();
+        let valid = [
+            json!({
+                "name": "a",
+                "script": "sh",
+                "env_file": [".env"],
+                "env_staging": {"PORT": "80"},
+                "depends_on": ["db", {"name": "cache", "condition": "healthy", "timeout": "1m"}],
+                "secrets": {"JWT_SECRET": {"file": "/run/secrets/jwt"}},
+                "stop_signal": "QUIT",
+                "stop_ladder": [{"signal": "SIGINT", "timeout": "5s"}, {"signal": "SIGTERM"}],
+                "limits": {
+                    "nofile": 65536,
+                    "core": {"soft": 0, "hard": "unlimited"},
+                    "as": "2G",
+                    "ionice": {"class": "best-effort", "level": 6},
+                    "cpu_affinity": "0-1"
+                },
+                "cgroup": {"memory_max": "1G", "memory_high": 900000000, "cpu_max": 0.5},
+                "max_memory_restart": "512M",
+                "max_cpu": {"percent": 90, "for": "5m"},
+                "reload": {"batch_size": 2, "ready_timeout": "1m", "wait_ready": true}
+            }),
+            json!({
+                "defaults": {"cwd": "/srv"},
+                "web": [{"name": "api", "script": "node"}]
+            }),
+            json!({"extends": "_base.yaml", "web": [{"name": "api", "args": ["b.js"]}]}),
+        ];
+        for config in valid {
+            let errors = errors(&validator, &config);
+            assert!(errors.is_empty(), "{}: {:?}", config, errors);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Secret env values
+//!
+//! Secrets are read when a process is spawned, from `env_from_file` dotenv
+//! files and from `secrets` entries (a file's contents or a command's
+//! stdout). They are never stored in the registry or state file. Env keys
+//! that are secret, or match one of the app's `secret_patterns`, are masked
+//! wherever the daemon shows a process.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::config::dotenv;
+
+/// Shown in place of a secret value
+pub const REDACTED: &str = "********";
+
+/// Where a secret env value comes from
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretSource {
+    /// The contents of a file, without the trailing newline
+    File(PathBuf),
+    /// The stdout of a shell command, without the trailing newline
+    Command(String),
+}
+
+impl SecretSource {
+    /// Read the secret. Commands run through `sh -c` in `cwd`.
+    pub fn read(&self, cwd: Option<&Path>) -> Result<String, String> {
+        let output = match self {
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?,
+            SecretSource::Command(command) => {
+                let mut cmd = std::process::Command::new("sh");
+                cmd.arg("-c").arg(command);
+                if let Some(cwd) = cwd {
+                    cmd.current_dir(cwd);
+                }
+                let output = cmd
+                    .output()
+                    .map_err(|e| format!("failed to run '{}': {}", command, e))?;
+                if !output.status.success() {
+                    return Err(format!("'{}' failed with {}", command, output.status));
+                }
+                String::from_utf8(output.stdout)
+                    .map_err(|_| format!("'{}' printed invalid UTF-8", command))?
+            }
+        };
+        Ok(output.trim_end_matches(['\n', '\r']).to_string())
+    }
+}
+
+/// Default `secret_patterns`
+pub fn default_patterns() -> Vec<String> {
+    ["*_SECRET", "*_TOKEN", "*_PASSWORD"]
+        .iter()
+        .map(|p| p.to_string())
+        .collect()
+}
+
+/// Read every secret of an app: `env_from_file` files in list order, then
+/// `secrets` entries, later ones winning
+pub fn load(
+    env_from_file: &[PathBuf],
+    secrets: &BTreeMap<String, SecretSource>,
+    cwd: Option<&Path>,
+) -> Result<HashMap<String, String>, String> {
+    let mut values = HashMap::new();
+    for path in env_from_file {
+        values.extend(dotenv::load(path)?);
+    }
+    for (key, source) in secrets {
+        let value = source.read(cwd).map_err(|e| format!("{}: {}", key, e))?;
+        values.insert(key.clone(), value);
+    }
+    Ok(values)
+}
+
+/// Whether an env key matches a pattern. `*` matches any run of characters;
+/// a pattern without `*` names a single key.
+pub fn matches_pattern(key: &str, pattern: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Whether an env key is secret: it has a `secrets` entry or matches one
+/// of `patterns`
+pub fn is_secret(key: &str, secrets: &BTreeMap<String, SecretSource>, patterns: &[String]) -> bool {
+    secrets.contains_key(key) || patterns.iter().any(|p| matches_pattern(key, p))
+}
+
+/// Mask the values of secret env keys (see `is_secret`)
+pub fn mask_env(
+    env: &HashMap<String, String>,
+    secrets: &BTreeMap<String, SecretSource>,
+    patterns: &[String],
+) -> HashMap<String, String> {
+    env.iter()
+        .map(|(key, value)| {
+            let secret = is_secret(key, secrets, patterns);
+            let value = if secret { REDACTED } else { value.as_str() };
+            (key.clone(), value.to_string())
+        })
+        .collect()
+}
+
+/// Replace every occurrence of the given secret values in `text`. Values
+/// shorter than four characters are left alone, since masking them would
+/// mangle unrelated output.
+pub fn mask_text(text: &str, values: &[&str]) -> String {
+    let mut values: Vec<&str> = values.iter().copied().filter(|v| v.len() >= 4).collect();
+    // Longest first, so a secret containing another is masked whole
+    values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+    values.into_iter().fold(text.to_string(), |text, value| {
+        text.replace(value, REDACTED)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("JWT_SECRET", "*_SECRET"));
+        assert!(matches_pattern("GITHUB_TOKEN", "*_TOKEN"));
+        assert!(matches_pattern("DB_PASSWORD_FILE", "DB_*_FILE"));
+        assert!(matches_pattern("API_KEY", "API_KEY"));
+        assert!(!matches_pattern("SECRET_NAME", "*_SECRET"));
+        assert!(!matches_pattern("API_KEYS", "API_KEY"));
+        assert!(!matches_pattern("X", "X*X"));
+    }
+
+    #[test]
+    fn test_mask_env() {
+        let env: HashMap<String, String> = [
+            ("JWT_SECRET", "hunter2"),
+            ("DB_URL", "postgres://app:pw@db"),
+            ("PORT", "3000"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let marked = BTreeMap::from([(
+            "DB_URL".to_string(),
+            SecretSource::Command("vault read db".to_string()),
+        )]);
+
+        let masked = mask_env(&env, &marked, &default_patterns());
+        assert_eq!(masked["JWT_SECRET"], REDACTED);
+        assert_eq!(masked["DB_URL"], REDACTED);
+        assert_eq!(masked["PORT"], "3000");
+    }
+
+    #[test]
+    fn test_load_sources() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("db.env"), "DB_USER=app\nDB_PASS=first\n").unwrap();
+        std::fs::write(dir.path().join("jwt"), "s3cr3t\n").unwrap();
+
+        let secrets = BTreeMap::from([
+            (
+                "JWT_SECRET".to_string(),
+                SecretSource::File(dir.path().join("jwt")),
+            ),
+            (
+                "DB_PASS".to_string(),
+                SecretSource::Command("printf second".to_string()),
+            ),
+        ]);
+        let values = load(&[dir.path().join("db.env")], &secrets, Some(dir.path())).unwrap();
+
+        assert_eq!(values["DB_USER"], "app");
+        assert_eq!(values["DB_PASS"], "second");
+        assert_eq!(values["JWT_SECRET"], "s3cr3t");
+
+        let failing = BTreeMap::from([("X".to_string(), SecretSource::Command("exit 3".into()))]);
+        assert!(load(&[], &failing, None).unwrap_err().starts_with("X: "));
+    }
+
+    #[test]
+    fn test_mask_text() {
+        let text = "connecting with s3cr3t-long and s3cr3t (id 42)";
+        assert_eq!(
+            mask_text(text, &["s3cr3t", "s3cr3t-long", "42"]),
+            "connecting with ******** and ******** (id 42)"
+        );
+    }
+}
@@ -5,15 +5,40 @@
 #![allow(dead_code)] // For future use
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+use crate::config::merge;
+
+/// SHA-256 of everything an app's config is read from, hex encoded: the
+/// config file, the files it `extends` and the app's `env_files`. `None`
+/// if the config file can't be read.
+pub fn config_checksum(path: &Path, env_files: &[PathBuf]) -> Option<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(std::fs::read(path).ok()?);
+    // A config without other inputs keeps the plain checksum of its content
+    for input in merge::extends_chain(path).iter().chain(env_files) {
+        let content = std::fs::read(input).ok();
+        hasher.update(input.as_os_str().as_bytes());
+        hasher.update([0]);
+        // A missing file hashes differently from an empty one
+        let len = content
+            .as_ref()
+            .map_or(u64::MAX, |content| content.len() as u64);
+        hasher.update(len.to_le_bytes());
+        hasher.update(content.unwrap_or_default());
+    }
+    let digest = hasher.finalize();
+    Some(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
 /// Reference to an app's config file
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AppReference {
     pub config_path: PathBuf,
-    pub checksum: Option<String>,
 }
 
 /// Persistent BPM configuration state
@@ -0,0 +1,1288 @@
+//! Config validation
+//!
+//! Strict checks run by `bpm validate` and by the daemon before it registers
+//! anything from a config file: unknown fields, missing required keys, bad
+//! durations, incomplete healthchecks and executables that cannot be found.
+
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::config::read_config::{
+    parse_as, parse_duration_str, parse_rlimit_value, parse_signal_str, parse_size_str, App,
+    ConfigFormat, Dependency, DependencyCondition, ENV_PROFILE_PREFIX,
+};
+use crate::config::secrets::SecretSource;
+use crate::config::{merge, procfile};
+use crate::process_manager::{credentials, dependency};
+
+/// Fields accepted on an app
+pub const APP_FIELDS: &[&str] = &[
+    "name",
+    "script",
+    "args",
+    "cwd",
+    "env",
+    "env_file",
+    "log",
+    "restart",
+    "crash_loop",
+    "reload",
+    "healthcheck",
+    "schedule",
+    "instances",
+    "port_base",
+    "depends_on",
+    "env_from_file",
+    "secrets",
+    "secret_patterns",
+    "stop_signal",
+    "kill_timeout",
+    "stop_ladder",
+    "user",
+    "group",
+    "supplementary_groups",
+    "umask",
+    "ambient_capabilities",
+    "limits",
+    "cgroup",
+    "max_memory_restart",
+    "max_cpu",
+];
+
+/// Fields accepted in an app's `log` block
+pub const LOG_FIELDS: &[&str] = &["out", "error", "combined"];
+
+/// Fields accepted in an app's `restart` block
+pub const RESTART_FIELDS: &[&str] = &[
+    "policy",
+    "max_restarts",
+    "restart_delay",
+    "backoff",
+    "success_exit_codes",
+    "restart_on_exit_codes",
+];
+
+/// Fields accepted in a restart `backoff` block
+pub const BACKOFF_FIELDS: &[&str] = &["multiplier", "max_delay", "jitter", "reset_after"];
+
+/// Fields accepted in an app's `crash_loop` block
+pub const CRASH_LOOP_FIELDS: &[&str] = &["window", "max_crashes", "cooldown"];
+
+/// Fields accepted in an app's `reload` block
+pub const RELOAD_FIELDS: &[&str] = &["batch_size", "ready_timeout", "wait_ready"];
+
+/// Fields accepted in an app's `limits` block
+pub const LIMITS_FIELDS: &[&str] = &[
+    "nofile",
+    "nproc",
+    "core",
+    "as",
+    "cpu",
+    "nice",
+    "ionice",
+    "oom_score_adj",
+    "cpu_affinity",
+];
+
+/// Fields accepted in an app's `cgroup` block
+pub const CGROUP_FIELDS: &[&str] = &[
+    "memory_max",
+    "memory_high",
+    "cpu_max",
+    "pids_max",
+    "io_weight",
+];
+
+/// Fields accepted in an app's `max_cpu` block
+pub const MAX_CPU_FIELDS: &[&str] = &["percent", "for"];
+
+/// Fields accepted in a `depends_on` entry
+pub const DEPENDENCY_FIELDS: &[&str] = &["name", "condition", "timeout"];
+
+/// Fields accepted in a `stop_ladder` step
+pub const STOP_STEP_FIELDS: &[&str] = &["signal", "timeout"];
+
+/// Fields accepted in an app's `healthcheck` block
+pub const HEALTHCHECK_FIELDS: &[&str] = &[
+    "type",
+    "interval",
+    "timeout",
+    "retries",
+    "start_period",
+    "url",
+    "command",
+    "host",
+    "port",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in a config
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Where the problem is, e.g. `my-project[1] (worker).restart`
+    pub location: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.location.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.location, self.message)
+        }
+    }
+}
+
+/// All diagnostics found in a config
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count()
+    }
+
+    fn error(&mut self, location: &str, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            location: location.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn warning(&mut self, location: &str, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            location: location.to_string(),
+            message: message.into(),
+        });
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+/// Validate a config file, or every config file in a directory
+pub fn validate_file(path: &Path) -> Report {
+    if path.is_dir() {
+        return validate_dir(path);
+    }
+
+    let mut report = Report::default();
+
+    // Procfiles only have their lines to get wrong; the apps made from them
+    // are always well-formed
+    if procfile::is_procfile(path) {
+        if let Err(e) = procfile::load(path) {
+            report.error("", e.to_string());
+        }
+        return report;
+    }
+
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            report.error("", format!("failed to read '{}': {}", path.display(), e));
+            return report;
+        }
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let format = ConfigFormat::from_path(path);
+
+    // Layered configs are checked after `extends` and `defaults` are applied
+    match parse_as::<Value>(&content, format) {
+        Ok(value) if merge::is_layered(&value) => match merge::load(path) {
+            Ok(value) => validate_value(&value, base_dir),
+            Err(e) => {
+                report.error("", e.to_string());
+                report
+            }
+        },
+        _ => validate_str(&content, format, base_dir),
+    }
+}
+
+fn validate_dir(dir: &Path) -> Report {
+    let mut report = Report::default();
+
+    let files = match merge::config_files_in(dir) {
+        Ok(files) => files,
+        Err(e) => {
+            report.error("", format!("failed to read '{}': {}", dir.display(), e));
+            return report;
+        }
+    };
+    if files.is_empty() {
+        report.error("", format!("no config files in '{}'", dir.display()));
+    }
+
+    for file in files {
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        for mut diagnostic in validate_file(&file).diagnostics {
+            diagnostic.location = match diagnostic.location.as_str() {
+                "" => file_name.to_string(),
+                location => format!("{}: {}", file_name, location),
+            };
+            report.diagnostics.push(diagnostic);
+        }
+    }
+
+    report
+}
+
+/// Validate config content. `base_dir` is the directory relative paths in
+/// the config are resolved against.
+pub fn validate_str(content: &str, format: ConfigFormat, base_dir: &Path) -> Report {
+    match parse_as::<Value>(content, format) {
+        Ok(value) => validate_value(&value, base_dir),
+        Err(e) => {
+            let mut report = Report::default();
+            report.error("", e.to_string());
+            report
+        }
+    }
+}
+
+fn validate_value(value: &Value, base_dir: &Path) -> Report {
+    let mut report = Report::default();
+
+    if value.get(merge::EXTENDS_KEY).is_some() {
+        report.error("", "'extends' can only be resolved when validating a file");
+        return report;
+    }
+    let mut value = value.clone();
+    if let Err(e) = merge::apply_defaults(&mut value) {
+        report.error("", e);
+        return report;
+    }
+    let value = &value;
+
+    let Some(top) = value.as_object() else {
+        report.error("", "config must be an object");
+        return report;
+    };
+
+    // (project, location, app)
+    let mut apps = Vec::new();
+    if top.contains_key("name") || top.contains_key("script") {
+        let name = top.get("name").and_then(Value::as_str).unwrap_or("app");
+        apps.push(("", name.to_string(), value));
+    } else {
+        if top.is_empty() {
+            report.error("", "config contains no apps");
+        }
+        for (project, project_apps) in top {
+            let Some(list) = project_apps.as_array() else {
+                report.error(project, "expected a list of apps");
+                continue;
+            };
+            for (idx, app) in list.iter().enumerate() {
+                let name = app.get("name").and_then(Value::as_str).unwrap_or("?");
+                apps.push((project, format!("{}[{}] ({})", project, idx, name), app));
+            }
+        }
+    }
+
+    // Names only need to be unique within a project
+    let mut seen_names = HashSet::new();
+    for &(project, ref location, app) in &apps {
+        if let Some(name) = app.get("name").and_then(Value::as_str) {
+            if name.contains('/') {
+                report.error(location, "app names may not contain '/'");
+            }
+            if !seen_names.insert((project, name)) {
+                report.error(location, format!("duplicate app name '{}'", name));
+            }
+        }
+        validate_app(&mut report, location, app, base_dir);
+    }
+    validate_dependencies(&mut report, &apps);
+
+    report
+}
+
+/// Check `depends_on` across the apps of a config: dependencies outside the
+/// config, `healthy` conditions that can never be met, and cycles
+fn validate_dependencies(report: &mut Report, apps: &[(&str, String, &Value)]) {
+    let project_of = |project: &str| (!project.is_empty()).then(|| project.to_string());
+
+    // (qualified name, location, app) for every app that parses
+    let parsed: Vec<(String, &str, App)> = apps
+        .iter()
+        .filter_map(|(project, location, value)| {
+            let app: App = serde_json::from_value((*value).clone()).ok()?;
+            let app = App {
+                project: project_of(project),
+                ..app
+            };
+            Some((app.qualified_name(), location.as_str(), app))
+        })
+        .collect();
+
+    let mut nodes = Vec::new();
+    for (name, location, app) in &parsed {
+        let dependencies: Vec<Dependency> = app
+            .depends_on
+            .iter()
+            .map(|d| d.qualified(app.project.as_deref()))
+            .collect();
+
+        for dependency in &dependencies {
+            let target = parsed
+                .iter()
+                .find(|(other, _, _)| other == dependency.name());
+            match target {
+                None => report.warning(
+                    location,
+                    format!(
+                        "depends_on '{}' is not in this config; it must already be running",
+                        dependency.name()
+                    ),
+                ),
+                Some((_, _, target))
+                    if dependency.condition() == DependencyCondition::Healthy
+                        && target.healthcheck.is_none() =>
+                {
+                    report.error(
+                        location,
+                        format!(
+                            "depends_on '{}' waits until it is healthy, but it has no healthcheck",
+                            dependency.name()
+                        ),
+                    )
+                }
+                Some(_) => {}
+            }
+        }
+
+        let names = dependencies.iter().map(|d| d.name().to_string()).collect();
+        nodes.push((name.clone(), names));
+    }
+
+    if let Err(cycle) = dependency::start_order(&nodes) {
+        report.error("", format!("dependency cycle: {}", cycle.join(" -> ")));
+    }
+}
+
+fn validate_app(report: &mut Report, location: &str, value: &Value, base_dir: &Path) {
+    let Some(fields) = value.as_object() else {
+        report.error(location, "app must be an object");
+        return;
+    };
+    let errors_before = report.error_count();
+
+    // `env_<profile>` blocks can have any name, so only check the fixed fields
+    let fixed_fields = fields
+        .iter()
+        .filter(|(key, _)| {
+            !key.starts_with(ENV_PROFILE_PREFIX) || APP_FIELDS.contains(&key.as_str())
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    check_unknown_fields(report, location, &Value::Object(fixed_fields), APP_FIELDS);
+    for required in ["name", "script"] {
+        if !fields.contains_key(required) {
+            report.error(location, format!("missing required field '{}'", required));
+        }
+    }
+
+    if let Some(log) = fields.get("log") {
+        check_unknown_fields(report, &format!("{}.log", location), log, LOG_FIELDS);
+    }
+
+    if let Some(restart) = fields.get("restart") {
+        let restart_location = format!("{}.restart", location);
+        check_unknown_fields(report, &restart_location, restart, RESTART_FIELDS);
+        check_duration(report, &restart_location, restart, "restart_delay");
+        if let Some(backoff) = restart.get("backoff") {
+            validate_backoff(report, &format!("{}.backoff", restart_location), backoff);
+        }
+    }
+
+    if let Some(crash_loop) = fields.get("crash_loop") {
+        let crash_loop_location = format!("{}.crash_loop", location);
+        check_unknown_fields(report, &crash_loop_location, crash_loop, CRASH_LOOP_FIELDS);
+        for field in ["window", "cooldown"] {
+            check_duration(report, &crash_loop_location, crash_loop, field);
+        }
+        if crash_loop.get("max_crashes").and_then(Value::as_u64) == Some(0) {
+            report.error(&crash_loop_location, "max_crashes must be at least 1");
+        }
+    }
+
+    if let Some(reload) = fields.get("reload") {
+        let reload_location = format!("{}.reload", location);
+        check_unknown_fields(report, &reload_location, reload, RELOAD_FIELDS);
+        check_duration(report, &reload_location, reload, "ready_timeout");
+        if reload.get("batch_size").and_then(Value::as_u64) == Some(0) {
+            report.error(&reload_location, "batch_size must be at least 1");
+        }
+        let wait_ready = reload.get("wait_ready").and_then(Value::as_bool);
+        if wait_ready != Some(true) && !fields.contains_key("healthcheck") {
+            report.warning(
+                &reload_location,
+                "without a healthcheck or wait_ready, a new process counts as ready once it has stayed up for a second",
+            );
+        }
+    }
+
+    check_signal(report, location, value, "stop_signal");
+    check_duration(report, location, value, "kill_timeout");
+    if let Some(ladder) = fields.get("stop_ladder") {
+        validate_stop_ladder(report, &format!("{}.stop_ladder", location), ladder);
+        if let Some(field) = ["stop_signal", "kill_timeout"]
+            .into_iter()
+            .find(|field| fields.contains_key(*field))
+        {
+            report.warning(
+                location,
+                format!("{} is ignored, stop_ladder replaces it", field),
+            );
+        }
+    }
+
+    if let Some(limits) = fields.get("limits") {
+        validate_limits(report, &format!("{}.limits", location), limits);
+    }
+    if let Some(cgroup) = fields.get("cgroup") {
+        validate_cgroup(report, &format!("{}.cgroup", location), cgroup);
+    }
+    if let Some(Value::String(size)) = fields.get("max_memory_restart") {
+        if let Err(e) = parse_size_str(size) {
+            report.error(location, format!("max_memory_restart: {}", e));
+        }
+    }
+    if let Some(max_cpu) = fields.get("max_cpu") {
+        let max_cpu_location = format!("{}.max_cpu", location);
+        check_unknown_fields(report, &max_cpu_location, max_cpu, MAX_CPU_FIELDS);
+        check_duration(report, &max_cpu_location, max_cpu, "for");
+        match max_cpu.get("percent").and_then(Value::as_f64) {
+            Some(percent) if percent <= 0.0 => {
+                report.error(&max_cpu_location, "percent must be above 0")
+            }
+            Some(_) => {}
+            None => report.error(&max_cpu_location, "missing required field 'percent'"),
+        }
+    }
+
+    if fields.get("instances").and_then(Value::as_u64) == Some(0) {
+        report.error(location, "instances must be at least 1");
+    }
+
+    if let Some(healthcheck) = fields.get("healthcheck") {
+        validate_healthcheck(report, &format!("{}.healthcheck", location), healthcheck);
+    }
+
+    if let Some(depends_on) = fields.get("depends_on") {
+        validate_depends_on(report, &format!("{}.depends_on", location), depends_on);
+    }
+
+    // Type errors are only interesting once the structural problems are fixed
+    if report.error_count() > errors_before {
+        return;
+    }
+
+    let app: App = match serde_json::from_value(value.clone()) {
+        Ok(app) => app,
+        Err(e) => {
+            report.error(location, e.to_string());
+            return;
+        }
+    };
+
+    // The last instance has the highest `port_base` port
+    let last_instance = app.instance_count().saturating_sub(1);
+    let resolved = app
+        .resolve(base_dir, last_instance)
+        .and_then(|_| app.resolve(base_dir, 0));
+    match resolved {
+        Ok(resolved) => {
+            check_executable(report, location, &resolved);
+            check_secret_files(report, location, &resolved);
+            check_run_as(report, location, &resolved);
+        }
+        Err(e) => report.error(location, e.to_string()),
+    }
+}
+
+/// Secret files are only read at spawn time, so a missing one is not fatal
+/// here, but it will be when the app starts
+fn check_secret_files(report: &mut Report, location: &str, app: &App) {
+    let secret_files = app.secrets.values().filter_map(|source| match source {
+        SecretSource::File(path) => Some(path),
+        SecretSource::Command(_) => None,
+    });
+    for path in app.env_from_file.iter().chain(secret_files) {
+        if !path.is_file() {
+            report.warning(
+                location,
+                format!("secret file '{}' does not exist", path.display()),
+            );
+        }
+    }
+}
+
+/// Users and groups are looked up where the daemon runs, which may not be
+/// this host, so unknown ones are only warned about
+fn check_run_as(report: &mut Report, location: &str, app: &App) {
+    if let Some(Err(e)) = app.user.as_deref().map(credentials::lookup_user) {
+        report.warning(location, e);
+    }
+    for group in app.group.iter().chain(&app.supplementary_groups) {
+        if let Err(e) = credentials::lookup_group(group) {
+            report.warning(location, e);
+        }
+    }
+}
+
+fn validate_healthcheck(report: &mut Report, location: &str, value: &Value) {
+    check_unknown_fields(report, location, value, HEALTHCHECK_FIELDS);
+    for field in ["interval", "timeout", "start_period"] {
+        check_duration(report, location, value, field);
+    }
+
+    let required = match value.get("type").and_then(Value::as_str) {
+        Some("http") => "url",
+        Some("tcp") => "port",
+        Some("command") => "command",
+        Some(other) => {
+            report.error(
+                location,
+                format!(
+                    "unknown healthcheck type '{}', expected http, tcp or command",
+                    other
+                ),
+            );
+            return;
+        }
+        None => {
+            report.error(location, "missing required field 'type'");
+            return;
+        }
+    };
+
+    if value.get(required).is_none_or(Value::is_null) {
+        report.error(
+            location,
+            format!(
+                "'{}' healthcheck requires '{}'",
+                value["type"].as_str().unwrap_or_default(),
+                required
+            ),
+        );
+    }
+}
+
+fn validate_depends_on(report: &mut Report, location: &str, value: &Value) {
+    let Some(entries) = value.as_array() else {
+        report.error(location, "expected a list of app names");
+        return;
+    };
+
+    for (idx, entry) in entries.iter().enumerate() {
+        let location = format!("{}[{}]", location, idx);
+        match entry {
+            Value::String(_) => {}
+            Value::Object(fields) => {
+                check_unknown_fields(report, &location, entry, DEPENDENCY_FIELDS);
+                check_duration(report, &location, entry, "timeout");
+                if !fields.contains_key("name") {
+                    report.error(&location, "missing required field 'name'");
+                }
+                match fields.get("condition").map(|c| c.as_str()) {
+                    None | Some(Some("running" | "healthy")) => {}
+                    Some(_) => {
+                        report.error(&location, "condition must be \"running\" or \"healthy\"")
+                    }
+                }
+            }
+            _ => report.error(
+                &location,
+                "expected an app name or {name, condition, timeout}",
+            ),
+        }
+    }
+}
+
+fn validate_stop_ladder(report: &mut Report, location: &str, value: &Value) {
+    let Some(steps) = value.as_array() else {
+        report.error(location, "expected a list of {signal, timeout}");
+        return;
+    };
+
+    for (idx, step) in steps.iter().enumerate() {
+        let location = format!("{}[{}]", location, idx);
+        check_unknown_fields(report, &location, step, STOP_STEP_FIELDS);
+        if step.get("signal").is_none() {
+            report.error(&location, "missing required field 'signal'");
+        }
+        check_signal(report, &location, step, "signal");
+        check_duration(report, &location, step, "timeout");
+    }
+}
+
+fn validate_backoff(report: &mut Report, location: &str, value: &Value) {
+    check_unknown_fields(report, location, value, BACKOFF_FIELDS);
+    for field in ["max_delay", "reset_after"] {
+        check_duration(report, location, value, field);
+    }
+    if let Some(multiplier) = value.get("multiplier").and_then(Value::as_f64) {
+        if multiplier < 1.0 {
+            report.error(location, "multiplier must be at least 1");
+        }
+    }
+    if let Some(jitter) = value.get("jitter").and_then(Value::as_f64) {
+        if !(0.0..=1.0).contains(&jitter) {
+            report.error(location, "jitter must be between 0 and 1");
+        }
+    }
+}
+
+fn validate_limits(report: &mut Report, location: &str, value: &Value) {
+    check_unknown_fields(report, location, value, LIMITS_FIELDS);
+    for field in ["nofile", "nproc", "core", "as", "cpu"] {
+        if let Some(Err(e)) = value.get(field).map(parse_rlimit_value) {
+            report.error(location, format!("{}: {}", field, e));
+        }
+    }
+    let in_range = |field: &str, min: i64, max: i64| match value.get(field) {
+        None | Some(Value::Null) => true,
+        Some(n) => n.as_i64().is_some_and(|n| (min..=max).contains(&n)),
+    };
+    if !in_range("nice", -20, 19) {
+        report.error(location, "nice must be a number from -20 to 19");
+    }
+    if !in_range("oom_score_adj", -1000, 1000) {
+        report.error(
+            location,
+            "oom_score_adj must be a number from -1000 to 1000",
+        );
+    }
+    if let Some(ionice) = value.get("ionice") {
+        if ionice
+            .get("level")
+            .and_then(Value::as_u64)
+            .is_some_and(|level| level > 7)
+        {
+            report.error(location, "ionice level must be from 0 to 7");
+        }
+    }
+}
+
+fn validate_cgroup(report: &mut Report, location: &str, value: &Value) {
+    check_unknown_fields(report, location, value, CGROUP_FIELDS);
+    let mut size = |field: &str| match value.get(field) {
+        Some(Value::String(s)) => match parse_size_str(s) {
+            Ok(size) => Some(size),
+            Err(e) => {
+                report.error(location, format!("{}: {}", field, e));
+                None
+            }
+        },
+        Some(size) => size.as_u64(),
+        None => None,
+    };
+    if let (Some(max), Some(high)) = (size("memory_max"), size("memory_high")) {
+        if high >= max {
+            report.warning(location, "memory_high has no effect at or above memory_max");
+        }
+    }
+    if let Some(cpus) = value.get("cpu_max").and_then(Value::as_f64) {
+        if cpus <= 0.0 {
+            report.error(location, "cpu_max must be above 0");
+        }
+    }
+    if let Some(weight) = value.get("io_weight").and_then(Value::as_u64) {
+        if !(1..=10000).contains(&weight) {
+            report.error(location, "io_weight must be from 1 to 10000");
+        }
+    }
+}
+
+fn check_unknown_fields(report: &mut Report, location: &str, value: &Value, known: &[&str]) {
+    let Some(fields) = value.as_object() else {
+        report.error(location, "expected an object");
+        return;
+    };
+
+    for key in fields.keys() {
+        if !known.contains(&key.as_str()) {
+            let message = match closest_match(key, known) {
+                Some(suggestion) => {
+                    format!("unknown field '{}', did you mean '{}'?", key, suggestion)
+                }
+                None => format!("unknown field '{}'", key),
+            };
+            report.error(location, message);
+        }
+    }
+}
+
+fn check_duration(report: &mut Report, location: &str, value: &Value, field: &str) {
+    match value.get(field) {
+        None | Some(Value::Null) => {}
+        Some(Value::String(s)) => {
+            if let Err(e) = parse_duration_str(s) {
+                report.error(location, format!("{}: {}", field, e));
+            }
+        }
+        Some(_) => report.error(
+            location,
+            format!("{}: expected a duration string like \"30s\"", field),
+        ),
+    }
+}
+
+fn check_signal(report: &mut Report, location: &str, value: &Value, field: &str) {
+    match value.get(field) {
+        None | Some(Value::Null) => {}
+        Some(Value::String(s)) => {
+            if let Err(e) = parse_signal_str(s) {
+                report.error(location, format!("{}: {}", field, e));
+            }
+        }
+        Some(_) => report.error(
+            location,
+            format!("{}: expected a signal name like \"SIGTERM\"", field),
+        ),
+    }
+}
+
+/// Check that the app's script can be executed
+fn check_executable(report: &mut Report, location: &str, app: &App) {
+    if let Some(cwd) = &app.cwd {
+        if !cwd.is_dir() {
+            report.warning(location, format!("cwd '{}' does not exist", cwd.display()));
+            // A relative script can't be checked without its directory
+            if app.script.contains('/') && !Path::new(&app.script).is_absolute() {
+                return;
+            }
+        }
+    }
+
+    if app.script.contains('/') {
+        let path = match &app.cwd {
+            Some(cwd) => cwd.join(&app.script),
+            None => PathBuf::from(&app.script),
+        };
+        if !is_executable(&path) {
+            report.error(
+                location,
+                format!("script '{}' is not an executable file", path.display()),
+            );
+        }
+    } else if find_in_path(&app.script).is_none() {
+        report.error(
+            location,
+            format!("executable '{}' not found on PATH", app.script),
+        );
+    }
+}
+
+/// Look up a program name on PATH
+pub fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Suggest a known field for a likely typo
+fn closest_match<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(report: &Report) -> Vec<String> {
+        report.diagnostics.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let json = r#"{"name": "app", "script": "sh", "args": ["-c", "true"]}"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(report.diagnostics.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn test_unknown_field_with_suggestion() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "restart": {"policy": "always", "restart_dealy": "5s"}
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(report.has_errors());
+        assert!(
+            messages(&report)[0].contains("did you mean 'restart_delay'"),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn test_missing_required_and_bad_duration() {
+        let yaml = r#"
+my-project:
+  - name: api
+    restart:
+      restart_delay: 5 seconds
+"#;
+        let report = validate_str(yaml, ConfigFormat::Yaml, Path::new("/tmp"));
+        let messages = messages(&report);
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("missing required field 'script'")));
+        assert!(messages.iter().any(|m| m.contains("invalid duration")));
+    }
+
+    #[test]
+    fn test_healthcheck_requirements() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "healthcheck": {"type": "http", "interval": "30s"}
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(messages(&report)[0].contains("'http' healthcheck requires 'url'"));
+    }
+
+    #[test]
+    fn test_missing_executable() {
+        let json = r#"{"name": "app", "script": "bpm-definitely-not-a-real-binary"}"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(messages(&report)[0].contains("not found on PATH"));
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let json = r#"{"p": [{"name": "a", "script": "sh"}, {"name": "a", "script": "sh"}]}"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(messages(&report)[0].contains("duplicate app name 'a'"));
+    }
+
+    #[test]
+    fn test_same_name_in_different_projects() {
+        let json = r#"{
+            "billing": [{"name": "worker", "script": "sh"}],
+            "search": [{"name": "worker", "script": "sh"}]
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(report.diagnostics.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn test_defaults_are_validated_per_app() {
+        let json = r#"{
+            "defaults": {"restart": {"restart_dealy": "5s"}},
+            "p": [{"name": "a", "script": "sh"}, {"name": "b", "script": "sh"}]
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert_eq!(report.error_count(), 2, "{}", report);
+        assert!(messages(&report)[0].starts_with("error: p[0] (a).restart"));
+    }
+
+    #[test]
+    fn test_env_profiles_are_not_unknown_fields() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "env_production": {"NODE_ENV": "production"},
+            "env_staging": {"NODE_ENV": "staging"}
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(report.diagnostics.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn test_dependency_cycle() {
+        let yaml = r#"
+shop:
+  - name: api
+    script: sh
+    depends_on: [worker]
+  - name: worker
+    script: sh
+    depends_on:
+      - name: api
+        condition: running
+        timeout: 10s
+"#;
+        let report = validate_str(yaml, ConfigFormat::Yaml, Path::new("/tmp"));
+        assert_eq!(
+            messages(&report),
+            vec!["error: dependency cycle: shop/api -> shop/worker -> shop/api"]
+        );
+    }
+
+    #[test]
+    fn test_dependency_checks() {
+        let yaml = r#"
+shop:
+  - name: db
+    script: sh
+  - name: api
+    script: sh
+    depends_on:
+      - name: db
+        condition: healthy
+      - billing/ledger
+  - name: worker
+    script: sh
+    depends_on:
+      - name: api
+        condition: ready
+        timout: 5s
+"#;
+        let report = validate_str(yaml, ConfigFormat::Yaml, Path::new("/tmp"));
+        let messages = messages(&report);
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("'shop/db' waits until it is healthy")));
+        assert!(messages
+            .iter()
+            .any(|m| m.starts_with("warning: shop[1] (api): depends_on 'billing/ledger'")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("did you mean 'timeout'")));
+        assert!(messages.iter().any(|m| m.contains("condition must be")));
+    }
+
+    #[test]
+    fn test_secret_sources() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "env_from_file": ["/nonexistent/db.env"],
+            "secrets": {
+                "JWT_SECRET": {"file": "/nonexistent/jwt"},
+                "API_TOKEN": {"command": "pass show api"}
+            }
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(!report.has_errors(), "{}", report);
+        assert_eq!(report.diagnostics.len(), 2, "{}", report);
+
+        let json = r#"{"name": "app", "script": "sh", "secrets": {"X": {"vault": "x"}}}"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(report.has_errors());
+    }
+
+    #[test]
+    fn test_stop_settings() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "stop_signal": "SIGSTAHP",
+            "kill_timeout": "30",
+            "stop_ladder": [{"signal": "INT", "timeout": "5s"}, {"timeout": "5s"}]
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        let messages = messages(&report);
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("unknown signal 'SIGSTAHP'")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("kill_timeout: invalid duration")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("stop_ladder[1]: missing required field 'signal'")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("stop_signal is ignored, stop_ladder replaces it")));
+    }
+
+    #[test]
+    fn test_restart_backoff_and_crash_loop() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "restart": {"backoff": {"multiplier": 0.5, "jitter": 2, "reset_after": "1 min"}},
+            "crash_loop": {"max_crashes": 0, "cooldown": "ten minutes"},
+            "reload": {"batch_size": 0, "ready_timeout": "soon"}
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        let messages = messages(&report);
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("restart.backoff: multiplier must be at least 1")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("jitter must be between 0 and 1")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("reset_after: invalid duration")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("crash_loop: max_crashes must be at least 1")));
+        assert!(messages.iter().any(|m| m.contains("cooldown: invalid")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("reload: batch_size must be at least 1")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("ready_timeout: invalid duration")));
+    }
+
+    #[test]
+    fn test_run_as_settings() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "user": "no-such-user-here",
+            "supplementary_groups": ["no-such-group-here"],
+            "umask": "0999"
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(messages(&report)
+            .iter()
+            .any(|m| m.contains("invalid umask '0999'")));
+
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "user": "no-such-user-here",
+            "supplementary_groups": ["no-such-group-here"],
+            "umask": 27,
+            "ambient_capabilities": ["net_bind_service"]
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(!report.has_errors(), "{}", report);
+        let warnings = messages(&report);
+        assert!(warnings
+            .iter()
+            .any(|m| m.contains("unknown user 'no-such-user-here'")));
+        assert!(warnings
+            .iter()
+            .any(|m| m.contains("unknown group 'no-such-group-here'")));
+
+        let json = r#"{"name": "app", "script": "sh", "ambient_capabilities": ["CAP_FLY"]}"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(messages(&report)
+            .iter()
+            .any(|m| m.contains("unknown capability 'CAP_FLY'")));
+    }
+
+    #[test]
+    fn test_limits() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "limits": {
+                "nofile": {"soft": 1024, "hard": 4096},
+                "core": "unlimited",
+                "as": "2G",
+                "nice": 5,
+                "ionice": {"class": "idle"},
+                "oom_score_adj": 300,
+                "cpu_affinity": "0"
+            },
+            "cgroup": {"memory_max": "1G", "memory_high": "768M", "cpu_max": 0.5, "pids_max": 64}
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        assert!(!report.has_errors(), "{}", report);
+
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "limits": {
+                "nofile": {"soft": 4096, "hard": 1024},
+                "nproc": "many",
+                "nice": -30,
+                "ionice": {"class": "best-effort", "level": 9},
+                "oom_score_ajd": 1
+            },
+            "cgroup": {"memory_max": "1 gig", "io_weight": 0}
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        let errors = messages(&report);
+        for expected in [
+            "nofile: the soft limit is above the hard limit",
+            "nproc: invalid size 'many'",
+            "nice must be a number from -20 to 19",
+            "ionice level must be from 0 to 7",
+            "unknown field 'oom_score_ajd', did you mean 'oom_score_adj'?",
+            "memory_max: invalid size '1 gig'",
+            "io_weight must be from 1 to 10000",
+        ] {
+            assert!(
+                errors.iter().any(|m| m.contains(expected)),
+                "{}: {:?}",
+                expected,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_restart_thresholds() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "max_memory_restart": "lots",
+            "max_cpu": {"percent": 0, "for": "soon", "over": 1}
+        }"#;
+        let report = validate_str(json, ConfigFormat::Json, Path::new("/tmp"));
+        let errors = messages(&report);
+        for expected in [
+            "max_memory_restart: invalid size 'lots'",
+            "percent must be above 0",
+            "for: ",
+            "unknown field 'over'",
+        ] {
+            assert!(
+                errors.iter().any(|m| m.contains(expected)),
+                "{}: {:?}",
+                expected,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_field_lists_match_structs() {
+        let json = r#"{
+            "name": "app",
+            "script": "sh",
+            "healthcheck": {"type": "tcp", "port": 1},
+            "restart": {"backoff": {}},
+            "max_cpu": {"percent": 90}
+        }"#;
+        let app: App = serde_json::from_str(json).unwrap();
+        let value = serde_json::to_value(&app).unwrap();
+
+        let keys = |v: &Value| -> Vec<String> { v.as_object().unwrap().keys().cloned().collect() };
+        for key in keys(&value) {
+            assert!(
+                APP_FIELDS.contains(&key.as_str()),
+                "APP_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["log"]) {
+            assert!(
+                LOG_FIELDS.contains(&key.as_str()),
+                "LOG_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["restart"]) {
+            assert!(
+                RESTART_FIELDS.contains(&key.as_str()),
+                "RESTART_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["crash_loop"]) {
+            assert!(
+                CRASH_LOOP_FIELDS.contains(&key.as_str()),
+                "CRASH_LOOP_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["reload"]) {
+            assert!(
+                RELOAD_FIELDS.contains(&key.as_str()),
+                "RELOAD_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["limits"]) {
+            assert!(
+                LIMITS_FIELDS.contains(&key.as_str()),
+                "LIMITS_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["cgroup"]) {
+            assert!(
+                CGROUP_FIELDS.contains(&key.as_str()),
+                "CGROUP_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["max_cpu"]) {
+            assert!(
+                MAX_CPU_FIELDS.contains(&key.as_str()),
+                "MAX_CPU_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["restart"]["backoff"]) {
+            assert!(
+                BACKOFF_FIELDS.contains(&key.as_str()),
+                "BACKOFF_FIELDS lacks {}",
+                key
+            );
+        }
+        for key in keys(&value["healthcheck"]) {
+            assert!(
+                HEALTHCHECK_FIELDS.contains(&key.as_str()),
+                "HEALTHCHECK_FIELDS lacks {}",
+                key
+            );
+        }
+    }
+}
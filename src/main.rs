@@ -1,6 +1,13 @@
 use clap::Parser;
+use std::path::PathBuf;
 
-use crate::communication::common::Command;
+use crate::communication::common::{
+    AdHocApp, Command, ExportRequest, ImportRequest, ReloadConfigRequest, ReloadRequest,
+    StartRequest,
+};
+use crate::config::import::ImportSource;
+use crate::config::procfile::Formation;
+use crate::config::read_config::ConfigFormat;
 mod communication;
 mod config;
 mod error;
@@ -23,17 +30,43 @@ enum CliCommands {
     List,
     /// Show status of a specific process
     Status { name: String },
-    /// Start a process from config file or by name
-    Start { payload: String },
-    /// Stop a running process
+    /// Start a process from a config file, a directory of configs, or by name
+    /// (or `project:<name>`). With `-- <command>`, run a command without a
+    /// config file.
+    Start {
+        #[arg(required_unless_present = "command", conflicts_with = "command")]
+        payload: Option<String>,
+        /// Env profile to apply (the app's `env_<profile>` block), or
+        /// KEY=VALUE env vars for a command
+        #[arg(long = "env")]
+        env: Vec<String>,
+        /// Instance counts per app, like foreman: `web=2,worker=3`, with
+        /// `all=<n>` for the rest and 0 to leave an app out
+        #[arg(long, conflicts_with = "command")]
+        formation: Option<String>,
+        /// Name of the app started from a command (defaults to the program's
+        /// file name)
+        #[arg(long, requires = "command")]
+        name: Option<String>,
+        /// Working directory of the command (defaults to the current one)
+        #[arg(long, requires = "command")]
+        cwd: Option<PathBuf>,
+        /// Restart policy of the command
+        #[arg(long, requires = "command", value_parser = ["always", "on-failure", "never"])]
+        restart: Option<String>,
+        /// Command to run, after `--`
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+    /// Stop a running process (or every process of `project:<name>`)
     Stop { name: String },
     /// Enable a process (add to managed list)
     Enable { payload: String },
     /// Disable a process (remove from managed list but don't stop)
     Disable { payload: String },
-    /// Delete a process (stop and remove)
+    /// Delete a process (stop and remove), or `project:<name>`
     Delete { payload: String },
-    /// View logs for a process
+    /// View logs for a process (or `project:<name>`)
     Logs {
         name: String,
         /// Number of lines to show
@@ -43,16 +76,108 @@ enum CliCommands {
         #[arg(short, long)]
         follow: bool,
     },
-    /// Restart a process
+    /// Restart a process (or every process of `project:<name>`)
     Restart { name: String },
+    /// Replace a process without downtime: start a new one, and stop the old
+    /// one once the new one is ready. Instances of an app are replaced in
+    /// batches; `project:<name>` reloads every app of a project.
+    Reload {
+        name: String,
+        /// Instances replaced at a time (defaults to the app's
+        /// `reload.batch_size`)
+        #[arg(long)]
+        batch_size: Option<u32>,
+    },
     /// Flush logs for a process
     Flush { name: Option<String> },
+    /// Clear a process's restart and crash counters (or those of every
+    /// process of `project:<name>`)
+    Reset { name: String },
     /// Save current process list
     Save,
     /// Resurrect saved processes
     Resurrect,
     /// Generate startup script
     Startup,
+    /// Run a different number of instances of an app with `instances`
+    Scale { name: String, count: u32 },
+    /// Re-read configs from disk and restart apps whose spawn settings
+    /// changed. Takes an app, `project:<name>`, or a config file or directory;
+    /// reloads everything when omitted.
+    ReloadConfig { target: Option<String> },
+    /// Check a config file (or directory) for errors without starting anything
+    Validate { file: PathBuf },
+    /// Print the JSON Schema of the config format, for editor integration
+    Schema,
+    /// Write a process's app out as a config file
+    Export {
+        name: String,
+        /// File to write; its extension picks JSON, YAML or TOML. Prints
+        /// JSON when omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert another process manager's config into bpm configs
+    Import {
+        #[command(subcommand)]
+        source: ImportCommands,
+    },
+    /// Inspect app configs
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print an app's fully merged config (defaults, extends, env files and
+    /// variables applied)
+    Show {
+        name: String,
+        /// Read the app from this config file instead of asking the daemon
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Env profile to apply when reading from a file
+        #[arg(long = "env", requires = "file")]
+        env: Option<String>,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ImportCommands {
+    /// Import a PM2 ecosystem file (JSON or YAML) or a `pm2 save` dump
+    Pm2 {
+        /// File to import (defaults to ~/.pm2/dump.pm2)
+        file: Option<PathBuf>,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Import the [program:x] sections of a supervisord.conf (following its
+    /// [include]) or of every .conf/.ini file in a directory like conf.d
+    Supervisord {
+        file: PathBuf,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct ImportOptions {
+    /// Write one config file per app into this directory instead of printing
+    /// them
+    #[arg(short, long)]
+    out_dir: Option<PathBuf>,
+    /// Format of the configs
+    #[arg(long, default_value = "yaml", value_parser = ["json", "yaml", "toml"])]
+    format: String,
+    /// Start the apps: from the written files with --out-dir, otherwise
+    /// registered directly without config files
+    #[arg(long)]
+    start: bool,
+    /// Env profile to start the apps with
+    #[arg(long = "env", requires = "start")]
+    env: Option<String>,
 }
 
 fn main() {
@@ -64,8 +189,23 @@ fn main() {
         CliCommands::Status { name } => {
             communication::client::run_client(Command::new_status(&name))
         }
-        CliCommands::Start { payload } => {
-            communication::client::run_client(Command::new_start(&payload))
+        CliCommands::Start {
+            payload,
+            env,
+            formation,
+            name,
+            cwd,
+            restart,
+            command,
+        } => {
+            let request = if command.is_empty() {
+                start_target(payload.unwrap_or_default(), env, formation)
+            } else {
+                start_command(command, name, cwd, restart, env)
+            };
+            request.and_then(|request| {
+                communication::client::run_client(Command::new_start(&request))
+            })
         }
         CliCommands::Stop { name } => communication::client::run_client(Command::new_stop(&name)),
         CliCommands::Enable { payload } => {
@@ -88,10 +228,18 @@ fn main() {
         CliCommands::Restart { name } => {
             communication::client::run_client(Command::new_restart(&name))
         }
+        CliCommands::Reload { name, batch_size } => {
+            let request = ReloadRequest {
+                target: name,
+                batch_size,
+            };
+            communication::client::run_client(Command::new_reload(&request))
+        }
         CliCommands::Flush { name } => {
             let payload = name.unwrap_or_default();
             communication::client::run_client(Command::new_flush(&payload))
         }
+        CliCommands::Reset { name } => communication::client::run_client(Command::new_reset(&name)),
         CliCommands::Save => communication::client::run_client(Command::Save),
         CliCommands::Resurrect => communication::client::run_client(Command::Resurrect),
         CliCommands::Startup => {
@@ -104,6 +252,58 @@ fn main() {
                 Err(e) => Err(e),
             }
         }
+        CliCommands::Validate { file } => {
+            // Validate locally, no daemon needed
+            let report = config::validate::validate_file(&file);
+            print!("{}", report);
+            if report.has_errors() {
+                Err(format!("{} has {} error(s)", file.display(), report.error_count()).into())
+            } else {
+                println!("{} is valid", file.display());
+                Ok(())
+            }
+        }
+        CliCommands::Schema => {
+            // Generated locally, no daemon needed
+            serde_json::to_string_pretty(&config::schema::generate())
+                .map(|schema| println!("{}", schema))
+                .map_err(|e| e.into())
+        }
+        CliCommands::Export { name, output } => {
+            // The daemon writes the file, so send an absolute path
+            let output = output.map(|path| std::path::absolute(&path).unwrap_or(path));
+            let request = ExportRequest { name, output };
+            communication::client::run_client(Command::new_export(&request))
+        }
+        CliCommands::Scale { name, count } => {
+            let payload = format!("{}:{}", name, count);
+            communication::client::run_client(Command::new_scale(&payload))
+        }
+        CliCommands::ReloadConfig { target } => {
+            let target = target.unwrap_or_default();
+            let request = ReloadConfigRequest {
+                path: absolute_path(&target),
+                target,
+            };
+            communication::client::run_client(Command::new_reload_config(&request))
+        }
+        CliCommands::Import {
+            source: ImportCommands::Pm2 { file, options },
+        } => match file.or_else(config::import::pm2::default_dump_path) {
+            Some(file) => import_configs(ImportSource::Pm2, &file, options),
+            None => Err("no file given and no home directory to find a PM2 dump in".into()),
+        },
+        CliCommands::Import {
+            source: ImportCommands::Supervisord { file, options },
+        } => import_configs(ImportSource::Supervisord, &file, options),
+        CliCommands::Config {
+            action: ConfigCommands::Show { name, file, env },
+        } => match file {
+            Some(file) => config::merge::show_app(&file, &name, env.as_deref())
+                .map(|output| println!("{}", output))
+                .map_err(|e| e.into()),
+            None => communication::client::run_client(Command::new_config_show(&name)),
+        },
     };
 
     if let Err(e) = result {
@@ -111,3 +311,123 @@ fn main() {
         std::process::exit(1);
     }
 }
+
+/// Start request for a config file, directory or registered process
+fn start_target(
+    payload: String,
+    env: Vec<String>,
+    formation: Option<String>,
+) -> Result<StartRequest, Box<dyn std::error::Error>> {
+    if env.len() > 1 || env.iter().any(|e| e.contains('=')) {
+        return Err("--env takes one profile name; KEY=VALUE only works with `-- <command>`".into());
+    }
+    if let Some(formation) = &formation {
+        formation.parse::<Formation>()?;
+    }
+
+    Ok(StartRequest {
+        path: absolute_path(&payload),
+        target: payload,
+        env: env.into_iter().next(),
+        formation,
+        command: None,
+    })
+}
+
+/// The target as an absolute path if it exists under the working directory.
+/// The daemon has its own working directory, so it needs this to find a
+/// config path, but a process name that happens to match a file here is
+/// still sent as given and the daemon looks it up first.
+fn absolute_path(target: &str) -> Option<String> {
+    std::fs::canonicalize(target)
+        .ok()
+        .map(|path| path.display().to_string())
+}
+
+/// Start request for a command run without a config file
+fn start_command(
+    command: Vec<String>,
+    name: Option<String>,
+    cwd: Option<PathBuf>,
+    restart: Option<String>,
+    env: Vec<String>,
+) -> Result<StartRequest, Box<dyn std::error::Error>> {
+    let env = env
+        .iter()
+        .map(|var| {
+            var.split_once('=')
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or_else(|| format!("--env '{}' is not KEY=VALUE", var))
+        })
+        .collect::<Result<_, _>>()?;
+    let name = name.unwrap_or_else(|| {
+        std::path::Path::new(&command[0])
+            .file_name()
+            .map_or(command[0].clone(), |n| n.to_string_lossy().to_string())
+    });
+    let cwd = std::fs::canonicalize(cwd.unwrap_or_else(|| PathBuf::from(".")))?;
+
+    Ok(StartRequest {
+        command: Some(AdHocApp {
+            name,
+            command,
+            cwd: Some(cwd),
+            env,
+            restart,
+        }),
+        ..StartRequest::default()
+    })
+}
+
+/// Convert a file of another process manager, then print the configs, write
+/// them out or start the apps
+fn import_configs(
+    source: ImportSource,
+    file: &std::path::Path,
+    options: ImportOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::path::absolute(file)?;
+    if options.start && options.out_dir.is_none() {
+        // The daemon reads the file and reports on it itself
+        let request = ImportRequest {
+            source,
+            path: file,
+            env: options.env,
+        };
+        return communication::client::run_client(Command::new_import(&request));
+    }
+
+    let import = config::import::read(source, &file)?;
+    let format = match options.format.as_str() {
+        "json" => ConfigFormat::Json,
+        "toml" => ConfigFormat::Toml,
+        _ => ConfigFormat::Yaml,
+    };
+    let Some(out_dir) = options.out_dir else {
+        println!("{}", config::import::render(source, &import.apps, format)?);
+        eprintln!("{}", import.report());
+        return Ok(());
+    };
+
+    let paths = config::import::write_configs(&import.apps, &std::path::absolute(out_dir)?, format)?;
+    println!("{}", import.report());
+    for path in &paths {
+        println!("Wrote {}", path.display());
+    }
+    if options.start {
+        for (app, path) in import.apps.iter().zip(paths) {
+            // Only apps that had the profile in the source get it
+            let env = options
+                .env
+                .clone()
+                .filter(|profile| app.env_profiles.contains_key(profile));
+            let request = StartRequest {
+                target: path.display().to_string(),
+                env,
+                ..StartRequest::default()
+            };
+            communication::client::run_client(Command::new_start(&request))?;
+        }
+    }
+    Ok(())
+}
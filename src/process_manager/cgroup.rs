@@ -0,0 +1,395 @@
+//! cgroup v2 control and accounting
+//!
+//! When the daemon can manage a cgroup v2 subtree, each app runs in a
+//! cgroup of its own below it. The app's `cgroup` settings are written to
+//! the group's control files, and its CPU and memory usage are read from
+//! the group, so they cover every process the app started, including ones
+//! that have already exited. A reload's new process gets a cgroup of its
+//! own, so the two processes aren't counted against one limit, and keeps it
+//! once the old process is gone; cgroup v2 can't rename a cgroup.
+//!
+//! A daemon in the root cgroup, as root, puts apps under `bpm-<id>`. One
+//! started in a cgroup delegated to it, such as a systemd unit with
+//! `Delegate=yes`, moves itself into a `daemon` leaf and puts apps under
+//! `apps`, since cgroups with controllers can't hold processes themselves.
+//! Anywhere else apps run in the daemon's cgroup, as they always did.
+
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::read_config::CgroupConfig;
+
+/// Controllers enabled for apps, where the host has them
+const CONTROLLERS: [&str; 4] = ["cpu", "memory", "pids", "io"];
+
+/// Period of `cpu.max`, in microseconds
+const CPU_PERIOD: u64 = 100_000;
+
+static SUBTREE: Lazy<Result<PathBuf, String>> = Lazy::new(|| {
+    let subtree = detect();
+    match &subtree {
+        Ok(path) => println!("Apps run in cgroups under {}", path.display()),
+        Err(reason) => println!("Apps share the daemon's cgroup: {}", reason),
+    }
+    subtree
+});
+
+/// The directory app cgroups are created in, or why there is none
+pub fn subtree() -> Result<&'static Path, &'static str> {
+    match &*SUBTREE {
+        Ok(path) => Ok(path),
+        Err(reason) => Err(reason),
+    }
+}
+
+fn detect() -> Result<PathBuf, String> {
+    let mount = cgroup2_mount().ok_or("no cgroup v2 hierarchy is mounted")?;
+    let own = std::fs::read_to_string("/proc/self/cgroup")
+        .ok()
+        .and_then(|cgroups| {
+            cgroups
+                .lines()
+                .find_map(|line| line.strip_prefix("0::").map(str::to_string))
+        })
+        .ok_or("the daemon isn't in a cgroup v2 group")?;
+    let mut base = mount.join(own.trim_start_matches('/'));
+
+    if own == "/" {
+        // The root cgroup may hold processes, so apps go right below it
+        let subtree = base.join(format!("bpm-{}", daemon_id()));
+        enable_controllers(&base);
+        create(&subtree)?;
+        enable_controllers(&subtree);
+        return Ok(subtree);
+    }
+
+    // A daemon that moved into its leaf before finds its subtree above it
+    if base.ends_with("daemon") && base.with_file_name("apps").is_dir() {
+        base.pop();
+    }
+    let procs = std::fs::read_to_string(base.join("cgroup.procs")).map_err(|e| e.to_string())?;
+    let own_pid = std::process::id().to_string();
+    if procs.lines().any(|pid| pid != own_pid) {
+        return Err(format!(
+            "{} holds other processes too, so it wasn't delegated to bpm",
+            base.display()
+        ));
+    }
+    nix::unistd::access(&base, nix::unistd::AccessFlags::W_OK)
+        .map_err(|_| format!("{} isn't writable", base.display()))?;
+
+    let leaf = base.join("daemon");
+    create(&leaf)?;
+    write(&leaf, "cgroup.procs", &own_pid)?;
+    enable_controllers(&base);
+    let apps = base.join("apps");
+    create(&apps)?;
+    enable_controllers(&apps);
+    Ok(apps)
+}
+
+/// Where the cgroup v2 hierarchy is mounted, from /proc/self/mountinfo
+fn cgroup2_mount() -> Option<PathBuf> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mountinfo.lines().find_map(|line| {
+        let (mount, filesystem) = line.split_once(" - ")?;
+        filesystem
+            .starts_with("cgroup2 ")
+            .then(|| mount.split(' ').nth(4).map(PathBuf::from))?
+    })
+}
+
+/// Tells apart the subtrees of daemons with different data directories
+fn daemon_id() -> String {
+    let data_dir = dirs::data_local_dir().unwrap_or_default();
+    let digest = Sha256::digest(data_dir.as_os_str().as_bytes());
+    digest[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn create(dir: &Path) -> Result<(), String> {
+    match std::fs::create_dir(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+            Err(format!("failed to create {}: {}", dir.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Enable the controllers `dir` has for its children, one at a time so a
+/// missing one doesn't keep the rest off
+fn enable_controllers(dir: &Path) {
+    let available = std::fs::read_to_string(dir.join("cgroup.controllers")).unwrap_or_default();
+    for controller in CONTROLLERS {
+        if available.split_whitespace().any(|c| c == controller) {
+            let _ = write(dir, "cgroup.subtree_control", &format!("+{}", controller));
+        }
+    }
+}
+
+fn write(dir: &Path, file: &str, value: &str) -> Result<(), String> {
+    std::fs::write(dir.join(file), value).map_err(|e| format!("{}: {}", file, e))
+}
+
+fn read(dir: &Path, file: &str) -> Option<String> {
+    std::fs::read_to_string(dir.join(file)).ok()
+}
+
+/// The value of `key` in a flat keyed file like `cpu.stat`
+fn read_key(dir: &Path, file: &str, key: &str) -> Option<u64> {
+    read(dir, file)?.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Create the cgroup of app `name` and write its settings. Returns the
+/// cgroup, and the settings it couldn't take because the host lacks their
+/// controller.
+pub fn prepare(name: &str, config: &CgroupConfig) -> Result<(PathBuf, Vec<String>), String> {
+    let path = subtree()?.join(dir_name(name));
+    create(&path)?;
+    let warnings = configure(&path, config);
+    Ok((path, warnings))
+}
+
+/// Create a cgroup for a new process of app `name` that runs next to the
+/// current one while the app is reloaded, like `prepare`
+pub fn prepare_handover(
+    name: &str,
+    config: &CgroupConfig,
+) -> Result<(PathBuf, Vec<String>), String> {
+    // An escaped app name has no `%r`, so this can't be another app's
+    let subtree = subtree()?;
+    let mut number = 0;
+    let path = loop {
+        let path = subtree.join(format!("{}%reload{}", dir_name(name), number));
+        if !path.exists() {
+            break path;
+        }
+        number += 1;
+    };
+    create(&path)?;
+    let warnings = configure(&path, config);
+    Ok((path, warnings))
+}
+
+/// Cgroup names can't hold a `/`, which project apps have
+fn dir_name(name: &str) -> String {
+    name.replace('%', "%25").replace('/', "%2F")
+}
+
+/// Write `config` to the control files of the cgroup at `path`. Unset
+/// settings are reset, so settings removed from a config are lifted.
+pub fn configure(path: &Path, config: &CgroupConfig) -> Vec<String> {
+    let size = |size: Option<u64>| size.map_or("max".to_string(), |bytes| bytes.to_string());
+    let cpu_max = match config.cpu_max {
+        Some(cpus) => format!(
+            "{} {}",
+            (cpus * CPU_PERIOD as f64).round() as u64,
+            CPU_PERIOD
+        ),
+        None => format!("max {}", CPU_PERIOD),
+    };
+    let settings = [
+        (
+            "memory.max",
+            config.memory_max.is_some(),
+            size(config.memory_max),
+        ),
+        (
+            "memory.high",
+            config.memory_high.is_some(),
+            size(config.memory_high),
+        ),
+        ("cpu.max", config.cpu_max.is_some(), cpu_max),
+        ("pids.max", config.pids_max.is_some(), size(config.pids_max)),
+        (
+            "io.weight",
+            config.io_weight.is_some(),
+            format!("default {}", config.io_weight.unwrap_or(100)),
+        ),
+    ];
+
+    let mut warnings = Vec::new();
+    for (file, set, value) in settings {
+        // Control files only exist with their controller enabled
+        if !path.join(file).exists() {
+            if set {
+                let controller = file.split('.').next().unwrap_or(file);
+                warnings.push(format!(
+                    "cgroup {} not applied: the {} controller isn't available",
+                    file, controller
+                ));
+            }
+            continue;
+        }
+        if let Err(e) = write(path, file, &value) {
+            warnings.push(format!("cgroup {} not applied, {}", file, e));
+        }
+    }
+    warnings
+}
+
+/// Have `command` move itself into the cgroup at `path` before it execs,
+/// so everything it starts is in the cgroup from the beginning
+pub fn join<'a>(path: &Path, command: &'a mut Command) -> &'a mut Command {
+    let Ok(procs) = CString::new(path.join("cgroup.procs").as_os_str().as_bytes()) else {
+        return command;
+    };
+
+    // SAFETY: only async-signal-safe calls, on data prepared beforehand
+    unsafe {
+        command.pre_exec(move || {
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // Writing 0 moves the writing process
+            let written = libc::write(fd, c"0".as_ptr().cast(), 1);
+            let error = std::io::Error::last_os_error();
+            libc::close(fd);
+            if written < 0 {
+                return Err(error);
+            }
+            Ok(())
+        })
+    }
+}
+
+/// What the processes of a cgroup use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Usage {
+    /// CPU time of every process that has been in the cgroup, in
+    /// microseconds
+    pub cpu_usec: u64,
+    /// Anonymous memory of the cgroup, which like RSS leaves out the page
+    /// cache `memory.current` counts; `None` without the memory controller
+    pub memory: Option<u64>,
+    /// The processes in the cgroup now
+    pub pids: Vec<u32>,
+}
+
+pub fn usage(path: &Path) -> Option<Usage> {
+    let pids = procs(path)?;
+    Some(Usage {
+        cpu_usec: read_key(path, "cpu.stat", "usage_usec")?,
+        memory: read_key(path, "memory.stat", "anon"),
+        pids,
+    })
+}
+
+/// The processes in the cgroup at `path`, `None` if it can't be read
+pub fn procs(path: &Path) -> Option<Vec<u32>> {
+    let procs = read(path, "cgroup.procs")?;
+    Some(procs.lines().filter_map(|pid| pid.parse().ok()).collect())
+}
+
+/// How many times the OOM killer has killed a process of the cgroup
+pub fn oom_kills(path: &Path) -> u64 {
+    read_key(path, "memory.events", "oom_kill").unwrap_or(0)
+}
+
+/// Remove the cgroup at `path` once it has no processes left
+pub fn remove(path: &Path) {
+    let _ = std::fs::remove_dir(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config::StopStep;
+    use crate::process_manager::{process, reaper, stop};
+
+    #[test]
+    fn test_app_in_its_own_cgroup() {
+        // Only where this host lets the daemon manage cgroups
+        let Ok(subtree) = subtree() else {
+            return;
+        };
+
+        let name = format!("test/cgroup-{}", std::process::id());
+        let (path, _) = prepare(&name, &CgroupConfig::default()).unwrap();
+        assert_eq!(path.parent(), Some(subtree));
+        assert!(path.ends_with(format!("test%2Fcgroup-{}", std::process::id())));
+
+        // A child the app starts is counted too, as is CPU time of exited ones
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            "i=0; while [ $i -lt 20000 ]; do i=$((i+1)); done; sleep 5 & wait",
+        ]);
+        process::in_own_session(&mut command);
+        let mut child = reaper::spawn_app(join(&path, &mut command)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        let usage = usage(&path).unwrap();
+        let _ = nix::sys::signal::killpg(
+            nix::unistd::Pid::from_raw(child.id() as i32),
+            nix::sys::signal::Signal::SIGKILL,
+        );
+        let _ = child.kill();
+        let _ = child.wait();
+
+        assert!(usage.pids.contains(&child.id()), "{:?}", usage);
+        assert_eq!(usage.pids.len(), 2, "{:?}", usage);
+        assert!(usage.cpu_usec > 0);
+
+        for _ in 0..50 {
+            if usage_pids(&path).is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        remove(&path);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    // `stop` reaps the child it stops
+    #[allow(clippy::zombie_processes)]
+    fn test_stop_reaches_processes_only_the_cgroup_ties_to_the_app() {
+        let Ok(_) = subtree() else {
+            return;
+        };
+        let name = format!("test/escaped-{}", std::process::id());
+        let (path, _) = prepare(&name, &CgroupConfig::default()).unwrap();
+
+        // A grandchild that leaves the app's session and is re-parented
+        // once its parent exits
+        let mut command = Command::new("sh");
+        command.args(["-c", "sh -c 'setsid sleep 30 & exit 0'; exec sleep 30"]);
+        process::in_own_session(&mut command);
+        let child = reaper::spawn_app(join(&path, &mut command)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        assert_eq!(usage_pids(&path).len(), 2);
+
+        stop::stop(child.id(), Some(&path), &[StopStep::default()]).unwrap();
+        assert!(usage_pids(&path).is_empty());
+        remove(&path);
+        assert!(!path.exists());
+    }
+
+    fn usage_pids(path: &Path) -> Vec<u32> {
+        usage(path).map(|usage| usage.pids).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_read_key() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("memory.events"),
+            "low 0\nhigh 3\nmax 9\noom 2\noom_kill 1\n",
+        )
+        .unwrap();
+        assert_eq!(oom_kills(dir.path()), 1);
+        assert_eq!(read_key(dir.path(), "memory.events", "high"), Some(3));
+        assert_eq!(oom_kills(&dir.path().join("missing")), 0);
+    }
+}
@@ -0,0 +1,281 @@
+//! Config Diff
+//!
+//! Compares a registered process with what its config file describes now,
+//! so `bpm reload-config` only restarts apps whose spawn settings changed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::config::read_config::{format_duration, format_size};
+use crate::process_manager::registry::ProcessInfo;
+
+/// One setting that differs between the registered process and its config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub field: &'static str,
+    pub detail: String,
+    /// Whether the running process must be restarted to pick it up
+    pub needs_restart: bool,
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.detail)
+    }
+}
+
+/// List the settings that differ between `old` and `new`
+pub fn diff(old: &ProcessInfo, new: &ProcessInfo) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut change = |field, detail, needs_restart| {
+        changes.push(Change {
+            field,
+            detail,
+            needs_restart,
+        })
+    };
+
+    // Settings the process is spawned with
+    if old.script != new.script {
+        change("script", format!("{} -> {}", old.script, new.script), true);
+    }
+    if old.args != new.args {
+        change("args", format!("{:?} -> {:?}", old.args, new.args), true);
+    }
+    if old.cwd != new.cwd {
+        change("cwd", format!("{:?} -> {:?}", old.cwd, new.cwd), true);
+    }
+    if old.env != new.env {
+        change("env", key_diff(&old.env, &new.env), true);
+    }
+    if old.stdout_log != new.stdout_log {
+        let detail = format!(
+            "{} -> {}",
+            old.stdout_log.display(),
+            new.stdout_log.display()
+        );
+        change("log.out", detail, true);
+    }
+    if old.env_from_file != new.env_from_file {
+        let detail = format!("{:?} -> {:?}", old.env_from_file, new.env_from_file);
+        change("env_from_file", detail, true);
+    }
+    if old.secrets != new.secrets {
+        change("secrets", key_diff(&old.secrets, &new.secrets), true);
+    }
+    if old.stderr_log != new.stderr_log {
+        let detail = format!(
+            "{} -> {}",
+            old.stderr_log.display(),
+            new.stderr_log.display()
+        );
+        change("log.error", detail, true);
+    }
+    if old.run_as != new.run_as {
+        let detail = format!("{} -> {}", old.run_as, new.run_as);
+        change("user", detail, true);
+    }
+    if old.limits != new.limits {
+        let detail = key_diff(&set_fields(&old.limits), &set_fields(&new.limits));
+        change("limits", detail, true);
+    }
+
+    // Settings the daemon applies to a running process
+    if old.cgroup != new.cgroup {
+        let detail = key_diff(&set_fields(&old.cgroup), &set_fields(&new.cgroup));
+        change("cgroup", detail, false);
+    }
+    if old.auto_restart != new.auto_restart {
+        let detail = format!("{} -> {}", old.auto_restart, new.auto_restart);
+        change("auto_restart", detail, false);
+    }
+    for (field, old_codes, new_codes) in [
+        (
+            "restart.success_exit_codes",
+            &old.restart.success_exit_codes,
+            &new.restart.success_exit_codes,
+        ),
+        (
+            "restart.restart_on_exit_codes",
+            &old.restart.restart_on_exit_codes,
+            &new.restart.restart_on_exit_codes,
+        ),
+    ] {
+        if old_codes != new_codes {
+            change(field, format!("{:?} -> {:?}", old_codes, new_codes), false);
+        }
+    }
+    if old.restart.max_restarts != new.restart.max_restarts {
+        let detail = format!(
+            "{} -> {}",
+            old.restart.max_restarts, new.restart.max_restarts
+        );
+        change("restart.max_restarts", detail, false);
+    }
+    if old.restart.restart_delay != new.restart.restart_delay {
+        let detail = format!(
+            "{} -> {}",
+            format_duration(old.restart.restart_delay),
+            format_duration(new.restart.restart_delay)
+        );
+        change("restart.restart_delay", detail, false);
+    }
+    if old.restart.backoff != new.restart.backoff {
+        let detail = match (&old.restart.backoff, &new.restart.backoff) {
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            _ => "changed",
+        };
+        change("restart.backoff", detail.to_string(), false);
+    }
+    if old.crash_loop != new.crash_loop {
+        let describe = |p: &ProcessInfo| {
+            format!(
+                "{} in {}, {} cooldown",
+                p.crash_loop.max_crashes,
+                format_duration(p.crash_loop.window),
+                format_duration(p.crash_loop.cooldown)
+            )
+        };
+        let detail = format!("{} -> {}", describe(old), describe(new));
+        change("crash_loop", detail, false);
+    }
+    if old.reload != new.reload {
+        let detail = key_diff(&set_fields(&old.reload), &set_fields(&new.reload));
+        change("reload", detail, false);
+    }
+    if old.max_memory != new.max_memory {
+        let describe = |p: &ProcessInfo| match p.max_memory {
+            0 => "none".to_string(),
+            bytes => format_size(bytes),
+        };
+        let detail = format!("{} -> {}", describe(old), describe(new));
+        change("max_memory_restart", detail, false);
+    }
+    if old.max_cpu != new.max_cpu {
+        let describe = |p: &ProcessInfo| p.max_cpu.map_or("none".to_string(), |c| c.to_string());
+        let detail = format!("{} -> {}", describe(old), describe(new));
+        change("max_cpu", detail, false);
+    }
+    if old.healthcheck != new.healthcheck {
+        let detail = match (&old.healthcheck, &new.healthcheck) {
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            _ => "changed",
+        };
+        change("healthcheck", detail.to_string(), false);
+    }
+    if old.watch_dirs != new.watch_dirs {
+        let detail = format!("{:?} -> {:?}", old.watch_dirs, new.watch_dirs);
+        change("watch", detail, false);
+    }
+    if old.stop_sequence != new.stop_sequence {
+        let steps = |p: &ProcessInfo| {
+            p.stop_sequence
+                .iter()
+                .map(|step| step.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let detail = format!("[{}] -> [{}]", steps(old), steps(new));
+        change("stop", detail, false);
+    }
+    if old.depends_on != new.depends_on {
+        let names = |p: &ProcessInfo| {
+            p.depends_on
+                .iter()
+                .map(|d| d.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        let detail = format!("{:?} -> {:?}", names(old), names(new));
+        change("depends_on", detail, false);
+    }
+
+    changes
+}
+
+/// The fields of a settings block that are set, by name
+fn set_fields(settings: &impl serde::Serialize) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(settings) {
+        Ok(serde_json::Value::Object(fields)) => fields
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .collect(),
+        _ => serde_json::Map::new(),
+    }
+}
+
+/// Summarize changes to a keyed setting (env vars, secret sources) by key
+/// only, since values may hold secrets
+fn key_diff<'a, V: PartialEq + 'a>(
+    old: impl IntoIterator<Item = (&'a String, &'a V)>,
+    new: impl IntoIterator<Item = (&'a String, &'a V)>,
+) -> String {
+    let old: BTreeMap<&String, &V> = old.into_iter().collect();
+    let new: BTreeMap<&String, &V> = new.into_iter().collect();
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).copied().collect();
+
+    keys.into_iter()
+        .filter_map(|key| match (old.get(key), new.get(key)) {
+            (None, Some(_)) => Some(format!("+{}", key)),
+            (Some(_), None) => Some(format!("-{}", key)),
+            (Some(a), Some(b)) if a != b => Some(format!("~{}", key)),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config::App;
+    use std::path::PathBuf;
+
+    fn process(json: &str) -> ProcessInfo {
+        let app: App = serde_json::from_str(json).unwrap();
+        ProcessInfo::from_app(&app, PathBuf::from("/tmp/app.json"))
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let json = r#"{"name": "api", "script": "node", "env": {"A": "1"}}"#;
+        assert!(diff(&process(json), &process(json)).is_empty());
+    }
+
+    #[test]
+    fn test_spawn_changes_need_restart() {
+        let old = process(
+            r#"{"name": "api", "script": "node", "args": ["a.js"], "env": {"A": "1", "B": "2"}, "limits": {"nofile": 1024}}"#,
+        );
+        let new = process(
+            r#"{"name": "api", "script": "node", "args": ["b.js"], "env": {"A": "9", "C": "3"}, "limits": {"nofile": 4096, "nice": 5}}"#,
+        );
+
+        let changes = diff(&old, &new);
+        let lines: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"args: ["a.js"] -> ["b.js"]"#.to_string(),
+                "env: ~A -B +C".to_string(),
+                "limits: +nice ~nofile".to_string(),
+            ]
+        );
+        assert!(changes.iter().all(|c| c.needs_restart));
+    }
+
+    #[test]
+    fn test_daemon_settings_apply_without_restart() {
+        let old = process(r#"{"name": "api", "script": "node"}"#);
+        let new = process(
+            r#"{"name": "api", "script": "node", "restart": {"policy": "never"}, "healthcheck": {"type": "tcp", "port": 80}, "max_memory_restart": "512M", "max_cpu": {"percent": 90, "for": "5m"}}"#,
+        );
+
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 4);
+        assert!(changes
+            .iter()
+            .any(|c| c.to_string() == "max_cpu: none -> 90% for 5m"));
+        assert!(changes.iter().all(|c| !c.needs_restart));
+    }
+}
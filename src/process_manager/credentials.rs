@@ -0,0 +1,346 @@
+//! Running apps as another user
+//!
+//! An app's `user`, `group`, `supplementary_groups`, `umask` and
+//! `ambient_capabilities` are looked up in the daemon before it spawns the
+//! app, so an unknown user, or a daemon without the privileges to switch to
+//! it, fails the start with a clear error. The child applies them between
+//! fork and exec.
+
+use nix::sys::stat::Mode;
+use nix::unistd::{Gid, Group, Uid, User};
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fmt;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use crate::config::read_config::{parse_capability_str, App};
+
+/// The credentials an app asks to run with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunAs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub supplementary_groups: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub umask: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ambient_capabilities: Vec<String>,
+}
+
+impl fmt::Display for RunAs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        match (&self.user, &self.group) {
+            (Some(user), Some(group)) => parts.push(format!("{}:{}", user, group)),
+            (Some(user), None) => parts.push(user.clone()),
+            (None, Some(group)) => parts.push(format!(":{}", group)),
+            (None, None) => parts.push("daemon's user".to_string()),
+        }
+        if !self.supplementary_groups.is_empty() {
+            parts.push(format!("+{}", self.supplementary_groups.join(",")));
+        }
+        if let Some(umask) = self.umask {
+            parts.push(format!("umask {:04o}", umask));
+        }
+        if !self.ambient_capabilities.is_empty() {
+            parts.push(self.ambient_capabilities.join(","));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl RunAs {
+    pub fn from_app(app: &App) -> Self {
+        Self {
+            user: app.user.clone(),
+            group: app.group.clone(),
+            supplementary_groups: app.supplementary_groups.clone(),
+            umask: app.umask,
+            ambient_capabilities: app.ambient_capabilities.clone(),
+        }
+    }
+
+    /// Look up the user and groups, checking the daemon is allowed to
+    /// switch to them
+    pub fn resolve(&self) -> Result<Credentials, String> {
+        self.resolve_for(Uid::effective())
+    }
+
+    fn resolve_for(&self, daemon: Uid) -> Result<Credentials, String> {
+        let root = daemon.is_root();
+        let user = self.user.as_deref().map(lookup_user).transpose()?;
+        if let Some(user) = &user {
+            if !root && user.uid != daemon {
+                return Err(format!(
+                    "can't run as user '{}': the daemon runs as uid {} and only root can switch users",
+                    user.name, daemon
+                ));
+            }
+        }
+
+        let gid = match &self.group {
+            Some(group) => Some(lookup_group(group)?),
+            None => user.as_ref().map(|user| user.gid),
+        };
+        if let (Some(gid), false) = (gid, root) {
+            let own_groups = nix::unistd::getgroups().unwrap_or_default();
+            if gid != Gid::effective() && !own_groups.contains(&gid) {
+                return Err(format!(
+                    "can't run as group {}: the daemon isn't a member and doesn't run as root",
+                    gid
+                ));
+            }
+        }
+
+        let supplementary = self
+            .supplementary_groups
+            .iter()
+            .map(|group| lookup_group(group))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !root && !supplementary.is_empty() {
+            return Err("supplementary_groups need the daemon to run as root".to_string());
+        }
+        // Root switching users takes on the user's groups, as a login would
+        let groups = match (&user, root) {
+            (Some(user), true) => {
+                let name = CString::new(user.name.as_str()).map_err(|e| e.to_string())?;
+                let mut groups = nix::unistd::getgrouplist(&name, gid.unwrap_or(user.gid))
+                    .map_err(|e| format!("failed to list groups of '{}': {}", user.name, e))?;
+                groups.extend(supplementary);
+                groups.sort_by_key(|gid| gid.as_raw());
+                groups.dedup();
+                Some(groups)
+            }
+            _ if !supplementary.is_empty() => Some(supplementary),
+            _ => None,
+        };
+
+        let capabilities = self
+            .ambient_capabilities
+            .iter()
+            .map(|name| parse_capability_str(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !root && !capabilities.is_empty() {
+            return Err("ambient_capabilities need the daemon to run as root".to_string());
+        }
+
+        Ok(Credentials {
+            user,
+            gid,
+            groups,
+            umask: self
+                .umask
+                .map(|mask| Mode::from_bits_truncate(mask as libc::mode_t)),
+            capabilities,
+        })
+    }
+}
+
+/// A user by name or uid
+pub(crate) fn lookup_user(name: &str) -> Result<User, String> {
+    let found = match name.parse::<u32>() {
+        Ok(uid) => User::from_uid(Uid::from_raw(uid)),
+        Err(_) => User::from_name(name),
+    };
+    found
+        .map_err(|e| format!("failed to look up user '{}': {}", name, e))?
+        .ok_or_else(|| format!("unknown user '{}'", name))
+}
+
+/// A group's gid, by name or gid
+pub(crate) fn lookup_group(name: &str) -> Result<Gid, String> {
+    let found = match name.parse::<u32>() {
+        Ok(gid) => Group::from_gid(Gid::from_raw(gid)),
+        Err(_) => Group::from_name(name),
+    };
+    found
+        .map_err(|e| format!("failed to look up group '{}': {}", name, e))?
+        .map(|group| group.gid)
+        .ok_or_else(|| format!("unknown group '{}'", name))
+}
+
+/// Credentials looked up and ready to apply in a forked child
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    user: Option<User>,
+    gid: Option<Gid>,
+    groups: Option<Vec<Gid>>,
+    umask: Option<Mode>,
+    capabilities: Vec<u32>,
+}
+
+impl Credentials {
+    /// HOME, USER and LOGNAME of the user the app runs as, if it switches
+    pub fn env(&self) -> Vec<(String, String)> {
+        let Some(user) = &self.user else {
+            return vec![];
+        };
+        vec![
+            ("HOME".to_string(), user.dir.display().to_string()),
+            ("USER".to_string(), user.name.clone()),
+            ("LOGNAME".to_string(), user.name.clone()),
+        ]
+    }
+
+    /// Have `command` take on these credentials before it execs
+    pub fn apply<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        let uid = self.user.as_ref().map(|user| user.uid);
+        let gid = self.gid;
+        let groups = self.groups.clone();
+        let umask = self.umask;
+        let capabilities = self.capabilities.clone();
+
+        // SAFETY: only async-signal-safe calls, on data prepared beforehand
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(mask) = umask {
+                    nix::sys::stat::umask(mask);
+                }
+                if let Some(groups) = &groups {
+                    nix::unistd::setgroups(groups)?;
+                }
+                if let Some(gid) = gid {
+                    nix::unistd::setgid(gid)?;
+                }
+                if let Some(uid) = uid {
+                    // Keep the permitted capabilities for the ambient set
+                    if !capabilities.is_empty() {
+                        nix::sys::prctl::set_keepcaps(true)?;
+                    }
+                    nix::unistd::setuid(uid)?;
+                }
+                raise_ambient(&capabilities)
+            })
+        }
+    }
+}
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+/// One half of the capability sets; the kernel reads and fills all three
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+#[allow(dead_code)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// Add `capabilities` to the inheritable and ambient sets, so they survive
+/// exec as a non-root user. Each must be in the permitted set.
+fn raise_ambient(capabilities: &[u32]) -> std::io::Result<()> {
+    if capabilities.is_empty() {
+        return Ok(());
+    }
+
+    let mut header = CapHeader {
+        version: CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapData::default(); 2];
+    // SAFETY: header and data are laid out as the kernel expects for v3
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    for capability in capabilities {
+        data[(capability / 32) as usize].inheritable |= 1 << (capability % 32);
+    }
+    // SAFETY: as above
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    for capability in capabilities {
+        let raise = libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong;
+        // SAFETY: prctl with plain integer arguments
+        let result = unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                raise,
+                *capability as libc::c_ulong,
+                0 as libc::c_ulong,
+                0 as libc::c_ulong,
+            )
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_as(user: &str) -> RunAs {
+        RunAs {
+            user: Some(user.to_string()),
+            ..RunAs::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_rejects_what_the_daemon_cannot_do() {
+        let error = run_as("no-such-user-here").resolve().unwrap_err();
+        assert_eq!(error, "unknown user 'no-such-user-here'");
+
+        let someone_else = Uid::from_raw(Uid::effective().as_raw().wrapping_add(1));
+        let error = run_as("root").resolve_for(someone_else).unwrap_err();
+        assert!(error.contains("only root can switch users"), "{}", error);
+
+        let caps = RunAs {
+            ambient_capabilities: vec!["CAP_NET_BIND_SERVICE".to_string()],
+            ..RunAs::default()
+        };
+        let error = caps.resolve_for(someone_else).unwrap_err();
+        assert!(
+            error.contains("need the daemon to run as root"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_apply_switches_user() {
+        // Switching users needs root
+        if !Uid::effective().is_root() {
+            return;
+        }
+        let Ok(Some(nobody)) = User::from_name("nobody") else {
+            return;
+        };
+
+        let run_as = RunAs {
+            umask: Some(0o027),
+            ambient_capabilities: vec!["CAP_NET_BIND_SERVICE".to_string()],
+            ..run_as("nobody")
+        };
+        let credentials = run_as.resolve().unwrap();
+        assert!(credentials
+            .env()
+            .contains(&("USER".to_string(), "nobody".to_string())));
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "id -u; id -g; umask; grep CapAmb /proc/self/status"]);
+        let output = credentials.apply(&mut command).output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines[0], nobody.uid.to_string());
+        assert_eq!(lines[1], nobody.gid.to_string());
+        assert_eq!(lines[2], "0027");
+        // CAP_NET_BIND_SERVICE is capability 10
+        assert!(lines[3].ends_with("0000000000000400"), "{}", lines[3]);
+    }
+}
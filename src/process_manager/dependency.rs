@@ -0,0 +1,298 @@
+//! App Dependencies
+//!
+//! Orders apps so that everything listed in `depends_on` starts first, and
+//! waits for a dependency to be running (or healthy) before its dependents
+//! are spawned. The waiting happens on a thread of its own, with the
+//! dependent left `Waiting` meanwhile.
+
+use chrono::Utc;
+use std::time::{Duration, Instant};
+
+use crate::config::read_config::{Dependency, DependencyCondition};
+use crate::process_manager::health::{check_health, HealthStatus};
+use crate::process_manager::registry::{ProcessInfo, ProcessRegistry, ProcessState};
+
+/// How often a dependency's state is polled while waiting for it
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long a dependency has to have been running to count as running
+const MIN_UPTIME: Duration = Duration::from_secs(2);
+
+/// Start order for a set of nodes given as `(name, dependency names)`.
+///
+/// Returns indices into `nodes` with every node after the nodes it depends
+/// on, keeping declaration order where the dependencies allow it. Several
+/// nodes may share a name (instances of one app); a dependency on that name
+/// waits for all of them. Dependencies outside the set are ignored. If the
+/// nodes contain a cycle, its names are returned, first name repeated last.
+pub fn start_order(nodes: &[(String, Vec<String>)]) -> Result<Vec<usize>, Vec<String>> {
+    let mut placed = vec![false; nodes.len()];
+    let mut order = Vec::with_capacity(nodes.len());
+
+    let is_ready = |placed: &[bool], deps: &[String]| {
+        deps.iter().all(|dep| {
+            nodes
+                .iter()
+                .zip(placed)
+                .all(|((name, _), placed)| name != dep || *placed)
+        })
+    };
+
+    while order.len() < nodes.len() {
+        let next = (0..nodes.len()).find(|&i| !placed[i] && is_ready(&placed, &nodes[i].1));
+        match next {
+            Some(i) => {
+                placed[i] = true;
+                order.push(i);
+            }
+            None => return Err(find_cycle(nodes, &placed)),
+        }
+    }
+
+    Ok(order)
+}
+
+/// Follow unplaced dependencies from the first unplaced node until a name
+/// repeats. Every unplaced node waits on another unplaced node, so this
+/// always ends in a cycle.
+fn find_cycle(nodes: &[(String, Vec<String>)], placed: &[bool]) -> Vec<String> {
+    let unplaced = |name: &str| (0..nodes.len()).find(|&i| !placed[i] && nodes[i].0 == name);
+
+    let mut path: Vec<String> = Vec::new();
+    let mut current = placed.iter().position(|p| !p);
+    while let Some(i) = current {
+        let name = &nodes[i].0;
+        if let Some(start) = path.iter().position(|n| n == name) {
+            let mut cycle = path.split_off(start);
+            cycle.push(name.clone());
+            return cycle;
+        }
+        path.push(name.clone());
+        current = nodes[i].1.iter().find_map(|dep| unplaced(dep));
+    }
+    path
+}
+
+/// Order registered processes so dependencies come first
+pub fn order_processes(processes: Vec<ProcessInfo>) -> Vec<ProcessInfo> {
+    let nodes: Vec<(String, Vec<String>)> = processes
+        .iter()
+        .map(|p| {
+            let deps = p.depends_on.iter().map(|d| d.name().to_string()).collect();
+            (p.app_name().to_string(), deps)
+        })
+        .collect();
+
+    // Cycles are rejected when the config is validated; keep the given
+    // order if one slipped through anyway
+    let Ok(order) = start_order(&nodes) else {
+        return processes;
+    };
+    let mut processes: Vec<Option<ProcessInfo>> = processes.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|i| processes[i].take())
+        .collect()
+}
+
+/// Whether every dependency of `process` meets its condition now. Fails
+/// if one is missing or stopped, so it won't without being started.
+pub fn dependencies_ready(
+    registry: &ProcessRegistry,
+    process: &ProcessInfo,
+) -> Result<bool, String> {
+    for dependency in &process.depends_on {
+        if !is_ready(registry, dependency)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Block until every dependency of `process` meets its condition. Returns
+/// false if `process` stops waiting first, because it was stopped or
+/// deleted in the meantime.
+pub fn wait_for_dependencies(
+    registry: &ProcessRegistry,
+    process: &ProcessInfo,
+) -> Result<bool, String> {
+    for dependency in &process.depends_on {
+        if !wait_for(registry, &process.name, dependency)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Block until a dependency meets its condition or its timeout runs out,
+/// as long as `waiting` is still waiting for it
+fn wait_for(
+    registry: &ProcessRegistry,
+    waiting: &str,
+    dependency: &Dependency,
+) -> Result<bool, String> {
+    let deadline = Instant::now() + dependency.timeout();
+    loop {
+        let still_waiting = registry
+            .get(waiting)
+            .is_some_and(|process| process.state == ProcessState::Waiting);
+        if !still_waiting {
+            return Ok(false);
+        }
+        if is_ready(registry, dependency)? {
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            let condition = match dependency.condition() {
+                DependencyCondition::Running => "running",
+                DependencyCondition::Healthy => "healthy",
+            };
+            return Err(format!(
+                "timed out after {:?} waiting for '{}' to be {}",
+                dependency.timeout(),
+                dependency.name(),
+                condition
+            ));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Whether a dependency (every instance of it, for clustered apps) meets
+/// its condition. A running one has to have stayed up for `MIN_UPTIME`,
+/// so one that crashes right after it is spawned doesn't count.
+fn is_ready(registry: &ProcessRegistry, dependency: &Dependency) -> Result<bool, String> {
+    let name = dependency.name();
+    let instances = match registry.get(name) {
+        Some(process) => vec![process],
+        None => registry.find_instances(name),
+    };
+    if instances.is_empty() {
+        return Err(format!("dependency '{}' is not registered", name));
+    }
+
+    let mut ready = true;
+    for process in &instances {
+        if matches!(process.state, ProcessState::Stopped | ProcessState::Errored) {
+            return Err(format!(
+                "dependency '{}' is {}",
+                process.name, process.state
+            ));
+        }
+        let uptime = process
+            .started_at
+            .and_then(|started| Utc::now().signed_duration_since(started).to_std().ok());
+        if process.state != ProcessState::Running || uptime.unwrap_or_default() < MIN_UPTIME {
+            ready = false;
+            continue;
+        }
+        if dependency.condition() == DependencyCondition::Healthy {
+            let Some(healthcheck) = &process.healthcheck else {
+                return Err(format!(
+                    "dependency '{}' has no healthcheck to wait for",
+                    process.name
+                ));
+            };
+            let status = check_health(healthcheck);
+            let _ = registry.update_health_status(&process.name, status.clone());
+            ready &= status == HealthStatus::Healthy;
+        }
+    }
+    Ok(ready)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config::App;
+    use serde_json::json;
+
+    fn nodes(spec: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+        spec.iter()
+            .map(|(name, deps)| {
+                let deps = deps.iter().map(|d| d.to_string()).collect();
+                (name.to_string(), deps)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_start_order_keeps_declaration_order() {
+        let nodes = nodes(&[
+            ("worker", &["api", "db"]),
+            ("api", &["db"]),
+            ("cron", &[]),
+            ("db", &["external"]),
+        ]);
+        assert_eq!(start_order(&nodes), Ok(vec![2, 3, 1, 0]));
+    }
+
+    #[test]
+    fn test_start_order_waits_for_all_instances() {
+        let nodes = nodes(&[("web", &["api"]), ("api", &[]), ("api", &[])]);
+        assert_eq!(start_order(&nodes), Ok(vec![1, 2, 0]));
+    }
+
+    #[test]
+    fn test_start_order_reports_cycle() {
+        let nodes = nodes(&[("cron", &[]), ("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+        assert_eq!(
+            start_order(&nodes),
+            Err(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "a".to_string()
+            ])
+        );
+    }
+
+    fn process(name: &str, depends_on: serde_json::Value) -> ProcessInfo {
+        let app: App = serde_json::from_value(json!({
+            "name": name,
+            "script": "sleep",
+            "depends_on": depends_on
+        }))
+        .unwrap();
+        ProcessInfo::from_command(&app)
+    }
+
+    #[test]
+    fn test_running_dependency_has_to_stay_up() {
+        let registry = ProcessRegistry::new();
+        let api = process("api", json!(["db"]));
+        let mut db = process("db", json!([]));
+        registry.register(db.clone()).unwrap();
+        assert!(dependencies_ready(&registry, &api).is_err());
+
+        db.state = ProcessState::Running;
+        db.pid = Some(1);
+        db.started_at = Some(Utc::now());
+        registry.remove("db");
+        registry.register(db.clone()).unwrap();
+        assert_eq!(dependencies_ready(&registry, &api), Ok(false));
+
+        db.started_at = Some(Utc::now() - chrono::Duration::seconds(5));
+        registry.remove("db");
+        registry.register(db).unwrap();
+        assert_eq!(dependencies_ready(&registry, &api), Ok(true));
+    }
+
+    #[test]
+    fn test_wait_ends_when_no_longer_waiting() {
+        let registry = ProcessRegistry::new();
+        let mut api = process("api", json!([{"name": "db", "timeout": "1s"}]));
+        let mut db = process("db", json!([]));
+        db.state = ProcessState::Backoff;
+        registry.register(db).unwrap();
+
+        // Stopped while waiting
+        registry.register(api.clone()).unwrap();
+        assert_eq!(wait_for_dependencies(&registry, &api), Ok(false));
+
+        api.state = ProcessState::Waiting;
+        registry.remove("api");
+        registry.register(api.clone()).unwrap();
+        let error = wait_for_dependencies(&registry, &api).unwrap_err();
+        assert!(error.starts_with("timed out after 1s"), "{}", error);
+    }
+}
@@ -16,7 +16,7 @@ pub enum HealthStatus {
 }
 
 /// Health check configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct HealthCheckConfig {
     pub check_type: HealthCheckType,
     pub interval: Duration,
@@ -25,7 +25,7 @@ pub struct HealthCheckConfig {
     pub start_period: Duration,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum HealthCheckType {
     Http {
         url: String,
@@ -41,6 +41,17 @@ pub enum HealthCheckType {
     },
 }
 
+impl HealthCheckType {
+    /// The TCP port the check connects to; None for a command
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            HealthCheckType::Http { url, .. } => Some(http_port(url)),
+            HealthCheckType::Tcp { port, .. } => Some(*port),
+            HealthCheckType::Command { .. } => None,
+        }
+    }
+}
+
 impl Default for HealthCheckConfig {
     fn default() -> Self {
         Self {
@@ -86,7 +97,7 @@ fn check_http(url: &str, timeout: Duration, expected_status: Option<u16>) -> Hea
         .split(':')
         .collect();
     let host = host_port.first().unwrap_or(&"localhost");
-    let port: u16 = host_port.get(1).and_then(|p| p.parse().ok()).unwrap_or(80);
+    let port = http_port(url);
 
     // First check if we can connect
     match TcpStream::connect_timeout(&format!("{}:{}", host, port).parse().unwrap(), timeout) {
@@ -141,6 +152,17 @@ fn check_http(url: &str, timeout: Duration, expected_status: Option<u16>) -> Hea
     }
 }
 
+/// The port of an HTTP check's URL, 80 if it has none
+fn http_port(url: &str) -> u16 {
+    url.trim_start_matches("http://")
+        .trim_start_matches("https://")
+        .split('/')
+        .next()
+        .and_then(|host_port| host_port.split(':').nth(1))
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(80)
+}
+
 /// TCP health check - just verifies the port is open
 fn check_tcp(host: &str, port: u16, timeout: Duration) -> HealthStatus {
     let addr = format!("{}:{}", host, port);
@@ -194,6 +216,21 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_check_port() {
+        let http = |url: &str| HealthCheckType::Http {
+            url: url.to_string(),
+            expected_status: None,
+        };
+        assert_eq!(http("http://localhost:3000/health").port(), Some(3000));
+        assert_eq!(http("http://localhost/health").port(), Some(80));
+        let command = HealthCheckType::Command {
+            cmd: "true".to_string(),
+            args: vec![],
+        };
+        assert_eq!(command.port(), None);
+    }
+
     #[test]
     fn test_command_true() {
         let status = check_command("true", &[], Duration::from_secs(5));
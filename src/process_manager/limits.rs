@@ -0,0 +1,299 @@
+//! Resource limits and scheduling of apps
+//!
+//! An app's `limits` are checked in the daemon before it spawns the app, so
+//! a limit the daemon isn't allowed to set fails the start with a clear
+//! error, then applied by the child between fork and exec. `bpm status`
+//! reads back the limits in effect from /proc.
+
+use nix::sched::CpuSet;
+use nix::sys::resource::{getrlimit, setrlimit, Resource, RLIM_INFINITY};
+use nix::unistd::{Pid, Uid};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use crate::config::read_config::{parse_cpu_list_str, Ionice, IoniceClass, Limits, Rlimit};
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+/// The resource behind each of `Limits::rlimits`, and its row in
+/// /proc/<pid>/limits
+const RESOURCES: [(&str, Resource, &str); 5] = [
+    ("nofile", Resource::RLIMIT_NOFILE, "Max open files"),
+    ("nproc", Resource::RLIMIT_NPROC, "Max processes"),
+    ("core", Resource::RLIMIT_CORE, "Max core file size"),
+    ("as", Resource::RLIMIT_AS, "Max address space"),
+    ("cpu", Resource::RLIMIT_CPU, "Max cpu time"),
+];
+
+fn resource(name: &str) -> Resource {
+    RESOURCES
+        .iter()
+        .find(|(field, ..)| *field == name)
+        .map(|(_, resource, _)| *resource)
+        .expect("every limit has a resource")
+}
+
+fn to_rlim(limit: Option<u64>) -> libc::rlim_t {
+    limit.unwrap_or(RLIM_INFINITY)
+}
+
+/// Check the daemon may set `limits`; only root can raise hard limits,
+/// lower its nice value or OOM score, or use the realtime IO class
+pub fn check(limits: &Limits) -> Result<(), String> {
+    if let Some(cpus) = &limits.cpu_affinity {
+        let allowed = nix::sched::sched_getaffinity(Pid::from_raw(0))
+            .map_err(|e| format!("failed to read the daemon's CPU affinity: {}", e))?;
+        if let Some(cpu) = cpus
+            .iter()
+            .find(|cpu| !allowed.is_set(**cpu).unwrap_or(false))
+        {
+            return Err(format!("limits.cpu_affinity: CPU {} isn't available", cpu));
+        }
+    }
+
+    if Uid::effective().is_root() {
+        return Ok(());
+    }
+    for (name, limit) in limits.rlimits() {
+        let Some(limit) = limit else { continue };
+        let (_, hard) = getrlimit(resource(name)).map_err(|e| e.to_string())?;
+        if to_rlim(limit.hard) > hard {
+            return Err(format!(
+                "limits.{}: only root can raise the hard limit above the daemon's {}",
+                name, hard
+            ));
+        }
+    }
+    if let Some(nice) = limits.nice {
+        let own = read_nice("self").unwrap_or(0);
+        if nice < own {
+            return Err(format!(
+                "limits.nice: only root can go below the daemon's nice value of {}",
+                own
+            ));
+        }
+    }
+    if let Some(adj) = limits.oom_score_adj {
+        let own = read_oom_score_adj("self").unwrap_or(0);
+        if adj < own {
+            return Err(format!(
+                "limits.oom_score_adj: only root can go below the daemon's {}",
+                own
+            ));
+        }
+    }
+    if limits.ionice.map(|ionice| ionice.class) == Some(IoniceClass::Realtime) {
+        return Err(
+            "limits.ionice: the realtime class needs the daemon to run as root".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Have `command` set `limits` on itself before it execs. This has to come
+/// before switching users, which drops the privileges to raise them.
+pub fn apply<'a>(limits: &Limits, command: &'a mut Command) -> &'a mut Command {
+    if limits.is_empty() {
+        return command;
+    }
+
+    let rlimits: Vec<(Resource, Rlimit)> = limits
+        .rlimits()
+        .into_iter()
+        .filter_map(|(name, limit)| Some((resource(name), limit?)))
+        .collect();
+    let nice = limits.nice;
+    let ioprio = limits.ionice.map(ioprio);
+    let oom_score_adj = limits.oom_score_adj.map(|adj| adj.to_string());
+    let cpus = limits.cpu_affinity.as_ref().map(|cpus| {
+        let mut set = CpuSet::new();
+        for cpu in cpus {
+            // check() has made sure the daemon can use it
+            let _ = set.set(*cpu);
+        }
+        set
+    });
+
+    // SAFETY: only async-signal-safe calls, on data prepared beforehand
+    unsafe {
+        command.pre_exec(move || {
+            for (resource, limit) in &rlimits {
+                setrlimit(*resource, to_rlim(limit.soft), to_rlim(limit.hard))?;
+            }
+            if let Some(nice) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(ioprio) = ioprio {
+                if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(adj) = &oom_score_adj {
+                write_oom_score_adj(adj.as_bytes())?;
+            }
+            if let Some(cpus) = &cpus {
+                nix::sched::sched_setaffinity(Pid::from_raw(0), cpus)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+fn ioprio(ionice: Ionice) -> libc::c_int {
+    let class = match ionice.class {
+        IoniceClass::Realtime => 1,
+        IoniceClass::BestEffort => 2,
+        IoniceClass::Idle => 3,
+    };
+    (class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(ionice.level)
+}
+
+/// Write /proc/self/oom_score_adj without allocating, as the child of a fork
+fn write_oom_score_adj(value: &[u8]) -> std::io::Result<()> {
+    // SAFETY: a constant C string path, and a buffer that outlives the write
+    unsafe {
+        let fd = libc::open(c"/proc/self/oom_score_adj".as_ptr(), libc::O_WRONLY);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let written = libc::write(fd, value.as_ptr().cast(), value.len());
+        let error = std::io::Error::last_os_error();
+        libc::close(fd);
+        if written < 0 {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// The limits `pid` runs with, read back from /proc
+pub fn read(pid: u32) -> std::io::Result<Limits> {
+    let proc = pid.to_string();
+    let table = std::fs::read_to_string(format!("/proc/{}/limits", pid))?;
+    let rlimit = |name: &str| {
+        let (.., row) = RESOURCES.iter().find(|(field, ..)| *field == name)?;
+        let line = table.lines().find(|line| line.starts_with(row))?;
+        let mut values = line[row.len()..]
+            .split_whitespace()
+            .map(|value| match value {
+                "unlimited" => Some(None),
+                value => value.parse::<u64>().ok().map(Some),
+            });
+        Some(Rlimit {
+            soft: values.next()??,
+            hard: values.next()??,
+        })
+    };
+
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid))?;
+    let cpu_affinity = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+        .and_then(|cpus| parse_cpu_list_str(cpus).ok());
+
+    Ok(Limits {
+        nofile: rlimit("nofile"),
+        nproc: rlimit("nproc"),
+        core: rlimit("core"),
+        address_space: rlimit("as"),
+        cpu: rlimit("cpu"),
+        nice: read_nice(&proc),
+        ionice: read_ionice(pid),
+        oom_score_adj: read_oom_score_adj(&proc),
+        cpu_affinity,
+    })
+}
+
+/// The nice value of a process, the 19th field of /proc/<pid>/stat
+fn read_nice(proc: &str) -> Option<i32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", proc)).ok()?;
+    // The command name may hold spaces, so count from after it
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(16)?.parse().ok()
+}
+
+fn read_oom_score_adj(proc: &str) -> Option<i32> {
+    std::fs::read_to_string(format!("/proc/{}/oom_score_adj", proc))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// The IO class of a process; `None` when it has none and follows its nice
+/// value
+fn read_ionice(pid: u32) -> Option<Ionice> {
+    // SAFETY: ioprio_get with plain integer arguments
+    let ioprio = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, pid) };
+    let class = match ioprio >> IOPRIO_CLASS_SHIFT {
+        1 => IoniceClass::Realtime,
+        2 => IoniceClass::BestEffort,
+        3 => IoniceClass::Idle,
+        _ => return None,
+    };
+    Some(Ionice {
+        class,
+        level: (ioprio & 0x7) as u8,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_and_read_back() {
+        let (_, nofile_hard) = getrlimit(Resource::RLIMIT_NOFILE).unwrap();
+        let own_nice = read_nice("self").unwrap();
+        let own_oom = read_oom_score_adj("self").unwrap();
+        let limits = Limits {
+            nofile: Some(Rlimit {
+                soft: Some(256),
+                hard: Some(nofile_hard.min(512)),
+            }),
+            core: Some(Rlimit {
+                soft: Some(0),
+                hard: Some(0),
+            }),
+            nice: Some((own_nice + 5).min(19)),
+            ionice: Some(Ionice {
+                class: IoniceClass::BestEffort,
+                level: 6,
+            }),
+            oom_score_adj: Some((own_oom + 100).min(1000)),
+            cpu_affinity: Some(vec![0]),
+            ..Limits::default()
+        };
+        check(&limits).unwrap();
+
+        let mut command = Command::new("sleep");
+        command.arg("5");
+        let mut child = apply(&limits, &mut command).spawn().unwrap();
+        // The limits are in place by the time exec has replaced the child
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let read = read(child.id());
+        let _ = child.kill();
+        let _ = child.wait();
+        let read = read.unwrap();
+
+        assert_eq!(read.nofile, limits.nofile);
+        assert_eq!(read.core, limits.core);
+        assert_eq!(read.nice, limits.nice);
+        assert_eq!(read.ionice, limits.ionice);
+        assert_eq!(read.oom_score_adj, limits.oom_score_adj);
+        assert_eq!(read.cpu_affinity, Some(vec![0]));
+    }
+
+    #[test]
+    fn test_check_rejects_unavailable_cpus() {
+        let limits = Limits {
+            cpu_affinity: Some(vec![CpuSet::count() - 1]),
+            ..Limits::default()
+        };
+        let error = check(&limits).unwrap_err();
+        assert!(error.contains("isn't available"), "{}", error);
+    }
+}
@@ -1,6 +1,14 @@
+pub mod cgroup;
+pub mod config_diff;
+pub mod credentials;
+pub mod dependency;
 pub(super) mod error;
 pub mod health;
+pub mod limits;
 pub mod process;
+pub mod reaper;
 pub mod registry;
+pub mod reload;
 pub mod spawner;
+pub mod stop;
 pub mod watch;
@@ -1,14 +1,21 @@
 //! Process utilities for collecting process tree info
 //!
 //! Provides utilities for getting descendant processes and combined usage stats.
+//! Apps are spawned as session leaders, so the processes of an app are its
+//! session plus any descendants that started their own.
 
 use once_cell::sync::Lazy;
 use std::collections::VecDeque;
 use std::error::Error;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
 use std::sync::Mutex;
-use sysinfo::{Pid, System};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, System};
 use thiserror::Error;
 
+use crate::process_manager::cgroup;
+
 #[derive(Debug, Error)]
 pub enum ProcessError {
     #[error("provided pid was invalid")]
@@ -23,22 +30,69 @@ pub fn collect_descendants(root_pid: u32) -> Result<Vec<Pid>, Box<dyn Error>> {
     if sys.process(root_pid).is_none() {
         return Err(Box::new(ProcessError::InvalidPid(root_pid.as_u32())));
     }
+    Ok(descendants(&sys, root_pid))
+}
+
+/// `root_pid` and every process below it, breadth first
+fn descendants(sys: &System, root_pid: Pid) -> Vec<Pid> {
     let mut result = Vec::new();
     let mut queue = VecDeque::new();
     queue.push_back(root_pid);
     while let Some(pid) = queue.pop_front() {
         result.push(pid);
-        result.extend(
+        queue.extend(
             sys.processes()
                 .iter()
-                .filter(|x| x.1.parent() == Some(pid))
-                .map(|x| {
-                    queue.push_back(*x.0);
-                    *x.0
-                }),
+                .filter(|x| x.1.parent() == Some(pid) && x.1.thread_kind().is_none())
+                .map(|x| *x.0),
         );
     }
-    Ok(result)
+    result
+}
+
+/// Every live process of the app whose main process is `root_pid`,
+/// including the main process itself and whatever is left in the app's
+/// `cgroup`. Read fresh rather than from the metrics snapshot, since it is
+/// used to tell when an app is fully stopped.
+pub fn app_processes(root_pid: u32, cgroup: Option<&Path>) -> Vec<u32> {
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, ProcessRefreshKind::nothing());
+
+    let root_pid = Pid::from_u32(root_pid);
+    let mut pids = descendants(&sys, root_pid);
+    pids.extend(
+        sys.processes()
+            .iter()
+            .filter(|(_, p)| p.session_id() == Some(root_pid) && p.thread_kind().is_none())
+            .map(|(pid, _)| *pid),
+    );
+    // Processes that left the app's session after their parent exited
+    // are still in its cgroup
+    let in_cgroup = cgroup.and_then(cgroup::procs).unwrap_or_default();
+    pids.extend(in_cgroup.into_iter().map(Pid::from_u32));
+    pids.sort();
+    pids.dedup();
+    pids.into_iter()
+        .filter(|pid| {
+            sys.process(*pid)
+                .is_some_and(|p| p.status() != ProcessStatus::Zombie)
+        })
+        .map(|pid| pid.as_u32())
+        .collect()
+}
+
+/// Make `command` start its process as the leader of a new session and
+/// process group, so the app's processes can be told apart and signalled
+/// together
+pub fn in_own_session(command: &mut Command) -> &mut Command {
+    // SAFETY: setsid is async-signal-safe
+    unsafe {
+        command.pre_exec(|| {
+            nix::unistd::setsid()
+                .map(|_| ())
+                .map_err(std::io::Error::from)
+        })
+    }
 }
 
 pub fn combined_usage(root_pid: u32) -> Result<(f32, u64), Box<dyn Error>> {
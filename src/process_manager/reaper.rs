@@ -0,0 +1,150 @@
+//! Orphan cleanup
+//!
+//! The daemon is a child subreaper, so when an app's main process dies the
+//! processes it leaves behind are re-parented to the daemon rather than to
+//! init. Each sweep stops those orphans, SIGTERM when first seen and SIGKILL
+//! if they are still around `ORPHAN_GRACE` later, and reaps them once they
+//! exit. Children the daemon spawned itself are left to whoever waits on
+//! them.
+
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag};
+use nix::unistd::{getpid, getsid, Pid};
+use std::collections::{BTreeSet, HashMap};
+use std::process::{Child, Command};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use sysinfo::{ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, System};
+
+/// How long an orphan gets to exit after SIGTERM
+const ORPHAN_GRACE: Duration = Duration::from_secs(5);
+
+/// App processes spawned by `spawn_app` that a sweep hasn't seen registered
+/// yet. Held for the whole sweep, so an app spawned meanwhile is either in
+/// it or not yet seen.
+static UNREGISTERED: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Spawn an app's main process. Sweeps leave it alone until it shows up
+/// among the registered apps, however long the daemon takes to register
+/// it.
+pub fn spawn_app(command: &mut Command) -> std::io::Result<Child> {
+    let mut unregistered = UNREGISTERED.lock().unwrap_or_else(PoisonError::into_inner);
+    let child = command.spawn()?;
+    unregistered.insert(child.id());
+    Ok(child)
+}
+
+/// Have orphaned descendants re-parented to this process
+pub fn become_subreaper() -> Result<(), String> {
+    nix::sys::prctl::set_child_subreaper(true)
+        .map_err(|e| format!("failed to become a child subreaper: {}", e))
+}
+
+/// Orphans seen so far, with when they were sent SIGTERM
+#[derive(Debug, Default)]
+pub struct Reaper {
+    terminated: HashMap<u32, Instant>,
+}
+
+impl Reaper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop orphans and reap the ones that exited. `app_pids` are the main
+    /// processes of registered apps, which are left alone, as are apps
+    /// still being registered. Returns the orphans signalled, with the
+    /// signal sent.
+    pub fn sweep(&mut self, app_pids: &[u32]) -> Vec<(u32, Signal)> {
+        let mut unregistered = UNREGISTERED.lock().unwrap_or_else(PoisonError::into_inner);
+        let daemon = getpid();
+        let daemon_session = getsid(None).ok();
+        let mut sys = System::new();
+        sys.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing(),
+        );
+
+        let mut signalled = Vec::new();
+        let mut alive = Vec::new();
+        let mut children = Vec::new();
+        for (pid, process) in sys.processes() {
+            let pid = pid.as_u32();
+            if process.parent().map(|p| p.as_u32()) != Some(daemon.as_raw() as u32)
+                || process.thread_kind().is_some()
+            {
+                continue;
+            }
+            children.push(pid);
+            if app_pids.contains(&pid) || unregistered.contains(&pid) {
+                continue;
+            }
+            // Healthcheck and secret commands run in the daemon's session;
+            // whoever spawned them collects their exit status
+            let session = process.session_id().map(|s| s.as_u32());
+            if session == daemon_session.map(|s| s.as_raw() as u32) {
+                continue;
+            }
+            if process.status() == ProcessStatus::Zombie {
+                let _ = waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG));
+                continue;
+            }
+
+            alive.push(pid);
+            let signal = match self.terminated.get(&pid) {
+                None => Signal::SIGTERM,
+                Some(sent) if sent.elapsed() >= ORPHAN_GRACE => Signal::SIGKILL,
+                Some(_) => continue,
+            };
+            if kill(Pid::from_raw(pid as i32), signal).is_ok() {
+                self.terminated.entry(pid).or_insert_with(Instant::now);
+                signalled.push((pid, signal));
+            }
+        }
+
+        self.terminated.retain(|pid, _| alive.contains(pid));
+        unregistered.retain(|pid| children.contains(pid) && !app_pids.contains(pid));
+        signalled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_manager::process::in_own_session;
+
+    #[test]
+    fn test_sweep_leaves_own_children_to_their_waiter() {
+        let mut child = Command::new("true").spawn().unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+
+        let mut reaper = Reaper::new();
+        assert!(reaper.sweep(&[]).iter().all(|(pid, _)| *pid != child.id()));
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_sweep_stops_orphans_leading_their_own_session() {
+        // Like an app's grandchild that called setsid() before its parent
+        // exited
+        let mut orphan = in_own_session(Command::new("sleep").arg("30"))
+            .spawn()
+            .unwrap();
+        let mut app = spawn_app(in_own_session(Command::new("sleep").arg("30"))).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+
+        let mut reaper = Reaper::new();
+        let signalled = reaper.sweep(&[]);
+        assert!(signalled.contains(&(orphan.id(), Signal::SIGTERM)));
+        assert!(signalled.iter().all(|(pid, _)| *pid != app.id()));
+
+        // Once registered, it is left alone as a registered app
+        reaper.sweep(&[app.id()]);
+        assert!(!UNREGISTERED.lock().unwrap().contains(&app.id()));
+
+        app.kill().unwrap();
+        let _ = app.wait();
+        let _ = orphan.wait();
+    }
+}
@@ -3,37 +3,135 @@
 //! This module provides the central registry for all processes managed by BPM.
 //! It handles process lifecycle, state tracking, and metrics collection.
 
-use crate::config::read_config::{App, HealthCheck, HealthCheckType as ConfigHealthCheckType};
+use crate::config::read_config::{
+    format_duration, format_size, parse_duration_str, App, CgroupConfig, CrashLoop, Dependency,
+    HealthCheck, HealthCheckType as ConfigHealthCheckType, Limits, MaxCpu, ReloadConfig,
+    RestartConfig, RestartPolicy, StopStep,
+};
+use crate::config::secrets::{self, SecretSource};
+use crate::config::state;
+use crate::process_manager::cgroup;
+use crate::process_manager::credentials::RunAs;
 use crate::process_manager::health::{HealthCheckConfig, HealthCheckType, HealthStatus};
 use crate::process_manager::process::combined_usage;
 use chrono::{DateTime, Utc};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use sysinfo::{Pid, System};
 
 /// Process lifecycle states
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProcessState {
     Starting,
+    /// Waiting for its dependencies before it is spawned
+    Waiting,
     Running,
     Stopping,
     Stopped,
     Errored,
     Restarting,
+    /// Waiting out the restart delay
+    Backoff,
+    /// Crashed too often; waiting out the crash-loop cooldown
+    CrashLooping,
+    /// Running while `bpm reload` waits for its replacement to be ready
+    Reloading,
 }
 
 impl std::fmt::Display for ProcessState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ProcessState::Starting => write!(f, "starting"),
+            ProcessState::Waiting => write!(f, "waiting"),
             ProcessState::Running => write!(f, "running"),
             ProcessState::Stopping => write!(f, "stopping"),
             ProcessState::Stopped => write!(f, "stopped"),
             ProcessState::Errored => write!(f, "errored"),
             ProcessState::Restarting => write!(f, "restarting"),
+            ProcessState::Backoff => write!(f, "backoff"),
+            ProcessState::CrashLooping => write!(f, "crash-looping"),
+            ProcessState::Reloading => write!(f, "reloading"),
+        }
+    }
+}
+
+/// How a process's last run ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    /// It exited with this code
+    Code(i32),
+    /// A signal killed it
+    Signal(String),
+    /// The OOM killer killed a process of its cgroup
+    OomKilled,
+}
+
+impl ExitReason {
+    /// The reason in a `waitpid` status, if the process has ended
+    pub fn from_wait_status(status: WaitStatus) -> Option<Self> {
+        match status {
+            WaitStatus::Exited(_, code) => Some(ExitReason::Code(code)),
+            WaitStatus::Signaled(_, signal, _) => Some(ExitReason::Signal(signal.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitReason::Code(code) => write!(f, "exit code {}", code),
+            ExitReason::Signal(signal) => write!(f, "killed by {}", signal),
+            ExitReason::OomKilled => write!(f, "killed by the OOM killer"),
+        }
+    }
+}
+
+/// Why the daemon last restarted a process
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartReason {
+    /// It ended and its restart policy restarts it
+    Exited,
+    /// `bpm restart`
+    Manual,
+    /// `bpm reload` handed over to a new process
+    Reloaded,
+    /// Its health check failed `retries` times in a row
+    Unhealthy,
+    /// Files it watches changed
+    FilesChanged,
+    /// It used more memory than its `max_memory_restart`
+    MaxMemory { memory: u64, limit: u64 },
+    /// It stayed above its `max_cpu` for as long as that allows
+    MaxCpu(MaxCpu),
+}
+
+impl std::fmt::Display for RestartReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestartReason::Exited => write!(f, "exited"),
+            RestartReason::Manual => write!(f, "restarted by hand"),
+            RestartReason::Reloaded => write!(f, "reloaded"),
+            RestartReason::Unhealthy => write!(f, "failed its health check"),
+            RestartReason::FilesChanged => write!(f, "watched files changed"),
+            RestartReason::MaxMemory { memory, limit } => write!(
+                f,
+                "used {} of memory, above max_memory_restart {}",
+                human_bytes(*memory),
+                format_size(*limit)
+            ),
+            RestartReason::MaxCpu(max_cpu) => write!(
+                f,
+                "used over {}% CPU for {}",
+                max_cpu.percent,
+                format_duration(max_cpu.sustained)
+            ),
         }
     }
 }
@@ -41,14 +139,31 @@ impl std::fmt::Display for ProcessState {
 /// Information about a managed process
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessInfo {
-    /// Unique name of the process
+    /// Unique name of the process (`project/app` for apps in a project)
     pub name: String,
+    /// Project the app belongs to, if it was declared in a multi-app config
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Env profile the app was started with (`bpm start --env <profile>`)
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Instance index for apps with `instances`; the name ends in `:<index>`
+    #[serde(default)]
+    pub instance: Option<u32>,
     /// Process ID (if running)
     pub pid: Option<u32>,
     /// Current state
     pub state: ProcessState,
     /// Path to the config file
     pub config_path: PathBuf,
+    /// Checksum of the config file when the process was registered or last
+    /// reloaded
+    #[serde(default)]
+    pub config_checksum: Option<String>,
+    /// The `env_file`s the process was started with, which count towards
+    /// the config checksum
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_files: Vec<PathBuf>,
     /// Script/command being run
     pub script: String,
     /// Arguments passed to the script
@@ -61,8 +176,6 @@ pub struct ProcessInfo {
     pub restart_count: u32,
     /// Time when the process was started
     pub started_at: Option<DateTime<Utc>>,
-    /// Number of consecutive quick crashes (< 5 seconds)
-    pub quick_crash_count: u32,
     /// Last known CPU usage (percentage)
     pub cpu_usage: f32,
     /// Last known memory usage (bytes)
@@ -72,8 +185,39 @@ pub struct ProcessInfo {
     pub stderr_log: PathBuf,
     /// Whether auto-restart is enabled
     pub auto_restart: bool,
+    /// Restart policy, and the exit codes it goes by
+    #[serde(default)]
+    pub restart: RestartConfig,
+    /// How the process last ended, if it has run
+    #[serde(default)]
+    pub last_exit: Option<ExitReason>,
+    /// Why the daemon last restarted the process
+    #[serde(default)]
+    pub last_restart_reason: Option<RestartReason>,
+    /// Automatic restarts in a row, since the process was last started by
+    /// hand or last stayed up for `RestartConfig::stable_after`
+    #[serde(default)]
+    pub restart_attempts: u32,
+    /// When a process in `Backoff` or `CrashLooping` is restarted
+    #[serde(default)]
+    pub next_restart_at: Option<DateTime<Utc>>,
+    /// When repeated crashes pause restarting
+    #[serde(default)]
+    pub crash_loop: CrashLoop,
+    /// How `bpm reload` hands over to a new process
+    #[serde(default)]
+    pub reload: ReloadConfig,
+    /// Crashes within the last crash-loop window
+    #[serde(default)]
+    pub recent_crashes: Vec<DateTime<Utc>>,
     /// Maximum memory before restart (0 = disabled)
     pub max_memory: u64,
+    /// CPU use that restarts the process once it lasts
+    #[serde(default)]
+    pub max_cpu: Option<MaxCpu>,
+    /// Since when the process has been above `max_cpu`
+    #[serde(skip)]
+    pub cpu_high_since: Option<Instant>,
     /// Health check configuration (optional)
     #[serde(skip)]
     pub healthcheck: Option<HealthCheckConfig>,
@@ -88,16 +232,69 @@ pub struct ProcessInfo {
     pub watch_dirs: Vec<PathBuf>,
     /// Watch patterns (e.g., "*.js", "*.py")
     pub watch_patterns: Vec<String>,
+    /// Apps that must be up before this one starts, with qualified names
+    #[serde(default)]
+    pub depends_on: Vec<Dependency>,
+    /// Dotenv files of secrets, read each time the process is spawned
+    #[serde(default)]
+    pub env_from_file: Vec<PathBuf>,
+    /// Secret env sources, read each time the process is spawned
+    #[serde(default)]
+    pub secrets: BTreeMap<String, SecretSource>,
+    /// Env keys whose values are masked when the process is shown or saved
+    #[serde(default = "secrets::default_patterns")]
+    pub secret_patterns: Vec<String>,
+    /// Secret values the process was last spawned with. Kept in memory only,
+    /// to mask them in its logs.
+    #[serde(skip)]
+    pub secret_values: Vec<String>,
+    /// Env keys whose values were masked when the process was saved. Their
+    /// values are read from the config again before it is spawned.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redacted_env: Vec<String>,
+    /// The app as given to `bpm start -- <command>`, for processes started
+    /// without a config file. Their `config_path` is `COMMAND_LINE_ORIGIN`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adhoc_app: Option<App>,
+    /// Signals sent in turn to stop the process; SIGKILL follows the last
+    #[serde(default = "default_stop_sequence")]
+    pub stop_sequence: Vec<StopStep>,
+    /// User, groups, umask and capabilities to spawn the process with
+    #[serde(default)]
+    pub run_as: RunAs,
+    /// Resource limits and scheduling to spawn the process with
+    #[serde(default)]
+    pub limits: Limits,
+    /// Settings of the process's cgroup
+    #[serde(default)]
+    pub cgroup: CgroupConfig,
+    /// The cgroup the process runs in, when the daemon manages cgroups
+    #[serde(default)]
+    pub cgroup_path: Option<PathBuf>,
+    /// OOM kills in the cgroup when the process was spawned
+    #[serde(default)]
+    pub oom_kills: u64,
+    /// The cgroup's CPU time at the last metrics refresh
+    #[serde(skip)]
+    pub cpu_sample: Option<(u64, Instant)>,
 }
 
+fn default_stop_sequence() -> Vec<StopStep> {
+    vec![StopStep::default()]
+}
+
+/// `config_path` of processes started from the command line
+pub const COMMAND_LINE_ORIGIN: &str = "<command line>";
+
 impl ProcessInfo {
     /// Create a new ProcessInfo from an App config
     pub fn from_app(app: &App, config_path: PathBuf) -> Self {
+        let name = app.process_name();
         let default_log_dir = dirs::data_local_dir()
             .unwrap_or_else(|| PathBuf::from("/tmp"))
             .join("bpm")
             .join("logs")
-            .join(&app.name);
+            .join(&name);
 
         // Determine log paths - use custom if specified, otherwise default
         let stdout_log = Self::resolve_log_path(&app.log.out, &default_log_dir, "out.log");
@@ -113,9 +310,14 @@ impl ProcessInfo {
         let watch_dirs = app.cwd.clone().map(|d| vec![d]).unwrap_or_default();
 
         Self {
-            name: app.name.clone(),
+            name,
+            project: app.project.clone(),
+            profile: app.profile.clone(),
+            instance: app.instance,
             pid: None,
             state: ProcessState::Stopped,
+            config_checksum: state::config_checksum(&config_path, &app.env_file),
+            env_files: app.env_file.clone(),
             config_path,
             script: app.script.clone(),
             args: app.args.clone(),
@@ -123,26 +325,64 @@ impl ProcessInfo {
             env: app.env.clone(),
             restart_count: 0,
             started_at: None,
-            quick_crash_count: 0,
             cpu_usage: 0.0,
             memory_usage: 0,
             stdout_log,
             stderr_log,
-            auto_restart: matches!(
-                app.restart.policy,
-                crate::config::read_config::RestartPolicy::Always
-                    | crate::config::read_config::RestartPolicy::OnFailure
-            ),
-            max_memory: 0,
+            auto_restart: app.restart.policy != RestartPolicy::Never
+                || !app.restart.restart_on_exit_codes.is_empty(),
+            restart: app.restart.clone(),
+            last_exit: None,
+            last_restart_reason: None,
+            restart_attempts: 0,
+            next_restart_at: None,
+            crash_loop: app.crash_loop,
+            reload: app.reload,
+            recent_crashes: vec![],
+            max_memory: app.max_memory_restart.unwrap_or(0),
+            max_cpu: app.max_cpu,
+            cpu_high_since: None,
             healthcheck,
             health_status: HealthStatus::Unknown,
             last_health_check: None,
             health_failures: 0,
             watch_dirs,
             watch_patterns: vec![],
+            depends_on: app
+                .depends_on
+                .iter()
+                .map(|d| d.qualified(app.project.as_deref()))
+                .collect(),
+            env_from_file: app.env_from_file.clone(),
+            secrets: app.secrets.clone(),
+            secret_patterns: app.secret_patterns.clone(),
+            secret_values: vec![],
+            redacted_env: vec![],
+            adhoc_app: None,
+            stop_sequence: app.stop_sequence(),
+            run_as: RunAs::from_app(app),
+            limits: app.limits.clone(),
+            cgroup: app.cgroup.clone(),
+            cgroup_path: None,
+            oom_kills: 0,
+            cpu_sample: None,
         }
     }
 
+    /// Create a ProcessInfo for an app started from the command line
+    pub fn from_command(app: &App) -> Self {
+        Self {
+            adhoc_app: Some(app.clone()),
+            ..Self::from_app(app, PathBuf::from(COMMAND_LINE_ORIGIN))
+        }
+    }
+
+    /// Whether the process was started from the command line rather than
+    /// from a config file
+    pub fn is_adhoc(&self) -> bool {
+        self.adhoc_app.is_some()
+    }
+
     /// Convert config HealthCheck to internal HealthCheckConfig
     fn convert_healthcheck(hc: &HealthCheck) -> HealthCheckConfig {
         let check_type = match hc.check_type {
@@ -193,26 +433,149 @@ impl ProcessInfo {
         }
     }
 
-    /// Parse duration string like "30s", "5m", "1h"
+    /// Parse duration string like "30s", "5m", "1h".
+    /// Configs are validated before registration, so the 30s fallback only
+    /// covers state saved by older versions.
     fn parse_duration_str(s: &str) -> Duration {
-        if s.ends_with('s') {
-            s.trim_end_matches('s')
-                .parse::<u64>()
-                .map(Duration::from_secs)
-                .unwrap_or(Duration::from_secs(30))
-        } else if s.ends_with('m') {
-            s.trim_end_matches('m')
-                .parse::<u64>()
-                .map(|m| Duration::from_secs(m * 60))
-                .unwrap_or(Duration::from_secs(30))
-        } else if s.ends_with('h') {
-            s.trim_end_matches('h')
-                .parse::<u64>()
-                .map(|h| Duration::from_secs(h * 3600))
-                .unwrap_or(Duration::from_secs(30))
+        parse_duration_str(s).unwrap_or(Duration::from_secs(30))
+    }
+
+    /// Name of the app this process runs: the name without its `:<index>`
+    /// suffix for instances
+    pub fn app_name(&self) -> &str {
+        match self.instance {
+            Some(_) => self
+                .name
+                .rsplit_once(':')
+                .map_or(self.name.as_str(), |(app, _)| app),
+            None => &self.name,
+        }
+    }
+
+    /// The environment to spawn the process with: `env` plus every secret,
+    /// read now
+    pub fn spawn_env(&self) -> Result<HashMap<String, String>, String> {
+        let mut env = self.env.clone();
+        env.extend(secrets::load(
+            &self.env_from_file,
+            &self.secrets,
+            self.cwd.as_deref(),
+        )?);
+        Ok(env)
+    }
+
+    /// A copy that is safe to show or save, with secret env values masked
+    pub fn redacted(&self) -> ProcessInfo {
+        let adhoc_app = self.adhoc_app.as_ref().map(|app| App {
+            env: secrets::mask_env(&app.env, &app.secrets, &app.secret_patterns),
+            ..app.clone()
+        });
+        let mut redacted_env: Vec<String> = self
+            .env
+            .keys()
+            .filter(|key| secrets::is_secret(key, &self.secrets, &self.secret_patterns))
+            .cloned()
+            .collect();
+        redacted_env.sort();
+        ProcessInfo {
+            env: secrets::mask_env(&self.env, &self.secrets, &self.secret_patterns),
+            secret_values: vec![],
+            redacted_env,
+            adhoc_app,
+            ..self.clone()
+        }
+    }
+
+    /// Whether env values masked in the state file haven't been put back,
+    /// so spawning it would pass on the mask instead
+    pub fn has_masked_env(&self) -> bool {
+        !self.redacted_env.is_empty()
+    }
+
+    /// Mask the process's secret values wherever they appear in `text`
+    pub fn mask_secrets(&self, text: &str) -> String {
+        let values: Vec<&str> = self
+            .env
+            .iter()
+            .filter(|(key, _)| secrets::is_secret(key, &self.secrets, &self.secret_patterns))
+            .map(|(_, value)| value.as_str())
+            .chain(self.secret_values.iter().map(String::as_str))
+            .collect();
+        secrets::mask_text(text, &values)
+    }
+
+    /// Whether `exit` counts as a clean exit. Unknown exits, of processes
+    /// the daemon didn't spawn, count as failures.
+    pub fn is_clean_exit(&self, exit: Option<&ExitReason>) -> bool {
+        matches!(exit, Some(ExitReason::Code(code)) if self.restart.success_exit_codes.contains(code))
+    }
+
+    /// Whether the restart policy restarts the process after `exit`
+    pub fn should_restart(&self, exit: Option<&ExitReason>) -> bool {
+        if !self.auto_restart {
+            return false;
+        }
+        if let Some(ExitReason::Code(code)) = exit {
+            if self.restart.restart_on_exit_codes.contains(code) {
+                return true;
+            }
+        }
+        match self.restart.policy {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => !self.is_clean_exit(exit),
+            RestartPolicy::Never => false,
+        }
+    }
+
+    /// Record that the process ended on its own, leaving it `Restarting` if
+    /// the policy restarts it, otherwise `Stopped` after a clean exit and
+    /// `Errored` after a failure. A process being reloaded stays
+    /// `Reloading`; the reload goes on with the new process, or fails and
+    /// leaves it as its exit calls for (see `cancel_reload`).
+    fn record_exit(&mut self, exit: Option<ExitReason>) {
+        let oom_killed = self
+            .cgroup_path
+            .as_deref()
+            .is_some_and(|path| cgroup::oom_kills(path) > self.oom_kills);
+        let exit = if oom_killed {
+            Some(ExitReason::OomKilled)
         } else {
-            Duration::from_secs(30)
+            exit
+        };
+        if self.state != ProcessState::Reloading {
+            self.state = self.state_after_exit(exit.as_ref());
         }
+        self.last_exit = exit;
+        self.pid = None;
+        self.cpu_usage = 0.0;
+        self.memory_usage = 0;
+        self.cpu_sample = None;
+        self.cpu_high_since = None;
+        // Don't clear started_at - keep it for crash detection
+    }
+
+    fn state_after_exit(&self, exit: Option<&ExitReason>) -> ProcessState {
+        if self.should_restart(exit) {
+            ProcessState::Restarting
+        } else if self.is_clean_exit(exit) {
+            ProcessState::Stopped
+        } else {
+            ProcessState::Errored
+        }
+    }
+
+    /// Whether the config file, a file it `extends` or an `env_file`
+    /// changed on disk since it was last loaded. `checksums` keeps what is
+    /// on disk now, so calls sharing it hash each config once.
+    pub fn config_changed(
+        &self,
+        checksums: &mut HashMap<(PathBuf, Vec<PathBuf>), Option<String>>,
+    ) -> bool {
+        self.config_checksum.is_some()
+            && *checksums
+                .entry((self.config_path.clone(), self.env_files.clone()))
+                .or_insert_with(|| state::config_checksum(&self.config_path, &self.env_files))
+                != self.config_checksum
     }
 
     /// Get the uptime as a human-readable string
@@ -225,33 +588,41 @@ impl ProcessInfo {
         match self.started_at {
             Some(started) => {
                 let duration = Utc::now().signed_duration_since(started);
-                let secs = duration.num_seconds();
-                if secs < 60 {
-                    format!("{}s", secs)
-                } else if secs < 3600 {
-                    format!("{}m", secs / 60)
-                } else if secs < 86400 {
-                    format!("{}h", secs / 3600)
-                } else {
-                    format!("{}d", secs / 86400)
-                }
+                short_duration(duration.num_seconds())
             }
             None => "-".to_string(),
         }
     }
 
+    /// Time left until a process in backoff or a crash loop is restarted
+    pub fn restart_countdown(&self) -> Option<String> {
+        if !matches!(
+            self.state,
+            ProcessState::Backoff | ProcessState::CrashLooping
+        ) {
+            return None;
+        }
+        let left = self.next_restart_at? - Utc::now();
+        // Round up, so the countdown ends on 1s rather than 0s
+        let millis = left.num_milliseconds().max(0);
+        Some(short_duration((millis + 999) / 1000))
+    }
+
     /// Format memory usage as human-readable string
     pub fn memory_display(&self) -> String {
-        let bytes = self.memory_usage;
-        if bytes < 1024 {
-            format!("{}B", bytes)
-        } else if bytes < 1024 * 1024 {
-            format!("{:.1}KB", bytes as f64 / 1024.0)
-        } else if bytes < 1024 * 1024 * 1024 {
-            format!("{:.1}MB", bytes as f64 / (1024.0 * 1024.0))
-        } else {
-            format!("{:.1}GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
-        }
+        human_bytes(self.memory_usage)
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{}B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1}KB", bytes as f64 / 1024.0)
+    } else if bytes < 1024 * 1024 * 1024 {
+        format!("{:.1}MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1}GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
     }
 }
 
@@ -300,6 +671,66 @@ impl ProcessRegistry {
         inner.processes.get(name).cloned()
     }
 
+    /// Find a process by list index, exact name, or app name without its
+    /// project prefix when that is unambiguous
+    pub fn find(&self, name_or_id: &str) -> Option<ProcessInfo> {
+        if let Ok(id) = name_or_id.parse::<usize>() {
+            return self.list().get(id).cloned();
+        }
+        if let Some(process) = self.get(name_or_id) {
+            return Some(process);
+        }
+
+        let suffix = format!("/{}", name_or_id);
+        let mut matches = self
+            .list()
+            .into_iter()
+            .filter(|p| p.name.ends_with(&suffix));
+        match (matches.next(), matches.next()) {
+            (Some(process), None) => Some(process),
+            _ => None,
+        }
+    }
+
+    /// Get every instance of a clustered app, by app name (or app name
+    /// without its project prefix when that is unambiguous)
+    pub fn find_instances(&self, app_name: &str) -> Vec<ProcessInfo> {
+        let instances: Vec<ProcessInfo> = self
+            .list()
+            .into_iter()
+            .filter(|p| p.instance.is_some())
+            .collect();
+
+        let exact: Vec<ProcessInfo> = instances
+            .iter()
+            .filter(|p| p.app_name() == app_name)
+            .cloned()
+            .collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+
+        let suffix = format!("/{}", app_name);
+        let matches: Vec<ProcessInfo> = instances
+            .into_iter()
+            .filter(|p| p.app_name().ends_with(&suffix))
+            .collect();
+        let apps: std::collections::HashSet<&str> = matches.iter().map(|p| p.app_name()).collect();
+        if apps.len() == 1 {
+            matches
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Get all processes belonging to a project, in list order
+    pub fn list_project(&self, project: &str) -> Vec<ProcessInfo> {
+        self.list()
+            .into_iter()
+            .filter(|p| p.project.as_deref() == Some(project))
+            .collect()
+    }
+
     /// Update a process's state
     pub fn update_state(&self, name: &str, state: ProcessState) -> Result<(), String> {
         let mut inner = self.inner.write().map_err(|e| e.to_string())?;
@@ -311,21 +742,68 @@ impl ProcessRegistry {
         }
     }
 
-    /// Update a process's PID
-    pub fn update_pid(&self, name: &str, pid: Option<u32>) -> Result<(), String> {
+    /// Move a process to state `to` if it is in state `from`. Returns
+    /// whether it was.
+    pub fn transition(&self, name: &str, from: ProcessState, to: ProcessState) -> bool {
+        let Ok(mut inner) = self.inner.write() else {
+            return false;
+        };
+        match inner.processes.get_mut(name) {
+            Some(process) if process.state == from => {
+                process.state = to;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Update a process's PID. Returns the PID it replaces.
+    pub fn update_pid(&self, name: &str, pid: Option<u32>) -> Result<Option<u32>, String> {
         let mut inner = self.inner.write().map_err(|e| e.to_string())?;
         if let Some(process) = inner.processes.get_mut(name) {
-            process.pid = pid;
+            let replaced = std::mem::replace(&mut process.pid, pid);
             if pid.is_some() {
                 process.started_at = Some(Utc::now());
                 process.state = ProcessState::Running;
             }
-            Ok(())
+            Ok(replaced)
         } else {
             Err(format!("Process '{}' not found", name))
         }
     }
 
+    /// Record the cgroup a process was just spawned into. A different
+    /// cgroup it was in before is removed once empty.
+    pub fn set_cgroup(&self, name: &str, path: Option<PathBuf>) -> Result<(), String> {
+        let mut inner = self.inner.write().map_err(|e| e.to_string())?;
+        let Some(process) = inner.processes.get_mut(name) else {
+            return Err(format!("Process '{}' not found", name));
+        };
+        process.oom_kills = path.as_deref().map_or(0, cgroup::oom_kills);
+        if let Some(previous) = std::mem::replace(&mut process.cgroup_path, path) {
+            if process.cgroup_path.as_ref() != Some(&previous) {
+                cgroup::remove(&previous);
+            }
+        }
+        process.cpu_sample = None;
+        Ok(())
+    }
+
+    /// End a reload that failed: the process is `Running` again if its old
+    /// process is still up, and otherwise left as its exit during the
+    /// reload calls for
+    pub fn cancel_reload(&self, name: &str) -> Result<(), String> {
+        let mut inner = self.inner.write().map_err(|e| e.to_string())?;
+        let Some(process) = inner.processes.get_mut(name) else {
+            return Err(format!("Process '{}' not found", name));
+        };
+        process.state = match process.pid {
+            Some(_) => ProcessState::Running,
+            None => process.state_after_exit(process.last_exit.as_ref()),
+        };
+        Ok(())
+    }
+
     /// Increment restart count
     pub fn increment_restart_count(&self, name: &str) -> Result<u32, String> {
         let mut inner = self.inner.write().map_err(|e| e.to_string())?;
@@ -337,6 +815,49 @@ impl ProcessRegistry {
         }
     }
 
+    /// Record why a process is being restarted
+    pub fn set_restart_reason(&self, name: &str, reason: RestartReason) -> Result<(), String> {
+        let mut inner = self.inner.write().map_err(|e| e.to_string())?;
+        let Some(process) = inner.processes.get_mut(name) else {
+            return Err(format!("Process '{}' not found", name));
+        };
+        process.last_restart_reason = Some(reason);
+        Ok(())
+    }
+
+    /// Replace a process's config-derived settings with `info`, keeping its
+    /// runtime state (PID, state, counters, metrics and health)
+    pub fn apply_config(&self, info: ProcessInfo) -> Result<(), String> {
+        let mut inner = self.inner.write().map_err(|e| e.to_string())?;
+        let Some(current) = inner.processes.get_mut(&info.name) else {
+            return Err(format!("Process '{}' not found", info.name));
+        };
+
+        *current = ProcessInfo {
+            pid: current.pid,
+            state: current.state.clone(),
+            restart_count: current.restart_count,
+            started_at: current.started_at,
+            cpu_usage: current.cpu_usage,
+            memory_usage: current.memory_usage,
+            health_status: current.health_status.clone(),
+            last_health_check: current.last_health_check,
+            health_failures: current.health_failures,
+            secret_values: current.secret_values.clone(),
+            last_exit: current.last_exit.clone(),
+            last_restart_reason: current.last_restart_reason.clone(),
+            restart_attempts: current.restart_attempts,
+            next_restart_at: current.next_restart_at,
+            recent_crashes: current.recent_crashes.clone(),
+            cgroup_path: current.cgroup_path.clone(),
+            oom_kills: current.oom_kills,
+            cpu_sample: current.cpu_sample,
+            cpu_high_since: current.cpu_high_since.filter(|_| info.max_cpu.is_some()),
+            ..info
+        };
+        Ok(())
+    }
+
     /// Remove a process from the registry
     pub fn remove(&self, name: &str) -> Option<ProcessInfo> {
         let mut inner = self.inner.write().ok()?;
@@ -346,10 +867,15 @@ impl ProcessRegistry {
     /// Get all processes
     pub fn list(&self) -> Vec<ProcessInfo> {
         let inner = self.inner.read().ok();
-        match inner {
+        let mut processes: Vec<ProcessInfo> = match inner {
             Some(guard) => guard.processes.values().cloned().collect(),
             None => Vec::new(),
-        }
+        };
+        // Stable order, so list IDs don't shuffle and instances stay together
+        processes.sort_by(|a, b| {
+            (a.app_name(), a.instance).cmp(&(b.app_name(), b.instance))
+        });
+        processes
     }
 
     /// Refresh metrics for all running processes
@@ -362,22 +888,27 @@ impl ProcessRegistry {
         inner.system.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
 
         // Collect PIDs first
-        let pids_to_check: Vec<(String, u32)> = inner
+        let pids_to_check: Vec<(String, u32, Option<PathBuf>)> = inner
             .processes
             .iter()
             .filter_map(|(name, p)| {
-                if p.state == ProcessState::Running || p.state == ProcessState::Starting {
-                    p.pid.map(|pid| (name.clone(), pid))
+                if matches!(
+                    p.state,
+                    ProcessState::Running | ProcessState::Starting | ProcessState::Reloading
+                ) {
+                    p.pid.map(|pid| (name.clone(), pid, p.cgroup_path.clone()))
                 } else {
                     None
                 }
             })
             .collect();
 
-        // Collect metrics - use combined_usage to get process tree metrics
-        let metrics: Vec<(String, Option<(f32, u64)>)> = pids_to_check
+        // Collect metrics - from the app's cgroup, which also counts CPU time
+        // of children that have exited, or else its process tree
+        let now = Instant::now();
+        let metrics: Vec<(String, Option<Metrics>)> = pids_to_check
             .iter()
-            .map(|(name, pid)| {
+            .map(|(name, pid, cgroup_path)| {
                 let sys_pid = Pid::from_u32(*pid);
                 // First check if process exists at all
                 let metrics = if let Some(proc) = inner.system.process(sys_pid) {
@@ -386,11 +917,22 @@ impl ProcessRegistry {
                     if proc.status() == ProcessStatus::Zombie {
                         // Process is dead (zombie)
                         None
+                    } else if let Some(usage) = cgroup_path.as_deref().and_then(cgroup::usage) {
+                        // Without the memory controller, add up what is in the cgroup
+                        let memory = usage.memory.unwrap_or_else(|| {
+                            usage
+                                .pids
+                                .iter()
+                                .filter_map(|pid| inner.system.process(Pid::from_u32(*pid)))
+                                .map(|proc| proc.memory())
+                                .sum()
+                        });
+                        Some(Metrics::Cgroup(usage.cpu_usec, memory))
                     } else {
                         // Try combined usage for process tree, fall back to single process
-                        combined_usage(*pid).ok().or_else(|| {
-                            Some((proc.cpu_usage(), proc.memory()))
-                        })
+                        let (cpu, memory) = combined_usage(*pid)
+                            .unwrap_or_else(|_| (proc.cpu_usage(), proc.memory()));
+                        Some(Metrics::Tree(cpu, memory))
                     }
                 } else {
                     None
@@ -402,7 +944,21 @@ impl ProcessRegistry {
         // Now update processes
         for (name, opt_metrics) in metrics {
             if let Some(process) = inner.processes.get_mut(&name) {
-                if let Some((cpu, mem)) = opt_metrics {
+                if let Some(metrics) = opt_metrics {
+                    let (cpu, mem) = match metrics {
+                        Metrics::Tree(cpu, memory) => (cpu, memory),
+                        Metrics::Cgroup(cpu_usec, memory) => {
+                            let cpu = match process.cpu_sample {
+                                Some((last_usec, at)) => {
+                                    let elapsed = now.duration_since(at).as_micros().max(1) as f32;
+                                    cpu_usec.saturating_sub(last_usec) as f32 / elapsed * 100.0
+                                }
+                                None => 0.0,
+                            };
+                            process.cpu_sample = Some((cpu_usec, now));
+                            (cpu, memory)
+                        }
+                    };
                     process.cpu_usage = cpu;
                     process.memory_usage = mem;
                     // Ensure state is Running if we got metrics
@@ -410,21 +966,82 @@ impl ProcessRegistry {
                         process.state = ProcessState::Running;
                     }
                 } else {
-                    // Process has died - mark as errored or stopped
-                    if process.state == ProcessState::Running || process.state == ProcessState::Starting {
-                        process.state = if process.auto_restart {
-                            ProcessState::Errored
-                        } else {
-                            ProcessState::Stopped
-                        };
-                        process.pid = None;
-                        // Don't clear started_at - keep it for crash detection
+                    // Process has died; reap it if it is the daemon's child
+                    if matches!(
+                        process.state,
+                        ProcessState::Running | ProcessState::Starting | ProcessState::Reloading
+                    ) {
+                        let exit = process.pid.and_then(reap);
+                        process.record_exit(exit);
                     }
                 }
             }
         }
     }
 
+    /// Running processes over their `max_memory_restart`, or over their
+    /// `max_cpu` for long enough, with the threshold each crossed. Checked
+    /// against the metrics of the last refresh.
+    pub fn check_thresholds(&self) -> Vec<(String, RestartReason)> {
+        let Ok(mut inner) = self.inner.write() else {
+            return vec![];
+        };
+        let now = Instant::now();
+        let mut crossed = Vec::new();
+        for process in inner.processes.values_mut() {
+            if process.state != ProcessState::Running {
+                continue;
+            }
+            if process.max_memory > 0 && process.memory_usage > process.max_memory {
+                process.cpu_high_since = None;
+                let reason = RestartReason::MaxMemory {
+                    memory: process.memory_usage,
+                    limit: process.max_memory,
+                };
+                crossed.push((process.name.clone(), reason));
+                continue;
+            }
+
+            let Some(max_cpu) = process.max_cpu else {
+                continue;
+            };
+            if process.cpu_usage <= max_cpu.percent {
+                process.cpu_high_since = None;
+                continue;
+            }
+            let since = *process.cpu_high_since.get_or_insert(now);
+            if now.duration_since(since) >= max_cpu.sustained {
+                process.cpu_high_since = None;
+                crossed.push((process.name.clone(), RestartReason::MaxCpu(max_cpu)));
+            }
+        }
+        crossed
+    }
+
+    /// Reap the running processes that have exited, recording how they
+    /// ended. Returns their names.
+    pub fn reap_exited(&self) -> Vec<String> {
+        let mut inner = match self.inner.write() {
+            Ok(guard) => guard,
+            Err(_) => return vec![],
+        };
+
+        let mut exited = Vec::new();
+        for process in inner.processes.values_mut() {
+            if !matches!(
+                process.state,
+                ProcessState::Running | ProcessState::Starting | ProcessState::Reloading
+            ) {
+                continue;
+            }
+            if let Some(exit) = process.pid.and_then(reap) {
+                process.record_exit(Some(exit));
+                exited.push(process.name.clone());
+            }
+        }
+        exited
+    }
+
     /// Check if any processes have died and need restart
     pub fn check_dead_processes(&self) -> Vec<String> {
         let mut dead = Vec::new();
@@ -434,7 +1051,7 @@ impl ProcessRegistry {
         };
 
         for process in inner.processes.values() {
-            if process.state == ProcessState::Errored && process.auto_restart {
+            if process.state == ProcessState::Restarting && process.pid.is_none() {
                 dead.push(process.name.clone());
             }
         }
@@ -442,6 +1059,93 @@ impl ProcessRegistry {
         dead
     }
 
+    /// Put a process waiting to restart into `Backoff` until its restart
+    /// delay is up, and return the delay. Once `max_restarts` restarts in a
+    /// row are used up it is left `Errored` instead, and `None` returned.
+    pub fn schedule_restart(&self, name: &str) -> Result<Option<Duration>, String> {
+        let mut inner = self.inner.write().map_err(|e| e.to_string())?;
+        let Some(process) = inner.processes.get_mut(name) else {
+            return Err(format!("Process '{}' not found", name));
+        };
+        let max_restarts = process.restart.max_restarts;
+        if max_restarts >= 0 && process.restart_attempts >= max_restarts as u32 {
+            process.state = ProcessState::Errored;
+            process.restart_attempts = 0;
+            process.next_restart_at = None;
+            return Ok(None);
+        }
+
+        process.restart_attempts += 1;
+        let delay = process
+            .restart
+            .delay(process.restart_attempts, jitter_noise());
+        process.state = ProcessState::Backoff;
+        process.next_restart_at = Some(Utc::now() + delay);
+        Ok(Some(delay))
+    }
+
+    /// Processes in `Backoff` whose restart delay is up, and crash-looping
+    /// ones whose cooldown is
+    pub fn due_restarts(&self) -> Vec<String> {
+        let inner = match self.inner.read() {
+            Ok(guard) => guard,
+            Err(_) => return vec![],
+        };
+        let now = Utc::now();
+        inner
+            .processes
+            .values()
+            .filter(|p| matches!(p.state, ProcessState::Backoff | ProcessState::CrashLooping))
+            .filter(|p| p.next_restart_at.is_none_or(|at| at <= now))
+            .map(|p| p.name.clone())
+            .collect()
+    }
+
+    /// Start the restarts in a row over for processes that have stayed up
+    /// long enough to count as stable
+    pub fn reset_stable_restarts(&self) {
+        let Ok(mut inner) = self.inner.write() else {
+            return;
+        };
+        let now = Utc::now();
+        for process in inner.processes.values_mut() {
+            let Some(started) = process.started_at else {
+                continue;
+            };
+            let uptime = now.signed_duration_since(started).to_std();
+            let stable = uptime.unwrap_or_default() >= process.restart.stable_after();
+            if process.state == ProcessState::Running && stable {
+                process.restart_attempts = 0;
+            }
+        }
+    }
+
+    /// Mark a process stopped by the daemon. Nothing changes if a restart on
+    /// another thread spawned it again since `pid` was stopped.
+    pub fn mark_stopped(
+        &self,
+        name: &str,
+        pid: u32,
+        exit: Option<ExitReason>,
+    ) -> Result<(), String> {
+        let mut inner = self.inner.write().map_err(|e| e.to_string())?;
+        let Some(process) = inner.processes.get_mut(name) else {
+            return Err(format!("Process '{}' not found", name));
+        };
+        if process.pid.is_some_and(|current| current != pid) {
+            return Ok(());
+        }
+        process.state = ProcessState::Stopped;
+        process.pid = None;
+        process.restart_attempts = 0;
+        process.next_restart_at = None;
+        process.recent_crashes.clear();
+        if exit.is_some() {
+            process.last_exit = exit;
+        }
+        Ok(())
+    }
+
     /// Get all running processes
     pub fn get_running_processes(&self) -> Vec<ProcessInfo> {
         let inner = match self.inner.read() {
@@ -457,6 +1161,17 @@ impl ProcessRegistry {
             .collect()
     }
 
+    /// Remember the secret values a process was spawned with
+    pub fn set_secret_values(&self, name: &str, values: Vec<String>) -> Result<(), String> {
+        let mut inner = self.inner.write().map_err(|e| e.to_string())?;
+        if let Some(process) = inner.processes.get_mut(name) {
+            process.secret_values = values;
+            Ok(())
+        } else {
+            Err(format!("Process '{}' not found", name))
+        }
+    }
+
     /// Update health status for a process
     pub fn update_health_status(&self, name: &str, status: HealthStatus) -> Result<(), String> {
         let mut inner = self.inner.write().map_err(|e| e.to_string())?;
@@ -495,39 +1210,52 @@ impl ProcessRegistry {
         }
     }
 
-    /// Check if process died quickly (< 5 seconds) and increment counter
-    /// Returns true if we should stop restarting (3 quick crashes)
-    pub fn check_quick_crash(&self, name: &str) -> bool {
+    /// Record a crash of a process about to be restarted. Returns true once
+    /// it has crashed `max_crashes` times within the crash-loop `window`,
+    /// leaving it `CrashLooping` until the `cooldown` is up.
+    pub fn check_crash_loop(&self, name: &str) -> bool {
         let mut inner = match self.inner.write() {
             Ok(guard) => guard,
             Err(_) => return false,
         };
+        let Some(process) = inner.processes.get_mut(name) else {
+            return false;
+        };
 
-        if let Some(process) = inner.processes.get_mut(name) {
-            if let Some(started) = process.started_at {
-                let uptime = Utc::now().signed_duration_since(started);
-                if uptime.num_seconds() < 5 {
-                    // Quick crash!
-                    process.quick_crash_count += 1;
-                    if process.quick_crash_count >= 3 {
-                        // Too many quick crashes, stop restarting
-                        return true;
-                    }
-                } else {
-                    // Process ran for > 5 seconds, reset quick crash counter
-                    process.quick_crash_count = 0;
-                }
-            }
+        let now = Utc::now();
+        let window = process.crash_loop.window;
+        process
+            .recent_crashes
+            .retain(|at| now.signed_duration_since(*at).to_std().unwrap_or_default() < window);
+        process.recent_crashes.push(now);
+        if (process.recent_crashes.len() as u32) < process.crash_loop.max_crashes {
+            return false;
         }
-        false
+
+        process.state = ProcessState::CrashLooping;
+        process.next_restart_at = Some(now + process.crash_loop.cooldown);
+        process.recent_crashes.clear();
+        process.restart_attempts = 0;
+        true
     }
 
-    /// Reset quick crash counter (called on successful long run)
-    pub fn reset_quick_crash_count(&self, name: &str) -> Result<(), String> {
+    /// Clear a process's restart and crash counters. A process waiting out
+    /// its backoff or crash-loop cooldown is restarted right away; returns
+    /// whether it was.
+    pub fn reset_counters(&self, name: &str) -> Result<bool, String> {
         let mut inner = self.inner.write().map_err(|e| e.to_string())?;
         if let Some(process) = inner.processes.get_mut(name) {
-            process.quick_crash_count = 0;
-            Ok(())
+            process.restart_count = 0;
+            process.restart_attempts = 0;
+            process.recent_crashes.clear();
+            let waiting = matches!(
+                process.state,
+                ProcessState::Backoff | ProcessState::CrashLooping
+            );
+            if waiting {
+                process.next_restart_at = None;
+            }
+            Ok(waiting)
         } else {
             Err(format!("Process '{}' not found", name))
         }
@@ -549,22 +1277,65 @@ impl ProcessRegistry {
         output.push_str(&"-".repeat(80));
         output.push('\n');
 
+        let mut config_changed = false;
+        // Instances and apps of one file share a config, so hash it once
+        let mut checksums = HashMap::new();
+        let mut current_group: Option<&str> = None;
+
         for (idx, process) in processes.iter().enumerate() {
+            // Instances of a clustered app are listed under a summary row
+            if process.instance.is_some() && current_group != Some(process.app_name()) {
+                let app_name = process.app_name();
+                let instances: Vec<&ProcessInfo> = processes
+                    .iter()
+                    .filter(|p| p.instance.is_some() && p.app_name() == app_name)
+                    .collect();
+                let running = instances
+                    .iter()
+                    .filter(|p| p.state == ProcessState::Running)
+                    .count();
+                output.push_str(&format!(
+                    "{:<4} {:<20} {}/{} running\n",
+                    "",
+                    truncate(app_name, 20),
+                    running,
+                    instances.len()
+                ));
+            }
+            current_group = process.instance.map(|_| process.app_name());
+
             let (status_icon, status_text) = match process.state {
                 ProcessState::Running => ("🟢", "running"),
                 ProcessState::Stopped => ("⚪", "stopped"),
                 ProcessState::Errored => ("🔴", "errored"),
                 ProcessState::Starting => ("🟡", "starting"),
+                ProcessState::Waiting => ("🟡", "waiting"),
                 ProcessState::Restarting => ("🟡", "restarting"),
                 ProcessState::Stopping => ("🟠", "stopping"),
+                ProcessState::Backoff => ("🟡", "backoff"),
+                ProcessState::CrashLooping => ("🔴", "crash-looping"),
+                ProcessState::Reloading => ("🟡", "reloading"),
             };
 
-            let status_display = format!("{} {}", status_icon, status_text);
+            let status_display = match process.restart_countdown() {
+                Some(countdown) => format!("{} {} {}", status_icon, status_text, countdown),
+                None => format!("{} {}", status_icon, status_text),
+            };
+
+            // Flag apps whose config was edited since they were loaded
+            let indent = if process.instance.is_some() { "  " } else { "" };
+            let width = 20 - indent.len();
+            let name = if process.config_changed(&mut checksums) {
+                config_changed = true;
+                format!("{}{}*", indent, truncate(&process.name, width - 1))
+            } else {
+                format!("{}{}", indent, truncate(&process.name, width))
+            };
 
             output.push_str(&format!(
                 "{:<4} {:<20} {:<18} {:<8} {:<8} {:<10} {:<10}\n",
                 idx,
-                truncate(&process.name, 20),
+                name,
                 status_display,
                 process.restart_count,
                 format!("{:.1}%", process.cpu_usage),
@@ -573,13 +1344,20 @@ impl ProcessRegistry {
             ));
         }
 
+        if config_changed {
+            output.push_str("\n* config changed on disk, run `bpm reload-config` to apply\n");
+        }
+
         output
     }
 
     /// Save registry state to disk
     pub fn save_state(&self, path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
         let inner = self.inner.read().map_err(|e| e.to_string())?;
-        let processes: Vec<&ProcessInfo> = inner.processes.values().collect();
+        // Secrets stay out of the state file; resurrect reads them back from
+        // the config
+        let processes: Vec<ProcessInfo> =
+            inner.processes.values().map(|p| p.redacted()).collect();
         let json = serde_json::to_string_pretty(&processes)?;
 
         if let Some(parent) = path.parent() {
@@ -607,6 +1385,44 @@ impl ProcessRegistry {
     }
 }
 
+/// Usage of a running process: CPU percent and memory of its process
+/// tree, or total CPU microseconds and memory of its cgroup
+enum Metrics {
+    Tree(f32, u64),
+    Cgroup(u64, u64),
+}
+
+/// Reap `pid` if it is an exited child of the daemon
+fn reap(pid: u32) -> Option<ExitReason> {
+    let pid = nix::unistd::Pid::from_raw(pid as i32);
+    waitpid(pid, Some(WaitPidFlag::WNOHANG))
+        .ok()
+        .and_then(ExitReason::from_wait_status)
+}
+
+/// A number in [0, 1) for restart delay jitter. It only has to differ
+/// between processes and restarts, not be unpredictable.
+fn jitter_noise() -> f64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    nanos as f64 / 1_000_000_000.0
+}
+
+/// Seconds in the largest whole unit, e.g. `90` as `1m`
+fn short_duration(secs: i64) -> String {
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else if secs < 86400 {
+        format!("{}h", secs / 3600)
+    } else {
+        format!("{}d", secs / 86400)
+    }
+}
+
 /// Truncate a string to a maximum length
 fn truncate(s: &str, max_len: usize) -> String {
     if s.len() > max_len {
@@ -619,32 +1435,61 @@ fn truncate(s: &str, max_len: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::read_config::Backoff;
 
     fn create_test_process(name: &str) -> ProcessInfo {
         ProcessInfo {
             name: name.to_string(),
+            project: None,
+            profile: None,
+            instance: None,
             pid: None,
             state: ProcessState::Stopped,
             config_path: PathBuf::from("/tmp/test.json"),
+            config_checksum: None,
+            env_files: vec![],
             script: "echo".to_string(),
             args: vec!["hello".to_string()],
             cwd: None,
             env: HashMap::new(),
             restart_count: 0,
             started_at: None,
-            quick_crash_count: 0,
             cpu_usage: 0.0,
             memory_usage: 0,
             stdout_log: PathBuf::from("/tmp/out.log"),
             stderr_log: PathBuf::from("/tmp/err.log"),
             auto_restart: true,
+            restart: RestartConfig::default(),
+            last_exit: None,
+            last_restart_reason: None,
+            restart_attempts: 0,
+            next_restart_at: None,
+            crash_loop: CrashLoop::default(),
+            reload: ReloadConfig::default(),
+            recent_crashes: vec![],
             max_memory: 0,
+            max_cpu: None,
+            cpu_high_since: None,
             healthcheck: None,
             health_status: HealthStatus::Unknown,
             last_health_check: None,
             health_failures: 0,
             watch_dirs: vec![],
             watch_patterns: vec![],
+            depends_on: vec![],
+            env_from_file: vec![],
+            secrets: BTreeMap::new(),
+            secret_patterns: secrets::default_patterns(),
+            secret_values: vec![],
+            redacted_env: vec![],
+            adhoc_app: None,
+            stop_sequence: vec![StopStep::default()],
+            run_as: RunAs::default(),
+            limits: Limits::default(),
+            cgroup: CgroupConfig::default(),
+            cgroup_path: None,
+            oom_kills: 0,
+            cpu_sample: None,
         }
     }
 
@@ -775,51 +1620,523 @@ mod tests {
     }
 
     #[test]
-    fn test_quick_crash_detection() {
+    fn test_crash_loop_detection() {
         let registry = ProcessRegistry::new();
         let mut info = create_test_process("crash-test");
-        info.started_at = Some(Utc::now());
-        
+        info.crash_loop.max_crashes = 3;
+        info.crash_loop.cooldown = Duration::from_secs(120);
         registry.register(info).unwrap();
 
-        // Simulate quick crashes
-        assert!(!registry.check_quick_crash("crash-test")); // 1st crash
-        assert!(!registry.check_quick_crash("crash-test")); // 2nd crash
-        assert!(registry.check_quick_crash("crash-test"));  // 3rd crash - should stop
+        assert!(!registry.check_crash_loop("crash-test")); // 1st crash
+        assert!(!registry.check_crash_loop("crash-test")); // 2nd crash
+        assert!(registry.check_crash_loop("crash-test")); // 3rd crash - crash loop
 
         let process = registry.get("crash-test").unwrap();
-        assert_eq!(process.quick_crash_count, 3);
+        assert_eq!(process.state, ProcessState::CrashLooping);
+        assert_eq!(process.restart_countdown().as_deref(), Some("2m"));
+        assert!(registry.due_restarts().is_empty());
+        assert!(registry.format_table().contains("crash-looping"));
+
+        // Retried once the cooldown is up, with a clean slate
+        let mut inner = registry.inner.write().unwrap();
+        inner.processes.get_mut("crash-test").unwrap().next_restart_at = Some(Utc::now());
+        drop(inner);
+        assert_eq!(registry.due_restarts(), ["crash-test"]);
+        assert!(!registry.check_crash_loop("crash-test"));
     }
 
     #[test]
-    fn test_quick_crash_reset_on_long_run() {
+    fn test_crash_loop_window() {
         let registry = ProcessRegistry::new();
         let mut info = create_test_process("long-run-test");
-        // Set started_at to 10 seconds ago
-        info.started_at = Some(Utc::now() - chrono::Duration::seconds(10));
-        
+        info.crash_loop.max_crashes = 2;
+        // An old crash, outside the window
+        info.recent_crashes = vec![Utc::now() - chrono::Duration::seconds(61)];
         registry.register(info).unwrap();
 
-        // First crash after long run - should reset counter
-        assert!(!registry.check_quick_crash("long-run-test"));
-        
+        assert!(!registry.check_crash_loop("long-run-test"));
         let process = registry.get("long-run-test").unwrap();
-        assert_eq!(process.quick_crash_count, 0); // Reset because it ran > 5 seconds
+        assert_eq!(process.recent_crashes.len(), 1);
     }
 
     #[test]
-    fn test_manual_reset_quick_crash_count() {
+    fn test_reset_counters() {
         let registry = ProcessRegistry::new();
         let mut info = create_test_process("reset-test");
-        info.started_at = Some(Utc::now());
-        info.quick_crash_count = 2;
-        
+        info.restart_count = 7;
+        info.restart_attempts = 3;
+        info.recent_crashes = vec![Utc::now(), Utc::now()];
         registry.register(info).unwrap();
 
-        // Manually reset
-        assert!(registry.reset_quick_crash_count("reset-test").is_ok());
-        
+        assert_eq!(registry.reset_counters("reset-test"), Ok(false));
+
         let process = registry.get("reset-test").unwrap();
-        assert_eq!(process.quick_crash_count, 0);
+        assert_eq!(process.restart_count, 0);
+        assert_eq!(process.restart_attempts, 0);
+        assert!(process.recent_crashes.is_empty());
+        assert!(registry.reset_counters("missing").is_err());
+
+        // A crash-looping process doesn't wait out its cooldown any more
+        let mut info = create_test_process("looping");
+        info.state = ProcessState::CrashLooping;
+        info.next_restart_at = Some(Utc::now() + chrono::Duration::minutes(5));
+        registry.register(info).unwrap();
+        assert!(registry.due_restarts().is_empty());
+        assert_eq!(registry.reset_counters("looping"), Ok(true));
+        assert_eq!(registry.due_restarts(), ["looping"]);
+    }
+
+    #[test]
+    fn test_registry_find_and_projects() {
+        let registry = ProcessRegistry::new();
+
+        let mut billing = create_test_process("billing/worker");
+        billing.project = Some("billing".to_string());
+        let mut search = create_test_process("search/worker");
+        search.project = Some("search".to_string());
+        let mut api = create_test_process("search/api");
+        api.project = Some("search".to_string());
+
+        registry.register(billing).unwrap();
+        registry.register(search).unwrap();
+        registry.register(api).unwrap();
+
+        assert_eq!(registry.find("billing/worker").unwrap().name, "billing/worker");
+        // Unqualified names resolve only when unambiguous
+        assert_eq!(registry.find("api").unwrap().name, "search/api");
+        assert!(registry.find("worker").is_none());
+
+        let names: Vec<String> = registry
+            .list_project("search")
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, vec!["search/api", "search/worker"]);
+    }
+
+    #[test]
+    fn test_instances_are_grouped() {
+        let registry = ProcessRegistry::new();
+        for index in [10, 2, 0] {
+            let mut instance = create_test_process(&format!("web/api:{}", index));
+            instance.project = Some("web".to_string());
+            instance.instance = Some(index);
+            registry.register(instance).unwrap();
+        }
+        registry.register(create_test_process("worker")).unwrap();
+
+        let names: Vec<String> = registry.list().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["web/api:0", "web/api:2", "web/api:10", "worker"]);
+
+        assert_eq!(registry.find_instances("web/api").len(), 3);
+        assert_eq!(registry.find_instances("api").len(), 3);
+        assert!(registry.find_instances("worker").is_empty());
+        assert!(registry.format_table().contains("0/3 running"));
+    }
+
+    #[test]
+    fn test_save_state_redacts_secrets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let state_path = dir.path().join("state.json");
+
+        let registry = ProcessRegistry::new();
+        let mut process = create_test_process("secret-test");
+        process.env.insert("JWT_SECRET".to_string(), "hunter2-jwt".to_string());
+        process.env.insert("PORT".to_string(), "3000".to_string());
+        // A value that only looks masked
+        process
+            .env
+            .insert("BANNER".to_string(), "********".to_string());
+        registry.register(process).unwrap();
+        registry
+            .set_secret_values("secret-test", vec!["from-vault".to_string()])
+            .unwrap();
+
+        registry.save_state(&state_path).unwrap();
+        let saved = std::fs::read_to_string(&state_path).unwrap();
+        assert!(!saved.contains("hunter2-jwt"));
+        assert!(!saved.contains("from-vault"));
+        assert!(saved.contains("3000"));
+
+        let process = registry.get("secret-test").unwrap();
+        assert!(!process.has_masked_env());
+        let loaded = ProcessRegistry::new();
+        loaded.load_state(&state_path).unwrap();
+        let reloaded = loaded.get("secret-test").unwrap();
+        assert!(reloaded.has_masked_env());
+        assert_eq!(reloaded.redacted_env, vec!["JWT_SECRET"]);
+        assert_eq!(reloaded.env["BANNER"], "********");
+
+        assert_eq!(
+            process.mask_secrets("token hunter2-jwt, db from-vault, port 3000"),
+            "token ********, db ********, port 3000"
+        );
+    }
+
+    #[test]
+    fn test_apply_config_keeps_runtime_state() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("app.json");
+        std::fs::write(&config_path, "{}").unwrap();
+
+        let registry = ProcessRegistry::new();
+        let mut process = create_test_process("reload-test");
+        process.config_path = config_path.clone();
+        process.config_checksum = state::config_checksum(&config_path, &[]);
+        registry.register(process.clone()).unwrap();
+        registry.update_pid("reload-test", Some(4242)).unwrap();
+        registry.increment_restart_count("reload-test").unwrap();
+
+        assert!(!registry
+            .get("reload-test")
+            .unwrap()
+            .config_changed(&mut HashMap::new()));
+        std::fs::write(&config_path, r#"{"edited": true}"#).unwrap();
+        assert!(registry
+            .get("reload-test")
+            .unwrap()
+            .config_changed(&mut HashMap::new()));
+
+        let mut updated = process;
+        updated.args = vec!["goodbye".to_string()];
+        updated.config_checksum = state::config_checksum(&config_path, &[]);
+        registry.apply_config(updated).unwrap();
+
+        let process = registry.get("reload-test").unwrap();
+        assert_eq!(process.args, vec!["goodbye"]);
+        assert_eq!(process.pid, Some(4242));
+        assert_eq!(process.restart_count, 1);
+        assert!(!process.config_changed(&mut HashMap::new()));
+    }
+
+    #[test]
+    fn test_config_changed_by_extends_base_or_env_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config_path = dir.path().join("app.json");
+        std::fs::write(
+            dir.path().join("_base.json"),
+            r#"{"defaults": {"cwd": "/srv"}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &config_path,
+            r#"{"extends": "_base.json", "name": "api", "script": "node", "env_file": [".env"]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join(".env"), "PORT=3000\n").unwrap();
+
+        let config = crate::config::read_config::AppConfig::from_file(&config_path).unwrap();
+        let app = config.get_apps()[0].resolve(dir.path(), 0).unwrap();
+        let process = ProcessInfo::from_app(&app, config_path.clone());
+        assert_eq!(process.env_files, vec![dir.path().join(".env")]);
+        assert!(!process.config_changed(&mut HashMap::new()));
+
+        std::fs::write(dir.path().join(".env"), "PORT=4000\n").unwrap();
+        assert!(process.config_changed(&mut HashMap::new()));
+        std::fs::write(dir.path().join(".env"), "PORT=3000\n").unwrap();
+        assert!(!process.config_changed(&mut HashMap::new()));
+
+        std::fs::write(
+            dir.path().join("_base.json"),
+            r#"{"defaults": {"cwd": "/opt"}}"#,
+        )
+        .unwrap();
+        assert!(process.config_changed(&mut HashMap::new()));
+
+        // Processes sharing a config and env files share one checksum
+        let mut checksums = HashMap::new();
+        let mut instance = process.clone();
+        instance.name = "api:1".to_string();
+        assert!(process.config_changed(&mut checksums));
+        assert!(instance.config_changed(&mut checksums));
+        assert_eq!(checksums.len(), 1);
+    }
+
+    #[test]
+    fn test_restart_policies() {
+        let exit = |code| Some(ExitReason::Code(code));
+        let killed = Some(ExitReason::Signal("SIGSEGV".to_string()));
+        let mut process = create_test_process("policy");
+
+        process.restart.policy = RestartPolicy::OnFailure;
+        process.restart.success_exit_codes = vec![0, 2];
+        assert!(!process.should_restart(exit(0).as_ref()));
+        assert!(!process.should_restart(exit(2).as_ref()));
+        assert!(process.should_restart(exit(1).as_ref()));
+        assert!(process.should_restart(killed.as_ref()));
+        assert!(process.should_restart(None));
+
+        process.restart.policy = RestartPolicy::Always;
+        assert!(process.should_restart(exit(0).as_ref()));
+
+        process.restart.policy = RestartPolicy::Never;
+        process.restart.restart_on_exit_codes = vec![75];
+        assert!(!process.should_restart(exit(1).as_ref()));
+        assert!(process.should_restart(exit(75).as_ref()));
+
+        process.auto_restart = false;
+        assert!(!process.should_restart(exit(75).as_ref()));
+    }
+
+    #[test]
+    fn test_mark_stopped_keeps_a_respawned_process() {
+        let registry = ProcessRegistry::new();
+        registry.register(create_test_process("respawn")).unwrap();
+        registry.update_pid("respawn", Some(100)).unwrap();
+        // Restarted on another thread while pid 100 was being stopped
+        registry.update_pid("respawn", Some(200)).unwrap();
+
+        registry.mark_stopped("respawn", 100, None).unwrap();
+        let process = registry.get("respawn").unwrap();
+        assert_eq!(process.pid, Some(200));
+        assert_eq!(process.state, ProcessState::Running);
+
+        registry.mark_stopped("respawn", 200, None).unwrap();
+        let process = registry.get("respawn").unwrap();
+        assert_eq!(process.pid, None);
+        assert_eq!(process.state, ProcessState::Stopped);
+    }
+
+    #[test]
+    // `reap_exited` waits for the child
+    #[allow(clippy::zombie_processes)]
+    fn test_exit_during_reload() {
+        let registry = ProcessRegistry::new();
+        let child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        let mut info = create_test_process("reloading");
+        info.state = ProcessState::Reloading;
+        info.pid = Some(child.id());
+        registry.register(info).unwrap();
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while registry.reap_exited().is_empty() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        // The reload decides what comes next, not the crash handling
+        let process = registry.get("reloading").unwrap();
+        assert_eq!(process.last_exit, Some(ExitReason::Code(3)));
+        assert_eq!(process.state, ProcessState::Reloading);
+        assert_eq!(process.pid, None);
+        assert!(registry.check_dead_processes().is_empty());
+
+        registry.cancel_reload("reloading").unwrap();
+        assert_eq!(
+            registry.get("reloading").unwrap().state,
+            ProcessState::Restarting
+        );
+    }
+
+    #[test]
+    // `reap_exited` waits for the children
+    #[allow(clippy::zombie_processes)]
+    fn test_reap_exited_records_exit() {
+        let registry = ProcessRegistry::new();
+        for (name, script) in [
+            ("clean", "exit 0"),
+            ("failed", "exit 3"),
+            ("killed", "kill -9 $$"),
+        ] {
+            let child = std::process::Command::new("sh")
+                .args(["-c", script])
+                .spawn()
+                .unwrap();
+            let mut info = create_test_process(name);
+            info.state = ProcessState::Running;
+            info.pid = Some(child.id());
+            registry.register(info).unwrap();
+        }
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let mut exited = Vec::new();
+        while exited.len() < 3 && std::time::Instant::now() < deadline {
+            exited.extend(registry.reap_exited());
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(exited.len(), 3);
+
+        let clean = registry.get("clean").unwrap();
+        assert_eq!(clean.last_exit, Some(ExitReason::Code(0)));
+        assert_eq!(clean.state, ProcessState::Stopped);
+        assert_eq!(clean.pid, None);
+
+        let failed = registry.get("failed").unwrap();
+        assert_eq!(
+            failed.last_exit.as_ref().unwrap().to_string(),
+            "exit code 3"
+        );
+        assert_eq!(failed.state, ProcessState::Restarting);
+        let mut dead = registry.check_dead_processes();
+        dead.sort();
+        assert_eq!(dead, ["failed", "killed"]);
+
+        let killed = registry.get("killed").unwrap();
+        assert_eq!(
+            killed.last_exit.as_ref().unwrap().to_string(),
+            "killed by SIGKILL"
+        );
+    }
+
+    #[test]
+    fn test_oom_kill_is_the_exit_reason() {
+        let cgroup = tempfile::tempdir().unwrap();
+        let events = cgroup.path().join("memory.events");
+        std::fs::write(&events, "oom 1\noom_kill 1\n").unwrap();
+
+        let registry = ProcessRegistry::new();
+        registry.register(create_test_process("hungry")).unwrap();
+        registry
+            .set_cgroup("hungry", Some(cgroup.path().to_path_buf()))
+            .unwrap();
+        assert_eq!(registry.get("hungry").unwrap().oom_kills, 1);
+
+        // A kill before this run doesn't count
+        let mut process = registry.get("hungry").unwrap();
+        process.record_exit(Some(ExitReason::Code(1)));
+        assert_eq!(process.last_exit, Some(ExitReason::Code(1)));
+
+        std::fs::write(&events, "oom 2\noom_kill 2\n").unwrap();
+        let mut process = registry.get("hungry").unwrap();
+        process.record_exit(Some(ExitReason::Signal("SIGKILL".to_string())));
+        assert_eq!(process.last_exit, Some(ExitReason::OomKilled));
+        assert_eq!(process.state, ProcessState::Restarting);
+    }
+
+    #[test]
+    fn test_check_thresholds() {
+        let registry = ProcessRegistry::new();
+        let mut info = create_test_process("leaky");
+        info.state = ProcessState::Running;
+        info.max_memory = 512 << 20;
+        info.memory_usage = 600 << 20;
+        registry.register(info).unwrap();
+
+        let mut info = create_test_process("busy");
+        info.state = ProcessState::Running;
+        info.cpu_usage = 95.0;
+        info.max_cpu = Some(MaxCpu {
+            percent: 90.0,
+            sustained: Duration::from_secs(60),
+        });
+        registry.register(info).unwrap();
+
+        // Memory restarts right away; CPU only once it has stayed high
+        let crossed = registry.check_thresholds();
+        assert_eq!(
+            crossed,
+            vec![(
+                "leaky".to_string(),
+                RestartReason::MaxMemory {
+                    memory: 600 << 20,
+                    limit: 512 << 20
+                }
+            )]
+        );
+        assert_eq!(
+            crossed[0].1.to_string(),
+            "used 600.0MB of memory, above max_memory_restart 512M"
+        );
+        assert!(registry.get("busy").unwrap().cpu_high_since.is_some());
+
+        let mut busy = registry.get("busy").unwrap();
+        busy.cpu_high_since = Some(Instant::now() - Duration::from_secs(61));
+        busy.memory_usage = 0;
+        registry.remove("busy");
+        registry.register(busy).unwrap();
+        registry.remove("leaky");
+        let crossed = registry.check_thresholds();
+        assert_eq!(crossed.len(), 1);
+        assert_eq!(crossed[0].1.to_string(), "used over 90% CPU for 1m");
+        assert!(registry.get("busy").unwrap().cpu_high_since.is_none());
+
+        // Dropping below the threshold starts the count over
+        let mut busy = registry.get("busy").unwrap();
+        busy.cpu_usage = 10.0;
+        busy.cpu_high_since = Some(Instant::now());
+        registry.remove("busy");
+        registry.register(busy).unwrap();
+        assert!(registry.check_thresholds().is_empty());
+        assert!(registry.get("busy").unwrap().cpu_high_since.is_none());
+    }
+
+    #[test]
+    fn test_schedule_restart() {
+        let registry = ProcessRegistry::new();
+        let mut info = create_test_process("flaky");
+        info.state = ProcessState::Restarting;
+        info.restart.max_restarts = 2;
+        info.restart.restart_delay = Duration::ZERO;
+        registry.register(info).unwrap();
+
+        for attempt in 1..=2 {
+            assert_eq!(registry.schedule_restart("flaky"), Ok(Some(Duration::ZERO)));
+            let process = registry.get("flaky").unwrap();
+            assert_eq!(process.state, ProcessState::Backoff);
+            assert_eq!(process.restart_attempts, attempt);
+            assert_eq!(registry.due_restarts(), ["flaky"]);
+        }
+
+        // The third restart in a row is one too many
+        assert_eq!(registry.schedule_restart("flaky"), Ok(None));
+        let process = registry.get("flaky").unwrap();
+        assert_eq!(process.state, ProcessState::Errored);
+        assert_eq!(process.restart_attempts, 0);
+        assert!(registry.due_restarts().is_empty());
+    }
+
+    #[test]
+    fn test_backoff_countdown_and_reset() {
+        let registry = ProcessRegistry::new();
+        let mut info = create_test_process("backoff");
+        info.state = ProcessState::Restarting;
+        info.restart.restart_delay = Duration::from_secs(10);
+        info.restart.backoff = Some(Backoff {
+            jitter: 0.0,
+            reset_after: Duration::from_secs(30),
+            ..Backoff::default()
+        });
+        registry.register(info).unwrap();
+
+        registry.schedule_restart("backoff").unwrap();
+        registry
+            .update_state("backoff", ProcessState::Restarting)
+            .unwrap();
+        let delay = registry.schedule_restart("backoff").unwrap();
+        assert_eq!(delay, Some(Duration::from_secs(20)));
+        let process = registry.get("backoff").unwrap();
+        assert_eq!(process.restart_countdown().as_deref(), Some("20s"));
+        assert!(registry.due_restarts().is_empty());
+        assert!(registry.format_table().contains("backoff 20s"));
+
+        // Up for a while: the next crash starts from restart_delay again
+        registry.update_pid("backoff", Some(1)).unwrap();
+        registry.reset_stable_restarts();
+        assert_eq!(registry.get("backoff").unwrap().restart_attempts, 2);
+        let mut inner = registry.inner.write().unwrap();
+        inner.processes.get_mut("backoff").unwrap().started_at =
+            Some(Utc::now() - chrono::Duration::seconds(31));
+        drop(inner);
+        registry.reset_stable_restarts();
+        assert_eq!(registry.get("backoff").unwrap().restart_attempts, 0);
+    }
+
+    #[test]
+    fn test_stable_uptime_resets_restarts_without_backoff() {
+        let registry = ProcessRegistry::new();
+        let mut info = create_test_process("steady");
+        info.state = ProcessState::Restarting;
+        info.restart.max_restarts = 2;
+        registry.register(info).unwrap();
+
+        registry.schedule_restart("steady").unwrap();
+        registry.update_pid("steady", Some(1)).unwrap();
+        let mut inner = registry.inner.write().unwrap();
+        inner.processes.get_mut("steady").unwrap().started_at =
+            Some(Utc::now() - chrono::Duration::seconds(61));
+        drop(inner);
+        registry.reset_stable_restarts();
+        assert_eq!(registry.get("steady").unwrap().restart_attempts, 0);
     }
 }
@@ -0,0 +1,280 @@
+//! Zero-downtime reloads
+//!
+//! `bpm reload` starts an app's new process next to the old one and only
+//! stops the old one once the new one is ready: once it passes its
+//! healthcheck while listening on the checked port itself, or with
+//! `wait_ready` once it sends `READY=1` to the socket in its
+//! `NOTIFY_SOCKET`, or with neither once it has stayed up for
+//! `READY_UPTIME`. A new process that exits first, or isn't ready within
+//! `ready_timeout`, is stopped again and the old one keeps running.
+
+use nix::unistd::Uid;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::read_config::format_duration;
+use crate::process_manager::health::{check_health, HealthCheckConfig, HealthStatus};
+use crate::process_manager::process::app_processes;
+
+/// How often a new process is checked for readiness
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// How long a new process without a readiness check has to stay up
+const READY_UPTIME: Duration = Duration::from_secs(1);
+
+static NEXT_SOCKET: AtomicU32 = AtomicU32::new(0);
+
+/// A socket a new process reports readiness on, as with systemd's
+/// `sd_notify`. The socket file is removed when it is dropped.
+#[derive(Debug)]
+pub struct NotifySocket {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl NotifySocket {
+    pub fn new() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "bpm-notify-{}-{}.sock",
+            std::process::id(),
+            NEXT_SOCKET.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Let `uid` send to the socket, for apps that run as another user
+    pub fn allow(&self, uid: Uid) -> Result<(), String> {
+        nix::unistd::chown(&self.path, Some(uid), None).map_err(|e| {
+            format!(
+                "failed to hand {} to uid {}: {}",
+                self.path.display(),
+                uid,
+                e
+            )
+        })
+    }
+
+    /// Whether `READY=1` has arrived since the last call
+    fn ready(&self) -> bool {
+        let mut buffer = [0u8; 4096];
+        let mut ready = false;
+        while let Ok(len) = self.socket.recv(&mut buffer) {
+            ready |= String::from_utf8_lossy(&buffer[..len])
+                .lines()
+                .any(|line| line == "READY=1");
+        }
+        ready
+    }
+}
+
+impl Drop for NotifySocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// How a new process shows it is ready
+#[derive(Debug, Clone, Copy)]
+pub enum Readiness<'a> {
+    /// It sends `READY=1` to the socket
+    Notify(&'a NotifySocket),
+    /// It passes its healthcheck, and one of its processes listens on the
+    /// port checked, so the old process answering doesn't count
+    Healthcheck(&'a HealthCheckConfig),
+    /// It stays up for `READY_UPTIME`
+    Uptime,
+}
+
+/// Block until the new process `child`, spawned at `started`, is ready.
+/// Fails if it exits first, or isn't ready within `timeout` of starting.
+pub fn wait_ready(
+    child: &mut Child,
+    started: Instant,
+    readiness: Readiness,
+    timeout: Duration,
+) -> Result<(), String> {
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Err(format!("exited ({})", status)),
+            Err(e) => return Err(format!("failed to wait for it: {}", e)),
+            Ok(None) => {}
+        }
+
+        let ready = match readiness {
+            Readiness::Notify(socket) => socket.ready(),
+            Readiness::Healthcheck(config) => {
+                config
+                    .check_type
+                    .port()
+                    .is_some_and(|port| listens_on(&app_processes(child.id(), None), port))
+                    && check_health(config) == HealthStatus::Healthy
+            }
+            Readiness::Uptime => started.elapsed() >= READY_UPTIME,
+        };
+        if ready {
+            return Ok(());
+        }
+        if started.elapsed() >= timeout {
+            return Err(format!("not ready within {}", format_duration(timeout)));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Whether a healthcheck can tell a new process from the one it replaces.
+/// A command can't, so an app reloaded on its command healthcheck has to
+/// use `wait_ready` instead.
+pub fn check_readiness(healthcheck: &HealthCheckConfig) -> Result<(), String> {
+    match healthcheck.check_type.port() {
+        Some(_) => Ok(()),
+        None => Err(
+            "its healthcheck is a command, which can't tell the new process from the old \
+             one; set `reload.wait_ready` and have the app send READY=1"
+                .to_string(),
+        ),
+    }
+}
+
+/// Whether one of `pids` has a socket listening on TCP `port`
+fn listens_on(pids: &[u32], port: u16) -> bool {
+    let sockets: Vec<String> = ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|table| std::fs::read_to_string(table).ok())
+        .flat_map(|table| listening_inodes(&table, port))
+        .map(|inode| format!("socket:[{}]", inode))
+        .collect();
+    pids.iter().any(|pid| {
+        std::fs::read_dir(format!("/proc/{}/fd", pid))
+            .into_iter()
+            .flatten()
+            .filter_map(|fd| std::fs::read_link(fd.ok()?.path()).ok())
+            .any(|target| {
+                sockets
+                    .iter()
+                    .any(|socket| target.as_os_str() == socket.as_str())
+            })
+    })
+}
+
+/// The inodes of the sockets listening on `port` in a `/proc/net/tcp`
+/// table, whose addresses are `ADDRESS:PORT` in hex and state `0A` is
+/// LISTEN
+fn listening_inodes(table: &str, port: u16) -> Vec<String> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let local_port = fields.get(1)?.rsplit(':').next()?;
+            let listening = u16::from_str_radix(local_port, 16).ok() == Some(port)
+                && fields.get(3) == Some(&"0A");
+            listening.then(|| fields.get(9).map(|inode| inode.to_string()))?
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_manager::health::HealthCheckType;
+    use std::process::Command;
+
+    fn sleeper() -> Child {
+        Command::new("sleep").arg("5").spawn().unwrap()
+    }
+
+    #[test]
+    fn test_ready_on_notify() {
+        let socket = NotifySocket::new().unwrap();
+        let path = socket.path().to_path_buf();
+        let mut child = sleeper();
+
+        let sender = UnixDatagram::unbound().unwrap();
+        sender.send_to(b"STATUS=warming up", &path).unwrap();
+        assert!(!socket.ready());
+        sender.send_to(b"STATUS=up\nREADY=1\n", &path).unwrap();
+        let result = wait_ready(
+            &mut child,
+            Instant::now(),
+            Readiness::Notify(&socket),
+            Duration::from_secs(2),
+        );
+        let _ = child.kill();
+        let _ = child.wait();
+        assert_eq!(result, Ok(()));
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_exit_or_timeout_is_not_ready() {
+        let socket = NotifySocket::new().unwrap();
+        let mut child = sleeper();
+        let result = wait_ready(
+            &mut child,
+            Instant::now(),
+            Readiness::Notify(&socket),
+            Duration::from_millis(300),
+        );
+        let _ = child.kill();
+        let _ = child.wait();
+        assert_eq!(result, Err("not ready within 300ms".to_string()));
+
+        let mut child = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        let started = Instant::now();
+        let error = wait_ready(
+            &mut child,
+            started,
+            Readiness::Uptime,
+            Duration::from_secs(5),
+        )
+        .unwrap_err();
+        assert!(error.contains("exited"), "{}", error);
+    }
+
+    #[test]
+    fn test_healthcheck_answered_by_another_process_is_not_ready() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(listens_on(&[std::process::id()], port));
+
+        let healthcheck = HealthCheckConfig {
+            check_type: HealthCheckType::Tcp {
+                host: "127.0.0.1".to_string(),
+                port,
+            },
+            ..HealthCheckConfig::default()
+        };
+        assert_eq!(check_health(&healthcheck), HealthStatus::Healthy);
+        let mut child = sleeper();
+        let result = wait_ready(
+            &mut child,
+            Instant::now(),
+            Readiness::Healthcheck(&healthcheck),
+            Duration::from_millis(500),
+        );
+        let _ = child.kill();
+        let _ = child.wait();
+        assert_eq!(result, Err("not ready within 500ms".to_string()));
+
+        let command = HealthCheckConfig {
+            check_type: HealthCheckType::Command {
+                cmd: "true".to_string(),
+                args: vec![],
+            },
+            ..HealthCheckConfig::default()
+        };
+        assert!(check_readiness(&command).is_err());
+        assert!(check_readiness(&healthcheck).is_ok());
+    }
+}
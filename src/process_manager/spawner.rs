@@ -11,6 +11,8 @@ use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 use tokio::sync::Mutex;
 
+use crate::config::interpolate::expand_tilde;
+
 use std::time::{Duration, Instant};
 pub struct LaunchCommand {
     program: String,
@@ -31,9 +33,11 @@ pub async fn spawn(
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     loop {
         let app_start_timestamp = Instant::now();
-        // let directory = directory.unwrap_or("~/");
-
-        let directory = directory.clone().unwrap_or_else(|| "~/".to_string());
+        let directory = directory
+            .as_deref()
+            .map(expand_tilde)
+            .or_else(|| dirs::home_dir().map(|home| home.display().to_string()))
+            .unwrap_or_else(|| "/".to_string());
         let mut child = Command::new(&program)
             .current_dir(directory)
             .args(&args)
@@ -0,0 +1,246 @@
+//! Stopping processes
+//!
+//! An app is sent each signal of its stop sequence in turn, polled until it
+//! exits or the step's timeout runs out, and finally SIGKILLed. Signals go
+//! to the app's process group and to every other process of the app,
+//! including what is left in its cgroup, and stopping only ends once none
+//! of them is left. The outcome says which
+//! signal actually ended the main process.
+
+use nix::errno::Errno;
+use nix::sys::signal::{kill, killpg, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::fmt;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::read_config::StopStep;
+use crate::process_manager::process::app_processes;
+use crate::process_manager::registry::ExitReason;
+
+/// How often a stopping process is checked for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long to wait for a process to go away after SIGKILL
+const KILL_WAIT: Duration = Duration::from_secs(5);
+
+/// How a stopped process ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    /// It was already gone before anything was sent
+    NotRunning,
+    /// The signal terminated it
+    Signaled(Signal),
+    /// It exited with `code` after being sent `signal`
+    Exited { signal: Signal, code: i32 },
+}
+
+impl fmt::Display for StopOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopOutcome::NotRunning => write!(f, "already exited"),
+            StopOutcome::Signaled(signal) => write!(f, "{}", signal),
+            StopOutcome::Exited { signal, code } => {
+                write!(f, "exited with code {} on {}", code, signal)
+            }
+        }
+    }
+}
+
+impl StopOutcome {
+    /// How the main process ended, if it was still running
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        match self {
+            StopOutcome::NotRunning => None,
+            StopOutcome::Signaled(signal) => Some(ExitReason::Signal(signal.to_string())),
+            StopOutcome::Exited { code, .. } => Some(ExitReason::Code(*code)),
+        }
+    }
+}
+
+/// Send each step's signal to the app whose main process is `pid`, and
+/// whose processes are in `cgroup` if it has one, until none of its
+/// processes is left, then SIGKILL whatever remains
+pub fn stop(pid: u32, cgroup: Option<&Path>, sequence: &[StopStep]) -> Result<StopOutcome, String> {
+    let main_pid = Pid::from_raw(pid as i32);
+    let mut outcome = exit_status(main_pid).map(|_| StopOutcome::NotRunning);
+    let mut remaining = app_processes(pid, cgroup);
+    if outcome.is_some() && remaining.is_empty() {
+        return Ok(StopOutcome::NotRunning);
+    }
+
+    let kill_step = StopStep {
+        signal: Signal::SIGKILL,
+        timeout: KILL_WAIT,
+    };
+    for step in sequence.iter().chain([&kill_step]) {
+        signal_app(main_pid, &remaining, step.signal)?;
+
+        let deadline = Instant::now() + step.timeout;
+        loop {
+            if outcome.is_none() {
+                outcome = exit_status(main_pid).map(|status| match status {
+                    Some(WaitStatus::Exited(_, code)) => StopOutcome::Exited {
+                        signal: step.signal,
+                        code,
+                    },
+                    Some(WaitStatus::Signaled(_, signal, _)) => StopOutcome::Signaled(signal),
+                    _ => StopOutcome::Signaled(step.signal),
+                });
+            }
+            remaining = app_processes(pid, cgroup);
+            if let (Some(outcome), true) = (outcome, remaining.is_empty()) {
+                return Ok(outcome);
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    Err(format!(
+        "{} process(es) still running {}s after SIGKILL: {:?}",
+        remaining.len(),
+        KILL_WAIT.as_secs(),
+        remaining
+    ))
+}
+
+/// Signal the app's process group, which its main process leads, and each
+/// of its processes, since some may have left the group
+fn signal_app(main_pid: Pid, processes: &[u32], signal: Signal) -> Result<(), String> {
+    let group = killpg(main_pid, signal);
+    let each = processes
+        .iter()
+        .map(|pid| kill(Pid::from_raw(*pid as i32), signal));
+    for result in std::iter::once(group).chain(each) {
+        match result {
+            // Already gone, or the group has no members left
+            Ok(()) | Err(Errno::ESRCH) => {}
+            Err(e) => return Err(format!("failed to send {}: {}", signal, e)),
+        }
+    }
+    Ok(())
+}
+
+/// `Some` once the process has exited, with its wait status if it was our
+/// child. Children are reaped here; processes adopted from a previous
+/// daemon can only be checked for existence.
+fn exit_status(pid: Pid) -> Option<Option<WaitStatus>> {
+    match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+        Ok(WaitStatus::StillAlive) => None,
+        Ok(status) => Some(Some(status)),
+        Err(Errno::ECHILD) => match kill(pid, None) {
+            Err(_) => Some(None),
+            Ok(()) if is_zombie(pid) => Some(None),
+            Ok(()) => None,
+        },
+        Err(_) => None,
+    }
+}
+
+/// Whether the process has exited but not been reaped by its parent
+fn is_zombie(pid: Pid) -> bool {
+    std::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .ok()
+        .and_then(|stat| {
+            let (_, rest) = stat.rsplit_once(')')?;
+            rest.trim_start().chars().next()
+        })
+        == Some('Z')
+}
+
+#[cfg(test)]
+// `stop` reaps the children it stops
+#[allow(clippy::zombie_processes)]
+mod tests {
+    use super::*;
+    use crate::process_manager::process::in_own_session;
+    use crate::process_manager::reaper;
+    use std::process::{Child, Command};
+    use tempfile::TempDir;
+
+    fn step(signal: Signal, millis: u64) -> StopStep {
+        StopStep {
+            signal,
+            timeout: Duration::from_millis(millis),
+        }
+    }
+
+    /// Run `script` the way the daemon runs apps, giving it time to set up
+    fn spawn_app(script: &str) -> Child {
+        let child =
+            reaper::spawn_app(in_own_session(Command::new("sh").args(["-c", script]))).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        child
+    }
+
+    fn is_gone(pid: u32) -> bool {
+        let pid = Pid::from_raw(pid as i32);
+        kill(pid, None).is_err() || is_zombie(pid)
+    }
+
+    #[test]
+    fn test_stop_reports_ending_signal() {
+        let child = spawn_app("exec sleep 30");
+        let outcome = stop(child.id(), None, &[step(Signal::SIGTERM, 2000)]).unwrap();
+        assert_eq!(outcome, StopOutcome::Signaled(Signal::SIGTERM));
+        assert_eq!(outcome.to_string(), "SIGTERM");
+    }
+
+    #[test]
+    fn test_stop_escalates() {
+        let child = spawn_app("trap '' INT; trap 'exit 3' TERM; while true; do sleep 0.1; done");
+        let started = Instant::now();
+        let sequence = [step(Signal::SIGINT, 300), step(Signal::SIGTERM, 2000)];
+        let outcome = stop(child.id(), None, &sequence).unwrap();
+        assert_eq!(
+            outcome,
+            StopOutcome::Exited {
+                signal: Signal::SIGTERM,
+                code: 3
+            }
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let child = spawn_app("trap '' TERM; while true; do sleep 0.1; done");
+        let outcome = stop(child.id(), None, &[step(Signal::SIGTERM, 200)]).unwrap();
+        assert_eq!(outcome, StopOutcome::Signaled(Signal::SIGKILL));
+    }
+
+    #[test]
+    fn test_stop_leaves_no_descendants() {
+        let dir = TempDir::new().unwrap();
+        let pids = dir.path().join("pids");
+        // One child stays in the app's group, one starts its own session,
+        // and one outlives the shell by ignoring SIGTERM
+        let child = spawn_app(&format!(
+            "sleep 30 & echo $! > {pids}; setsid sleep 30 & echo $! >> {pids}; \
+             sh -c \"trap '' TERM; sleep 30\" & echo $! >> {pids}; wait",
+            pids = pids.display()
+        ));
+        let descendants: Vec<u32> = std::fs::read_to_string(&pids)
+            .unwrap()
+            .lines()
+            .map(|pid| pid.parse().unwrap())
+            .collect();
+        assert_eq!(descendants.len(), 3);
+
+        let outcome = stop(child.id(), None, &[step(Signal::SIGTERM, 500)]).unwrap();
+        assert_eq!(outcome, StopOutcome::Signaled(Signal::SIGTERM));
+        assert!(descendants.iter().all(|pid| is_gone(*pid)));
+        assert!(app_processes(child.id(), None).is_empty());
+    }
+
+    #[test]
+    fn test_stop_exited_process() {
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        assert_eq!(
+            stop(child.id(), None, &[StopStep::default()]),
+            Ok(StopOutcome::NotRunning)
+        );
+    }
+}